@@ -0,0 +1,502 @@
+use std::collections::BTreeMap;
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, File, Permissions};
+use std::io::{self, BufReader, Read};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{symlink, PermissionsExt};
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+
+use chrono::TimeZone;
+
+use crate::common::AppError;
+use crate::file_ops::exists;
+use crate::filesystem::{escape_name, unix_time_to_local, NodeType, SysNode};
+use crate::provider::FsProvider;
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum ArchiveKind {
+    Tar,
+    TarGz,
+    TarZst,
+    Zip,
+}
+
+impl ArchiveKind {
+    /// The kind of archive a file is, by its name.
+    pub fn detect(name: &OsStr) -> Option<ArchiveKind> {
+        let name = name.as_bytes().to_ascii_lowercase();
+        let ends = |suffixes: &[&str]| suffixes.iter().any(|s| name.ends_with(s.as_bytes()));
+        if ends(&[".tar"]) {
+            Some(ArchiveKind::Tar)
+        } else if ends(&[".tar.gz", ".tgz"]) {
+            Some(ArchiveKind::TarGz)
+        } else if ends(&[".tar.zst", ".tzst"]) {
+            Some(ArchiveKind::TarZst)
+        } else if ends(&[".zip", ".jar"]) {
+            Some(ArchiveKind::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+/// What is known of an entry of an archive without unpacking it.
+struct ArchiveEntry {
+    typ: NodeType,
+    mode: u32,
+    uid: u32,
+    gid: u32,
+    user: OsString,
+    group: OsString,
+    size: u64,
+    modified: chrono::DateTime<chrono::Local>,
+}
+
+impl ArchiveEntry {
+    // a directory only known from the paths of the entries inside it
+    fn implicit_dir() -> ArchiveEntry {
+        ArchiveEntry {
+            typ: NodeType::Dir,
+            mode: 0o755,
+            uid: 0,
+            gid: 0,
+            user: OsString::new(),
+            group: OsString::new(),
+            size: 0,
+            modified: SystemTime::UNIX_EPOCH.into(),
+        }
+    }
+
+    fn sys_node(&self, name: &OsStr) -> SysNode {
+        let mut node = SysNode::new(name, self.typ);
+        node.mode = self.mode;
+        node.uid = self.uid;
+        node.gid = self.gid;
+        node.user = self.user.clone();
+        node.group = self.group.clone();
+        node.size = self.size;
+        node.modified = self.modified;
+        node.accessed = self.modified;
+        node.changed = self.modified;
+        node.nlink = 1;
+        node
+    }
+}
+
+/// An archive file opened for browsing. Its index of entries is read once;
+/// the content is only unpacked when extracting.
+pub struct Archive {
+    path: PathBuf,
+    kind: ArchiveKind,
+    // by the path inside the archive, without a leading `/` or `./`
+    entries: BTreeMap<PathBuf, ArchiveEntry>,
+}
+
+impl Archive {
+    pub fn open(path: &Path, kind: ArchiveKind) -> Result<Archive, AppError> {
+        let mut archive = Archive {
+            path: path.to_path_buf(),
+            kind,
+            entries: BTreeMap::new(),
+        };
+        let result = match kind {
+            ArchiveKind::Zip => archive.read_zip_index(),
+            _ => archive.read_tar_index(),
+        };
+        result.map_err(|err| {
+            AppError::PathError(
+                format!("cannot read archive: {}", err),
+                escape_name(path.as_os_str()),
+            )
+        })?;
+        // directories don't need entries of their own
+        let dirs: Vec<PathBuf> = archive
+            .entries
+            .keys()
+            .flat_map(|p| p.ancestors().skip(1))
+            .filter(|p| !p.as_os_str().is_empty())
+            .map(Path::to_path_buf)
+            .collect();
+        for dir in dirs {
+            archive
+                .entries
+                .entry(dir)
+                .or_insert_with(ArchiveEntry::implicit_dir);
+        }
+        Ok(archive)
+    }
+
+    fn tar_reader(&self) -> io::Result<Box<dyn Read>> {
+        let file = BufReader::new(File::open(&self.path)?);
+        Ok(match self.kind {
+            ArchiveKind::TarGz => Box::new(flate2::read::GzDecoder::new(file)),
+            ArchiveKind::TarZst => Box::new(zstd::stream::read::Decoder::with_buffer(file)?),
+            _ => Box::new(file),
+        })
+    }
+
+    fn read_tar_index(&mut self) -> io::Result<()> {
+        let mut tar = tar::Archive::new(self.tar_reader()?);
+        for entry in tar.entries()? {
+            let entry = entry?;
+            let Some(path) = clean_path(&entry.path_bytes()) else {
+                continue;
+            };
+            let header = entry.header();
+            let typ = match header.entry_type() {
+                tar::EntryType::Directory => NodeType::Dir,
+                tar::EntryType::Symlink => NodeType::SymLink,
+                tar::EntryType::Block => NodeType::BlockDevice,
+                tar::EntryType::Char => NodeType::CharDevice,
+                tar::EntryType::Fifo => NodeType::Fifo,
+                t if t.is_file() || t.is_hard_link() => NodeType::File,
+                // pax and GNU extension headers
+                _ => continue,
+            };
+            let uid = header.uid().unwrap_or(0) as u32;
+            let gid = header.gid().unwrap_or(0) as u32;
+            let name = |name: Option<&[u8]>, id: u32| match name {
+                Some(name) if !name.is_empty() => OsStr::from_bytes(name).to_os_string(),
+                _ => id.to_string().into(),
+            };
+            let mtime = header.mtime().unwrap_or(0);
+            self.entries.insert(
+                path,
+                ArchiveEntry {
+                    typ,
+                    mode: header.mode().unwrap_or(0o644) & 0o7777,
+                    uid,
+                    gid,
+                    user: name(header.username_bytes(), uid),
+                    group: name(header.groupname_bytes(), gid),
+                    size: entry.size(),
+                    modified: unix_time_to_local(mtime as i64, 0),
+                },
+            );
+        }
+        Ok(())
+    }
+
+    fn read_zip_index(&mut self) -> io::Result<()> {
+        let mut zip = zip::ZipArchive::new(BufReader::new(File::open(&self.path)?))?;
+        for i in 0..zip.len() {
+            let file = zip.by_index_raw(i)?;
+            let Some(path) = clean_path(file.name_raw()) else {
+                continue;
+            };
+            let typ = if file.is_dir() {
+                NodeType::Dir
+            } else if file.is_symlink() {
+                NodeType::SymLink
+            } else {
+                NodeType::File
+            };
+            let mode = match (file.unix_mode(), &typ) {
+                (Some(mode), _) => mode & 0o7777,
+                (None, NodeType::Dir) => 0o755,
+                (None, _) => 0o644,
+            };
+            // MS-DOS time, which is local
+            let modified = file
+                .last_modified()
+                .and_then(|t| {
+                    chrono::Local
+                        .with_ymd_and_hms(
+                            t.year() as i32,
+                            t.month() as u32,
+                            t.day() as u32,
+                            t.hour() as u32,
+                            t.minute() as u32,
+                            t.second() as u32,
+                        )
+                        .single()
+                })
+                .unwrap_or_else(|| SystemTime::UNIX_EPOCH.into());
+            self.entries.insert(
+                path,
+                ArchiveEntry {
+                    typ,
+                    mode,
+                    uid: 0,
+                    gid: 0,
+                    user: OsString::new(),
+                    group: OsString::new(),
+                    size: file.size(),
+                    modified,
+                },
+            );
+        }
+        Ok(())
+    }
+
+    fn extract_tar(&self, targets: &[(PathBuf, PathBuf)]) -> io::Result<usize> {
+        let mut count = 0;
+        let mut tar = tar::Archive::new(self.tar_reader()?);
+        for entry in tar.entries()? {
+            let mut entry = entry?;
+            let Some(path) = clean_path(&entry.path_bytes()) else {
+                continue;
+            };
+            let Some((root, dest)) = target_of(&path, targets) else {
+                continue;
+            };
+            make_dirs(root, &dest, entry.header().entry_type().is_dir())?;
+            if entry.header().entry_type().is_hard_link() {
+                // to the copy of the linked file, if it's extracted too
+                let linked = entry.link_name_bytes().and_then(|l| clean_path(&l));
+                match linked.and_then(|l| target_of(&l, targets)) {
+                    Some((_, linked)) if exists(&linked) => fs::hard_link(linked, &dest)?,
+                    _ => continue,
+                }
+            } else {
+                entry.unpack(&dest)?;
+            }
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    fn extract_zip(&self, targets: &[(PathBuf, PathBuf)]) -> io::Result<usize> {
+        let mut count = 0;
+        let mut zip = zip::ZipArchive::new(BufReader::new(File::open(&self.path)?))?;
+        for i in 0..zip.len() {
+            let mut file = zip.by_index(i)?;
+            let Some(path) = clean_path(file.name_raw()) else {
+                continue;
+            };
+            let Some((root, dest)) = target_of(&path, targets) else {
+                continue;
+            };
+            make_dirs(root, &dest, file.is_dir())?;
+            if !file.is_dir() {
+                if file.is_symlink() {
+                    let mut link = Vec::new();
+                    file.read_to_end(&mut link)?;
+                    symlink(OsStr::from_bytes(&link), &dest)?;
+                } else {
+                    io::copy(&mut file, &mut File::create_new(&dest)?)?;
+                }
+            }
+            if let (Some(mode), false) = (file.unix_mode(), file.is_symlink()) {
+                fs::set_permissions(&dest, Permissions::from_mode(mode & 0o7777))?;
+            }
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+impl FsProvider for Archive {
+    fn list(&self, dir: &Path) -> io::Result<Vec<SysNode>> {
+        let dir = inner(dir);
+        Ok(self
+            .entries
+            .iter()
+            .filter(|(path, _)| path.parent() == Some(dir))
+            .filter_map(|(path, entry)| Some(entry.sys_node(path.file_name()?)))
+            .collect())
+    }
+
+    fn stat(&self, path: &Path) -> io::Result<SysNode> {
+        let path = inner(path);
+        match (self.entries.get(path), path.file_name()) {
+            (Some(entry), Some(name)) => Ok(entry.sys_node(name)),
+            _ => Err(io::ErrorKind::NotFound.into()),
+        }
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Box<dyn Read + '_>> {
+        let path = inner(path);
+        let mut content = Vec::new();
+        if self.kind == ArchiveKind::Zip {
+            let mut zip = zip::ZipArchive::new(BufReader::new(File::open(&self.path)?))?;
+            for i in 0..zip.len() {
+                let mut file = zip.by_index(i)?;
+                if clean_path(file.name_raw()).as_deref() == Some(path) {
+                    file.read_to_end(&mut content)?;
+                    return Ok(Box::new(io::Cursor::new(content)));
+                }
+            }
+        } else {
+            let mut tar = tar::Archive::new(self.tar_reader()?);
+            for entry in tar.entries()? {
+                let mut entry = entry?;
+                if clean_path(&entry.path_bytes()).as_deref() == Some(path) {
+                    entry.read_to_end(&mut content)?;
+                    return Ok(Box::new(io::Cursor::new(content)));
+                }
+            }
+        }
+        Err(io::ErrorKind::NotFound.into())
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        if self.kind != ArchiveKind::Zip {
+            let inner_path = inner(path);
+            let mut tar = tar::Archive::new(self.tar_reader()?);
+            for entry in tar.entries()? {
+                let entry = entry?;
+                if clean_path(&entry.path_bytes()).as_deref() == Some(inner_path) {
+                    return match entry.link_name_bytes() {
+                        Some(link) => Ok(PathBuf::from(OsStr::from_bytes(&link))),
+                        None => Err(io::ErrorKind::InvalidInput.into()),
+                    };
+                }
+            }
+            return Err(io::ErrorKind::NotFound.into());
+        }
+        // zip keeps the target as the content
+        let mut link = Vec::new();
+        self.read(path)?.read_to_end(&mut link)?;
+        Ok(PathBuf::from(OsStr::from_bytes(&link)))
+    }
+
+    /// Unpacks in a single pass through the archive.
+    fn extract(&self, targets: &[(PathBuf, PathBuf)]) -> io::Result<usize> {
+        if targets.iter().any(|(_, to)| exists(to)) {
+            return Err(io::ErrorKind::AlreadyExists.into());
+        }
+        let targets: Vec<(PathBuf, PathBuf)> = targets
+            .iter()
+            .map(|(from, to)| (inner(from).to_path_buf(), to.clone()))
+            .collect();
+        match self.kind {
+            ArchiveKind::Zip => self.extract_zip(&targets),
+            _ => self.extract_tar(&targets),
+        }
+    }
+}
+
+// the key of an entry in the index
+fn inner(path: &Path) -> &Path {
+    path.strip_prefix("/").unwrap_or(path)
+}
+
+/// A path inside an archive made relative, or `None` if it would lead out of
+/// the directory it's extracted to.
+fn clean_path(bytes: &[u8]) -> Option<PathBuf> {
+    let mut path = PathBuf::new();
+    for c in Path::new(OsStr::from_bytes(bytes)).components() {
+        match c {
+            Component::Normal(name) => path.push(name),
+            Component::RootDir | Component::CurDir => {}
+            Component::ParentDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(path).filter(|p| !p.as_os_str().is_empty())
+}
+
+// where an entry goes if it's one of the targets or inside one, with the
+// path of that target
+fn target_of<'a>(path: &Path, targets: &'a [(PathBuf, PathBuf)]) -> Option<(&'a Path, PathBuf)> {
+    targets.iter().find_map(|(inner, to)| {
+        let rest = path.strip_prefix(inner).ok()?;
+        Some(match rest.as_os_str().is_empty() {
+            true => (to.as_path(), to.clone()),
+            false => (to.as_path(), to.join(rest)),
+        })
+    })
+}
+
+/// Creates the directories from `root` down to the parent of `dest`, and
+/// `dest` itself if `is_dir`. Each must be a real directory: an entry is
+/// refused if its way passes through a symlink, which an earlier entry of
+/// the archive may have made to lead anywhere.
+fn make_dirs(root: &Path, dest: &Path, is_dir: bool) -> io::Result<()> {
+    let Ok(rest) = dest.strip_prefix(root) else {
+        return Err(io::ErrorKind::InvalidInput.into());
+    };
+    // not `root.join("")`, whose trailing slash would follow a symlink
+    let mut dirs: Vec<PathBuf> = rest
+        .ancestors()
+        .map(|a| match a.as_os_str().is_empty() {
+            true => root.to_path_buf(),
+            false => root.join(a),
+        })
+        .collect();
+    dirs.reverse();
+    if !is_dir {
+        dirs.pop();
+    }
+    for dir in dirs {
+        match fs::symlink_metadata(&dir) {
+            Ok(md) if md.is_dir() => {}
+            Ok(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}: not a directory", escape_name(dir.as_os_str())),
+                ))
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => fs::create_dir(&dir)?,
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::process;
+
+    // an archive whose `top/a` is a symlink to `outside`, followed by a file
+    // meant to be written through it
+    fn scratch(kind: &str) -> (PathBuf, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("navigator-{}-{}", kind, process::id()));
+        fs::create_dir_all(dir.join("outside")).unwrap();
+        fs::create_dir_all(dir.join("dest")).unwrap();
+        (dir.join("outside"), dir)
+    }
+
+    fn assert_refused(archive: &Archive, dir: &Path, outside: &Path) {
+        let targets = [(PathBuf::from("/top"), dir.join("dest/top"))];
+        assert!(archive.extract(&targets).is_err());
+        assert!(!outside.join("evil").exists());
+        assert!(fs::symlink_metadata(dir.join("dest/top/a"))
+            .unwrap()
+            .file_type()
+            .is_symlink());
+    }
+
+    #[test]
+    fn tar_entries_stay_out_of_symlinks() {
+        let (outside, dir) = scratch("tar");
+        let path = dir.join("test.tar");
+        let mut builder = tar::Builder::new(File::create(&path).unwrap());
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        header.set_mode(0o777);
+        builder.append_link(&mut header, "top/a", &outside).unwrap();
+        let mut header = tar::Header::new_gnu();
+        header.set_size(4);
+        header.set_mode(0o644);
+        builder
+            .append_data(&mut header, "top/a/evil", &b"evil"[..])
+            .unwrap();
+        builder.into_inner().unwrap();
+
+        let archive = Archive::open(&path, ArchiveKind::Tar).ok().unwrap();
+        assert_refused(&archive, &dir, &outside);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn zip_entries_stay_out_of_symlinks() {
+        let (outside, dir) = scratch("zip");
+        let path = dir.join("test.zip");
+        let mut zip = zip::ZipWriter::new(File::create(&path).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        zip.add_symlink("top/a", outside.to_str().unwrap(), options)
+            .unwrap();
+        zip.start_file("top/a/evil", options).unwrap();
+        zip.write_all(b"evil").unwrap();
+        zip.finish().unwrap();
+
+        let archive = Archive::open(&path, ArchiveKind::Zip).ok().unwrap();
+        assert_refused(&archive, &dir, &outside);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
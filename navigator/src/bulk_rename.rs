@@ -0,0 +1,387 @@
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use crate::common::AppError;
+use crate::file_ops::exists;
+use crate::filesystem::escape_name;
+
+/// A name in a directory and what it's renamed to.
+pub type NamePair = (OsString, OsString);
+
+/// Writes `names` to a temporary file, one per line, and lets the user edit
+/// them with `$VISUAL` or `$EDITOR` (`vi` if neither is set). Returns the
+/// edited text. The terminal must be given to the editor meanwhile.
+pub fn edit_names(names: &[OsString]) -> Result<Vec<u8>, AppError> {
+    if let Some(name) = names.iter().find(|n| n.as_bytes().contains(&b'\n')) {
+        return Err(AppError::PathError(
+            "cannot edit names with line breaks".to_owned(),
+            escape_name(name),
+        ));
+    }
+    let mut text = Vec::new();
+    for name in names {
+        text.extend_from_slice(name.as_bytes());
+        text.push(b'\n');
+    }
+
+    let file = TempFile::create(&text)?;
+    let path = &file.0;
+    let editor = ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(env::var_os)
+        .find(|e| !e.is_empty())
+        .unwrap_or_else(|| OsString::from("vi"));
+    // through the shell, the editor may come with options
+    let status = process::Command::new("sh")
+        .arg("-c")
+        .arg("$NAVIGATOR_EDITOR \"$1\"")
+        .arg("sh")
+        .arg(path)
+        .env("NAVIGATOR_EDITOR", &editor)
+        .status();
+    let edited = fs::read(path);
+    drop(file);
+    match status {
+        Ok(status) if status.success() => Ok(edited?),
+        Ok(status) => Err(AppError::StrError(format!(
+            "{} failed: {}",
+            editor.to_string_lossy(),
+            status
+        ))),
+        Err(err) => Err(AppError::StrError(format!(
+            "cannot run {}: {}",
+            editor.to_string_lossy(),
+            err
+        ))),
+    }
+}
+
+/// A file only the user can read, removed when dropped.
+struct TempFile(PathBuf);
+
+impl TempFile {
+    /// Creates a new file in the temporary directory with `text` in it. An
+    /// existing file, or a symlink planted there, is never opened.
+    fn create(text: &[u8]) -> io::Result<TempFile> {
+        let dir = env::temp_dir();
+        let mut attempt = 0;
+        loop {
+            let name = format!("navigator-rename-{}-{}.txt", process::id(), attempt);
+            let path = dir.join(name);
+            match fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .mode(0o600)
+                .open(&path)
+            {
+                Ok(mut file) => {
+                    let temp = TempFile(path);
+                    file.write_all(text)?;
+                    return Ok(temp);
+                }
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists && attempt < 100 => {
+                    attempt += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// The renames the user asked for by editing `names` into `text`, line by
+/// line; unchanged names are left out. Lists everything wrong with them
+/// instead if any can't be done in `dir`.
+pub fn plan(dir: &Path, names: &[OsString], text: &[u8]) -> Result<Vec<NamePair>, Vec<String>> {
+    let text = text.strip_suffix(b"\n").unwrap_or(text);
+    let lines: Vec<&[u8]> = match text.is_empty() {
+        true => Vec::new(),
+        false => text.split(|b| *b == b'\n').collect(),
+    };
+    if lines.len() != names.len() {
+        return Err(vec![format!(
+            "{} names given for {} entries, lines must not be added or removed",
+            lines.len(),
+            names.len()
+        )]);
+    }
+
+    let mut problems = Vec::new();
+    let mut pairs = Vec::new();
+    for (name, line) in names.iter().zip(lines) {
+        let new_name = OsStr::from_bytes(line);
+        if new_name == name {
+            continue;
+        }
+        if !valid_name(new_name) {
+            problems.push(format!("not a valid name: '{}'", escape_name(new_name)));
+            continue;
+        }
+        pairs.push((name.clone(), new_name.to_os_string()));
+    }
+    problems.extend(conflicts(dir, &pairs));
+    match problems.is_empty() {
+        true => Ok(pairs),
+        false => Err(problems),
+    }
+}
+
+fn valid_name(name: &OsStr) -> bool {
+    let bytes = name.as_bytes();
+    !bytes.is_empty() && !bytes.contains(&b'/') && bytes != b"." && bytes != b".."
+}
+
+/// What stands in the way of the renames in `dir`: entries which are gone,
+/// and names given twice or taken by entries which keep theirs.
+pub fn conflicts(dir: &Path, pairs: &[NamePair]) -> Vec<String> {
+    let mut problems = Vec::new();
+    for (i, (from, to)) in pairs.iter().enumerate() {
+        if !exists(&dir.join(from)) {
+            problems.push(format!("'{}' no longer exists", escape_name(from)));
+        }
+        let before = pairs[..i].iter().filter(|(_, other)| other == to).count();
+        if before == 1 {
+            problems.push(format!("'{}' is given to several entries", escape_name(to)));
+        } else if before == 0
+            && exists(&dir.join(to))
+            && !pairs.iter().any(|(other, _)| other == to)
+        {
+            problems.push(format!("'{}' already exists", escape_name(to)));
+        }
+    }
+    problems
+}
+
+/// Orders the renames so that no name is taken before its entry got
+/// another. Renames going round in a cycle (a to b, b to a) take a detour
+/// through a temporary name.
+fn steps(dir: &Path, pairs: &[NamePair]) -> Vec<NamePair> {
+    let mut pending: Vec<NamePair> = pairs.to_vec();
+    let mut steps = Vec::new();
+    let mut tmp_count = 0;
+    while !pending.is_empty() {
+        let free = pending
+            .iter()
+            .position(|(_, to)| !pending.iter().any(|(from, _)| from == to));
+        if let Some(i) = free {
+            steps.push(pending.remove(i));
+            continue;
+        }
+        // only cycles are left, one of them is broken up
+        let (from, to) = pending.remove(0);
+        let tmp = loop {
+            tmp_count += 1;
+            let tmp = OsString::from(format!(".navigator-rename-{}", tmp_count));
+            if !exists(&dir.join(&tmp)) && !pairs.iter().any(|(_, to)| *to == tmp) {
+                break tmp;
+            }
+        };
+        steps.push((from, tmp.clone()));
+        pending.push((tmp, to));
+    }
+    steps
+}
+
+/// Renames entries of `dir`. Nothing is overwritten; if a rename fails, the
+/// ones done before are reversed.
+pub fn apply(dir: &Path, pairs: &[NamePair]) -> Result<(), AppError> {
+    let steps = steps(dir, pairs);
+    for (i, (from, to)) in steps.iter().enumerate() {
+        let (from, to) = (dir.join(from), dir.join(to));
+        let result = match exists(&to) {
+            true => Err(io::Error::from(io::ErrorKind::AlreadyExists)),
+            false => fs::rename(&from, &to),
+        };
+        if let Err(err) = result {
+            for (from, to) in steps[..i].iter().rev() {
+                let _ = fs::rename(dir.join(to), dir.join(from));
+            }
+            return Err(AppError::PathError(
+                err.to_string(),
+                escape_name(from.as_os_str()),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// The renames which undo `pairs`.
+pub fn reversed(pairs: &[NamePair]) -> Vec<NamePair> {
+    pairs
+        .iter()
+        .map(|(from, to)| (to.clone(), from.clone()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::{symlink, PermissionsExt};
+
+    #[test]
+    fn temp_file_is_private_and_removed() {
+        // a symlink where the first file would go is left alone
+        let target = env::temp_dir().join(format!("navigator-target-{}", process::id()));
+        let planted = env::temp_dir().join(format!("navigator-rename-{}-0.txt", process::id()));
+        symlink(&target, &planted).unwrap();
+
+        let file = TempFile::create(b"a\nb\n").unwrap();
+        let path = file.0.clone();
+        assert_ne!(path, planted);
+        assert!(!target.exists());
+        assert_eq!(fs::read(&path).unwrap(), b"a\nb\n");
+        assert_eq!(
+            fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+        drop(file);
+        assert!(!path.exists());
+
+        fs::remove_file(&planted).unwrap();
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("navigator-bulk-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // a file for each name, with the name as content
+    fn populate(dir: &Path, names: &[&str]) -> Vec<OsString> {
+        for name in names {
+            fs::write(dir.join(name), name).unwrap();
+        }
+        names.iter().map(OsString::from).collect()
+    }
+
+    // the names in `dir` with the names they had at first
+    fn content(dir: &Path) -> Vec<(String, String)> {
+        let mut content: Vec<(String, String)> = fs::read_dir(dir)
+            .unwrap()
+            .map(|e| {
+                let path = e.unwrap().path();
+                let name = path.file_name().unwrap().to_str().unwrap().to_owned();
+                (name, fs::read_to_string(&path).unwrap())
+            })
+            .collect();
+        content.sort();
+        content
+    }
+
+    fn pairs(pairs: &[(&str, &str)]) -> Vec<NamePair> {
+        pairs
+            .iter()
+            .map(|(from, to)| (OsString::from(from), OsString::from(to)))
+            .collect()
+    }
+
+    fn owned(content: &[(&str, &str)]) -> Vec<(String, String)> {
+        content
+            .iter()
+            .map(|(a, b)| (a.to_string(), b.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn swaps_and_cycles() {
+        let dir = temp_dir("cycles");
+        let names = populate(&dir, &["a", "b", "x", "y", "z"]);
+        let planned = plan(&dir, &names, b"b\na\ny\nz\nx\n").unwrap();
+        assert_eq!(
+            planned,
+            pairs(&[("a", "b"), ("b", "a"), ("x", "y"), ("y", "z"), ("z", "x")])
+        );
+        // each cycle is broken up once
+        let temps = steps(&dir, &planned)
+            .iter()
+            .filter(|(_, to)| to.as_bytes().starts_with(b".navigator-rename-"))
+            .count();
+        assert_eq!(temps, 2);
+
+        apply(&dir, &planned).unwrap();
+        let swapped = [("a", "b"), ("b", "a"), ("x", "z"), ("y", "x"), ("z", "y")];
+        assert_eq!(content(&dir), owned(&swapped));
+        apply(&dir, &reversed(&planned)).unwrap();
+        assert_eq!(
+            content(&dir),
+            owned(&[("a", "a"), ("b", "b"), ("x", "x"), ("y", "y"), ("z", "z")])
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn chain_into_a_free_name() {
+        let dir = temp_dir("chain");
+        let names = populate(&dir, &["a", "b", "c"]);
+        let planned = plan(&dir, &names, b"b\nc\nd").unwrap();
+        assert_eq!(
+            steps(&dir, &planned),
+            pairs(&[("c", "d"), ("b", "c"), ("a", "b")])
+        );
+        apply(&dir, &planned).unwrap();
+        assert_eq!(content(&dir), owned(&[("b", "a"), ("c", "b"), ("d", "c")]));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reports_conflicts() {
+        let dir = temp_dir("conflicts");
+        let names = populate(&dir, &["a", "b", "c"]);
+
+        let problems = plan(&dir, &names, b"a\nb\n").unwrap_err();
+        assert_eq!(
+            problems,
+            ["2 names given for 3 entries, lines must not be added or removed"]
+        );
+        assert!(plan(&dir, &names, b"").is_err());
+
+        let problems = plan(&dir, &names, b"x\nx\nx\n").unwrap_err();
+        assert_eq!(problems, ["'x' is given to several entries"]);
+
+        // c keeps its name
+        let problems = plan(&dir, &names, b"c\nb\nc\n").unwrap_err();
+        assert_eq!(problems, ["'c' already exists"]);
+
+        let problems = plan(&dir, &names, b"..\nd/e\nc\n").unwrap_err();
+        assert_eq!(
+            problems,
+            ["not a valid name: '..'", "not a valid name: 'd/e'"]
+        );
+
+        fs::remove_file(dir.join("b")).unwrap();
+        let problems = plan(&dir, &names, b"a\nd\nc\n").unwrap_err();
+        assert_eq!(problems, ["'b' no longer exists"]);
+
+        assert_eq!(plan(&dir, &names, b"a\nb\nc").unwrap(), []);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn failed_rename_reverses_the_others() {
+        let dir = temp_dir("rollback");
+        populate(&dir, &["b", "c"]);
+        // c and b are renamed before the gone a fails
+        let err = apply(&dir, &pairs(&[("c", "d"), ("b", "c2"), ("a", "b")]));
+        assert!(err.is_err());
+        assert_eq!(content(&dir), owned(&[("b", "b"), ("c", "c")]));
+
+        // nothing is overwritten
+        populate(&dir, &["e"]);
+        let err = apply(&dir, &pairs(&[("b", "x"), ("c", "e")]));
+        assert!(err.is_err());
+        assert_eq!(content(&dir), owned(&[("b", "b"), ("c", "c"), ("e", "e")]));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
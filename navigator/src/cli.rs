@@ -0,0 +1,413 @@
+use std::{ffi::OsString, path::PathBuf};
+
+use crate::colors::Theme;
+use crate::common::*;
+use crate::config::*;
+use crate::export::{json::JsonFormat, walk::*};
+use crate::graph::columns::Column;
+use crate::screen::Layout;
+use crate::tree_node::SortOrder;
+
+pub const DEFAULT_OUTPUT: &str = "/tmp/navigator.dir";
+
+pub enum OutputMode {
+    File(PathBuf),
+    None,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Charset {
+    Unicode,
+    Ascii,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Mode {
+    Interactive,
+    PrintTree,
+    Json(JsonFormat),
+    ListKeys,
+}
+
+pub enum Command {
+    Run(Options),
+    Help,
+    Version,
+}
+
+/// Settings given on the command line. `None` means "not given", so the
+/// value from the config file (or the default) is used.
+pub struct Options {
+    pub mode: Mode,
+    pub start_path: Option<PathBuf>,
+    pub output: OutputMode,
+    pub config_path: Option<PathBuf>,
+    pub show_hidden: Option<bool>,
+    pub sort: Option<SortOrder>,
+    pub reverse: Option<bool>,
+    pub theme: Option<Theme>,
+    pub layout: Option<Layout>,
+    pub one_file_system: Option<bool>,
+    pub columns: Option<Vec<Column>>,
+    pub human_sizes: Option<bool>,
+    pub walk: WalkOptions,
+    pub charset: Charset,
+}
+
+impl Options {
+    fn new() -> Options {
+        Options {
+            mode: Mode::Interactive,
+            start_path: None,
+            output: OutputMode::File(PathBuf::from(DEFAULT_OUTPUT)),
+            config_path: None,
+            show_hidden: None,
+            sort: None,
+            reverse: None,
+            theme: None,
+            layout: None,
+            one_file_system: None,
+            columns: None,
+            human_sizes: None,
+            walk: WalkOptions::default(),
+            charset: Charset::Unicode,
+        }
+    }
+
+    pub fn apply_to(&self, config: &mut Config) {
+        if let Some(show_hidden) = self.show_hidden {
+            config.show_hidden = show_hidden;
+        }
+        if let Some(sort) = self.sort {
+            config.sort = sort;
+        }
+        if let Some(reverse) = self.reverse {
+            config.sort_reverse = reverse;
+        }
+        if let Some(theme) = self.theme {
+            config.theme = theme;
+        }
+        if let Some(layout) = self.layout {
+            config.layout = layout;
+        }
+        if let Some(one_file_system) = self.one_file_system {
+            config.one_file_system = one_file_system;
+        }
+        if let Some(columns) = &self.columns {
+            config.columns = columns.clone();
+        }
+        if let Some(human_sizes) = self.human_sizes {
+            config.human_sizes = human_sizes;
+        }
+    }
+}
+
+pub fn usage() -> String {
+    format!(
+        "\
+Usage: {name} [OPTIONS] [PATH]
+       {name} --print-tree [OPTIONS] [PATH]
+       {name} --json|--ndjson [OPTIONS] [PATH]
+
+Terminal tool for navigating the Linux file system. PATH may also be
+ssh://[USER@]HOST[:PORT][/PATH] to browse a remote host over SFTP.
+F10 quits and writes the selected directory to the output file,
+Esc quits without writing anything, F1 lists all keys.
+
+Options:
+  -h, --help               Print this help and exit
+  -V, --version            Print version and exit
+      --keys               Print the active key bindings and exit
+  -o, --output <FILE>      Write the selected directory to FILE
+                           (default {output})
+      --no-output          Do not write the selected directory anywhere
+  -c, --config <FILE>      Read settings from FILE instead of the default
+                           config file
+  -a, --all                Show hidden files
+      --no-hidden          Do not show hidden files
+  -s, --sort <ORDER>       Sort by name, ext, size or time
+  -r, --reverse            Reverse the sort order
+      --theme <THEME>      Color theme: default, 256, mono or ls
+                           (colors from LS_COLORS)
+      --tree-only          Show only the directory tree
+      --list-only          Show only the file list
+      --dual               Show two file lists side by side
+      --columns <LIST>     File list columns, comma separated: type, perms,
+                           owner, group, size, mtime, atime, ctime, inode,
+                           links, name
+  -H, --human-readable     Show sizes in KiB, MiB, ...
+  -x, --one-file-system    Don't count directories on other file systems
+                           in directory sizes
+
+Non-interactive modes:
+      --print-tree         Print the directory tree of PATH to stdout
+      --json               Print the directory tree of PATH as JSON
+      --ndjson             Print one JSON object per entry and line
+
+Options for non-interactive modes:
+  -L, --depth <N>          Descend at most N levels
+  -d, --dirs-only          List directories only
+  -P, --pattern <GLOB>     List only files matching GLOB (repeatable)
+  -I, --exclude <GLOB>     Skip entries matching GLOB (repeatable)
+      --charset <CHARSET>  Tree lines: unicode or ascii
+",
+        name = env!("CARGO_PKG_NAME"),
+        output = DEFAULT_OUTPUT
+    )
+}
+
+pub fn version() -> String {
+    format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))
+}
+
+// short options without a value, which can be bundled like `-aH`
+const SHORT_FLAGS: &str = "hVarHxd";
+
+struct ArgIter {
+    args: std::vec::IntoIter<OsString>,
+    /// The rest of a bundle of short options, taken before the next argument.
+    pending: Option<OsString>,
+}
+
+impl ArgIter {
+    fn next(&mut self) -> Option<OsString> {
+        self.pending.take().or_else(|| self.args.next())
+    }
+
+    fn value(&mut self, opt: &str, inline: Option<String>) -> Result<String, AppError> {
+        if let Some(v) = inline {
+            return Ok(v);
+        }
+        match self.next() {
+            Some(v) => v
+                .into_string()
+                .map_err(|_| AppError::UsageError(format!("invalid value for '{}'", opt))),
+            None => Err(AppError::UsageError(format!(
+                "option '{}' requires a value",
+                opt
+            ))),
+        }
+    }
+}
+
+fn no_value(opt: &str, inline: &Option<String>) -> Result<(), AppError> {
+    match inline {
+        Some(_) => Err(AppError::UsageError(format!(
+            "option '{}' doesn't take a value",
+            opt
+        ))),
+        None => Ok(()),
+    }
+}
+
+pub fn parse_args(args: Vec<OsString>) -> Result<Command, AppError> {
+    let mut opts = Options::new();
+    let mut it = ArgIter {
+        args: args.into_iter(),
+        pending: None,
+    };
+    let mut only_paths = false;
+
+    while let Some(arg) = it.next() {
+        let arg_str = arg.to_string_lossy().to_string();
+        if only_paths || !arg_str.starts_with('-') || arg_str == "-" {
+            if opts.start_path.is_some() {
+                return Err(AppError::UsageError(format!(
+                    "unexpected argument '{}'",
+                    arg_str
+                )));
+            }
+            opts.start_path = Some(PathBuf::from(arg));
+            continue;
+        }
+        if arg_str == "--" {
+            only_paths = true;
+            continue;
+        }
+
+        let (opt, inline) = match arg_str.split_once('=') {
+            Some((o, v)) if arg_str.starts_with("--") => (o.to_owned(), Some(v.to_owned())),
+            // bundled short options, e.g. -aH: the first one now, the rest next
+            _ if !arg_str.starts_with("--")
+                && arg_str.len() > 2
+                && arg_str.is_char_boundary(2)
+                && SHORT_FLAGS.contains(&arg_str[1..2]) =>
+            {
+                it.pending = Some(OsString::from(format!("-{}", &arg_str[2..])));
+                (arg_str[..2].to_owned(), None)
+            }
+            // short option with attached value, e.g. -L2
+            _ if !arg_str.starts_with("--") && arg_str.len() > 2 && arg_str.is_char_boundary(2) => {
+                (arg_str[..2].to_owned(), Some(arg_str[2..].to_owned()))
+            }
+            _ => (arg_str.clone(), None),
+        };
+
+        match opt.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-V" | "--version" => return Ok(Command::Version),
+            "-o" | "--output" => {
+                opts.output = OutputMode::File(PathBuf::from(it.value(&opt, inline)?));
+            }
+            "--no-output" => {
+                no_value(&opt, &inline)?;
+                opts.output = OutputMode::None;
+            }
+            "-c" | "--config" => {
+                opts.config_path = Some(PathBuf::from(it.value(&opt, inline)?));
+            }
+            "-a" | "--all" => {
+                no_value(&opt, &inline)?;
+                opts.show_hidden = Some(true);
+            }
+            "--no-hidden" => {
+                no_value(&opt, &inline)?;
+                opts.show_hidden = Some(false);
+            }
+            "-s" | "--sort" => {
+                let v = it.value(&opt, inline)?;
+                opts.sort =
+                    Some(SortOrder::parse(&v).ok_or_else(|| {
+                        AppError::UsageError(format!("invalid sort order '{}'", v))
+                    })?);
+            }
+            "-r" | "--reverse" => {
+                no_value(&opt, &inline)?;
+                opts.reverse = Some(true);
+            }
+            "--theme" => {
+                let v = it.value(&opt, inline)?;
+                opts.theme = Some(
+                    Theme::parse(&v)
+                        .ok_or_else(|| AppError::UsageError(format!("unknown theme '{}'", v)))?,
+                );
+            }
+            "--tree-only" => {
+                no_value(&opt, &inline)?;
+                opts.layout = Some(Layout::TreeOnly);
+            }
+            "--list-only" => {
+                no_value(&opt, &inline)?;
+                opts.layout = Some(Layout::ListOnly);
+            }
+            "--dual" => {
+                no_value(&opt, &inline)?;
+                opts.layout = Some(Layout::Dual);
+            }
+            "--columns" => {
+                let v = it.value(&opt, inline)?;
+                opts.columns = Some(Column::parse_list(&v).map_err(AppError::UsageError)?);
+            }
+            "-H" | "--human-readable" => {
+                no_value(&opt, &inline)?;
+                opts.human_sizes = Some(true);
+            }
+            "-x" | "--one-file-system" => {
+                no_value(&opt, &inline)?;
+                opts.one_file_system = Some(true);
+            }
+            "--keys" => {
+                no_value(&opt, &inline)?;
+                opts.mode = Mode::ListKeys;
+            }
+            "--print-tree" => {
+                no_value(&opt, &inline)?;
+                opts.mode = Mode::PrintTree;
+            }
+            "--json" => {
+                no_value(&opt, &inline)?;
+                opts.mode = Mode::Json(JsonFormat::Tree);
+            }
+            "--ndjson" => {
+                no_value(&opt, &inline)?;
+                opts.mode = Mode::Json(JsonFormat::Lines);
+            }
+            "-L" | "--depth" => {
+                let v = it.value(&opt, inline)?;
+                opts.walk.max_depth = Some(
+                    v.parse()
+                        .map_err(|_| AppError::UsageError(format!("invalid depth '{}'", v)))?,
+                );
+            }
+            "-d" | "--dirs-only" => {
+                no_value(&opt, &inline)?;
+                opts.walk.dirs_only = true;
+            }
+            "-P" | "--pattern" => {
+                let v = it.value(&opt, inline)?;
+                opts.walk.filter.include.push(Pattern::new(&v));
+            }
+            "-I" | "--exclude" => {
+                let v = it.value(&opt, inline)?;
+                opts.walk.filter.exclude.push(Pattern::new(&v));
+            }
+            "--charset" => {
+                let v = it.value(&opt, inline)?;
+                opts.charset = match v.as_str() {
+                    "unicode" | "utf8" | "utf-8" => Charset::Unicode,
+                    "ascii" => Charset::Ascii,
+                    _ => return Err(AppError::UsageError(format!("invalid charset '{}'", v))),
+                };
+            }
+            _ => return Err(AppError::UsageError(format!("unknown option '{}'", opt))),
+        }
+    }
+
+    Ok(Command::Run(opts))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn parse(args: &[&str]) -> Result<Options, AppError> {
+        match parse_args(args.iter().map(OsString::from).collect())? {
+            Command::Run(opts) => Ok(opts),
+            _ => panic!("expected options to run with"),
+        }
+    }
+
+    #[test]
+    fn bundled_flags() {
+        let opts = parse(&["-aHx", "dir"]).unwrap();
+        assert_eq!(opts.show_hidden, Some(true));
+        assert_eq!(opts.human_sizes, Some(true));
+        assert_eq!(opts.one_file_system, Some(true));
+        assert_eq!(opts.start_path, Some(PathBuf::from("dir")));
+    }
+
+    #[test]
+    fn bundle_ending_with_a_value() {
+        let opts = parse(&["-dL2"]).unwrap();
+        assert!(opts.walk.dirs_only);
+        assert_eq!(opts.walk.max_depth, Some(2));
+
+        let opts = parse(&["-ao", "out"]).unwrap();
+        assert_eq!(opts.show_hidden, Some(true));
+        assert!(matches!(opts.output, OutputMode::File(p) if p == Path::new("out")));
+    }
+
+    #[test]
+    fn attached_and_long_values() {
+        let opts = parse(&["-L3", "--sort=size", "-r"]).unwrap();
+        assert_eq!(opts.walk.max_depth, Some(3));
+        assert!(opts.sort == Some(SortOrder::Size));
+        assert_eq!(opts.reverse, Some(true));
+    }
+
+    #[test]
+    fn usage_errors() {
+        assert!(parse(&["-aZ"]).is_err());
+        assert!(parse(&["--all=yes"]).is_err());
+        assert!(parse(&["-L"]).is_err());
+        assert!(parse(&["a", "b"]).is_err());
+    }
+
+    #[test]
+    fn paths_after_double_dash() {
+        let opts = parse(&["--", "-a"]).unwrap();
+        assert_eq!(opts.show_hidden, None);
+        assert_eq!(opts.start_path, Some(PathBuf::from("-a")));
+    }
+}
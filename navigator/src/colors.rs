@@ -0,0 +1,476 @@
+use std::{collections::HashMap, env, fs, path::Path};
+
+use ncurses::*;
+
+use crate::common::*;
+use crate::filesystem::*;
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Theme {
+    Default,
+    Color256,
+    Mono,
+    /// Colors from `LS_COLORS` or a dircolors file, like `ls --color`.
+    Ls,
+}
+
+impl Theme {
+    pub fn parse(s: &str) -> Option<Theme> {
+        match s {
+            "default" => Some(Theme::Default),
+            "256" | "256color" => Some(Theme::Color256),
+            "mono" | "monochrome" => Some(Theme::Mono),
+            "ls" | "ls_colors" | "dircolors" => Some(Theme::Ls),
+            _ => None,
+        }
+    }
+}
+
+/// The kinds of entries `LS_COLORS` has colors for.
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Indicator {
+    File,
+    Dir,
+    Link,
+    Fifo,
+    Socket,
+    Block,
+    Char,
+    Exec,
+    Setuid,
+    Setgid,
+    Sticky,
+    OtherWritable,
+    StickyOtherWritable,
+}
+
+// LS_COLORS code and dircolors keywords
+const INDICATORS: &[(Indicator, &str, &[&str])] = &[
+    (Indicator::File, "fi", &["FILE"]),
+    (Indicator::Dir, "di", &["DIR"]),
+    (Indicator::Link, "ln", &["LINK", "LNK", "SYMLINK"]),
+    (Indicator::Fifo, "pi", &["FIFO", "PIPE"]),
+    (Indicator::Socket, "so", &["SOCK"]),
+    (Indicator::Block, "bd", &["BLK", "BLOCK"]),
+    (Indicator::Char, "cd", &["CHR", "CHAR"]),
+    (Indicator::Exec, "ex", &["EXEC"]),
+    (Indicator::Setuid, "su", &["SETUID"]),
+    (Indicator::Setgid, "sg", &["SETGID"]),
+    (Indicator::Sticky, "st", &["STICKY"]),
+    (Indicator::OtherWritable, "ow", &["OTHER_WRITABLE", "OWR"]),
+    (
+        Indicator::StickyOtherWritable,
+        "tw",
+        &["STICKY_OTHER_WRITABLE", "OWT"],
+    ),
+];
+
+impl Indicator {
+    /// Picks the indicator like `ls` does: special permission bits first,
+    /// then the file type.
+    pub fn of(sn: &SysNode) -> Indicator {
+        match sn.typ {
+            NodeType::Dir => {
+                let sticky = sn.mode & 0o1000 != 0;
+                let other_writable = sn.mode & 0o002 != 0;
+                match (sticky, other_writable) {
+                    (true, true) => Indicator::StickyOtherWritable,
+                    (false, true) => Indicator::OtherWritable,
+                    (true, false) => Indicator::Sticky,
+                    (false, false) => Indicator::Dir,
+                }
+            }
+            NodeType::SymLink => Indicator::Link,
+            NodeType::BlockDevice => Indicator::Block,
+            NodeType::CharDevice => Indicator::Char,
+            NodeType::Fifo => Indicator::Fifo,
+            NodeType::Socket => Indicator::Socket,
+            NodeType::File if sn.mode & 0o4000 != 0 => Indicator::Setuid,
+            NodeType::File if sn.mode & 0o2000 != 0 => Indicator::Setgid,
+            NodeType::File if sn.mode & 0o111 != 0 => Indicator::Exec,
+            NodeType::File => Indicator::File,
+        }
+    }
+}
+
+/// Foreground and background color (-1 is the terminal default) and
+/// attributes like bold.
+#[derive(Copy, Clone, PartialEq)]
+pub struct Style {
+    fg: i16,
+    bg: i16,
+    attrs: attr_t,
+}
+
+impl Style {
+    const PLAIN: Style = Style {
+        fg: -1,
+        bg: -1,
+        attrs: 0,
+    };
+
+    /// Parses SGR codes as used in `LS_COLORS`, e.g. `01;34` or `38;5;208`.
+    pub fn parse(s: &str) -> Option<Style> {
+        let mut style = Style::PLAIN;
+        let mut codes = s.split(';').filter(|c| !c.is_empty());
+        while let Some(code) = codes.next() {
+            let code: i16 = code.parse().ok()?;
+            match code {
+                0 => style = Style::PLAIN,
+                1 => style.attrs |= A_BOLD,
+                2 => style.attrs |= A_DIM,
+                3 => style.attrs |= A_ITALIC,
+                4 => style.attrs |= A_UNDERLINE,
+                5 => style.attrs |= A_BLINK,
+                7 => style.attrs |= A_REVERSE,
+                30..=37 => style.fg = code - 30,
+                39 => style.fg = -1,
+                40..=47 => style.bg = code - 40,
+                49 => style.bg = -1,
+                90..=97 => style.fg = code - 90 + 8,
+                100..=107 => style.bg = code - 100 + 8,
+                38 | 48 => {
+                    let color = match codes.next()? {
+                        "5" => codes.next()?.parse::<u8>().ok()? as i16,
+                        // true color, approximated by the 256 color cube
+                        "2" => {
+                            let mut rgb = [0u16; 3];
+                            for c in &mut rgb {
+                                *c = codes.next()?.parse::<u8>().ok()? as u16;
+                            }
+                            16 + (rgb[0] * 5 / 255 * 36 + rgb[1] * 5 / 255 * 6 + rgb[2] * 5 / 255)
+                                as i16
+                        }
+                        _ => return None,
+                    };
+                    match code {
+                        38 => style.fg = color,
+                        _ => style.bg = color,
+                    }
+                }
+                _ => {}
+            }
+        }
+        Some(style)
+    }
+}
+
+/// Styles by entry kind and by file name ending, before they are turned into
+/// color pairs.
+pub struct Palette {
+    types: HashMap<Indicator, Style>,
+    extensions: Vec<(String, Style)>,
+}
+
+const DEFAULT_COLORS: &str = "di=34:ex=36:ln=35:pi=33:so=35:bd=01;33:cd=01;33:\
+su=37;41:sg=30;43:tw=30;42:ow=34;42:st=37;44:\
+*.tar=31:*.tgz=31:*.gz=31:*.zst=31:*.xz=31:*.bz2=31:*.zip=31:*.7z=31:*.deb=31:*.rpm=31";
+
+const COLORS_256: &str = "di=38;5;75:ex=38;5;114:ln=38;5;176:pi=38;5;179:so=38;5;176:\
+bd=01;38;5;179:cd=01;38;5;179:su=38;5;231;48;5;160:sg=38;5;16;48;5;178:\
+tw=38;5;16;48;5;114:ow=38;5;75;48;5;236:st=38;5;231;48;5;25:\
+*.tar=38;5;203:*.tgz=38;5;203:*.gz=38;5;203:*.zst=38;5;203:*.xz=38;5;203:*.bz2=38;5;203:\
+*.zip=38;5;203:*.7z=38;5;203:*.deb=38;5;203:*.rpm=38;5;203:\
+*.jpg=38;5;179:*.jpeg=38;5;179:*.png=38;5;179:*.gif=38;5;179:*.svg=38;5;179:*.webp=38;5;179:\
+*.mp3=38;5;141:*.flac=38;5;141:*.ogg=38;5;141:*.wav=38;5;141:\
+*.mp4=38;5;141:*.mkv=38;5;141:*.webm=38;5;141:*.avi=38;5;141:\
+*.md=38;5;187:*.txt=38;5;187:*.pdf=38;5;187";
+
+const MONO: &str = "di=01:ln=04:ex=01;04";
+
+impl Palette {
+    fn new() -> Palette {
+        Palette {
+            types: HashMap::new(),
+            extensions: Vec::new(),
+        }
+    }
+
+    /// Reads an `LS_COLORS` value. Unknown keys and invalid entries are
+    /// skipped, like `ls` does.
+    pub fn from_ls_colors(s: &str) -> Palette {
+        let mut palette = Palette::new();
+        for entry in s.split(':') {
+            let Some((key, value)) = entry.split_once('=') else {
+                continue;
+            };
+            let Some(style) = Style::parse(value) else {
+                continue;
+            };
+            match key.strip_prefix('*') {
+                Some(ending) => palette.extensions.push((ending.to_lowercase(), style)),
+                None => {
+                    if let Some(i) = INDICATORS.iter().find(|i| i.1 == key) {
+                        palette.types.insert(i.0, style);
+                    }
+                }
+            }
+        }
+        palette
+    }
+
+    /// Reads a dircolors database, the format `dircolors -p` prints.
+    pub fn from_dircolors(text: &str) -> Result<Palette, String> {
+        let mut palette = Palette::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let mut words = line.split_whitespace();
+            let (Some(key), Some(value)) = (words.next(), words.next()) else {
+                continue;
+            };
+            let style =
+                || Style::parse(value).ok_or(format!("{}: invalid color '{}'", n + 1, value));
+            if key.starts_with('.') || key.starts_with('*') {
+                let ending = key.trim_start_matches('*');
+                palette.extensions.push((ending.to_lowercase(), style()?));
+            } else if let Some(i) = INDICATORS.iter().find(|i| i.2.contains(&key)) {
+                palette.types.insert(i.0, style()?);
+            }
+            // TERM, COLOR, NORMAL, ... don't apply here
+        }
+        Ok(palette)
+    }
+
+    /// The palette of a theme. The `ls` theme reads `dircolors` if given,
+    /// otherwise `LS_COLORS`, and falls back to the default theme.
+    pub fn for_theme(theme: Theme, dircolors: Option<&Path>) -> Result<Palette, AppError> {
+        let palette = match theme {
+            Theme::Default => Palette::from_ls_colors(DEFAULT_COLORS),
+            Theme::Color256 => Palette::from_ls_colors(COLORS_256),
+            Theme::Mono => Palette::from_ls_colors(MONO),
+            Theme::Ls => match (dircolors, env::var("LS_COLORS")) {
+                (Some(path), _) => {
+                    let text = fs::read_to_string(path).map_err(|err| {
+                        AppError::ConfigError(format!("{}: {}", path.to_string_lossy(), err))
+                    })?;
+                    Palette::from_dircolors(&text).map_err(|err| {
+                        AppError::ConfigError(format!("{}:{}", path.to_string_lossy(), err))
+                    })?
+                }
+                (None, Ok(ls_colors)) if !ls_colors.is_empty() => {
+                    Palette::from_ls_colors(&ls_colors)
+                }
+                _ => Palette::from_ls_colors(DEFAULT_COLORS),
+            },
+        };
+        Ok(palette)
+    }
+}
+
+// xterm colors 16..255 as RGB
+fn rgb_256(color: i16) -> (i32, i32, i32) {
+    const LEVELS: [i32; 6] = [0, 95, 135, 175, 215, 255];
+    match color {
+        16..=231 => {
+            let c = (color - 16) as usize;
+            (LEVELS[c / 36], LEVELS[c / 6 % 6], LEVELS[c % 6])
+        }
+        _ => {
+            let gray = 8 + 10 * (color as i32 - 232);
+            (gray, gray, gray)
+        }
+    }
+}
+
+/// Maps a color the terminal doesn't have to the closest basic one.
+fn fit_color(color: i16, colors: i32) -> i16 {
+    if (color as i32) < colors {
+        return color;
+    }
+    if color < 16 {
+        return color - 8;
+    }
+    const BASIC: [(i32, i32, i32); 8] = [
+        (0, 0, 0),
+        (205, 0, 0),
+        (0, 205, 0),
+        (205, 205, 0),
+        (0, 0, 238),
+        (205, 0, 205),
+        (0, 205, 205),
+        (229, 229, 229),
+    ];
+    let (r, g, b) = rgb_256(color);
+    let distance = |c: &(i32, i32, i32)| (c.0 - r).pow(2) + (c.1 - g).pow(2) + (c.2 - b).pow(2);
+    (0..8).min_by_key(|i| distance(&BASIC[*i])).unwrap_or(7) as i16
+}
+
+/// A palette turned into ncurses attributes, with a color pair for every
+/// color combination used.
+pub struct ColorScheme {
+    types: HashMap<Indicator, attr_t>,
+    extensions: Vec<(String, attr_t)>,
+}
+
+impl ColorScheme {
+    /// Allocates the color pairs. Needs the screen to be set up.
+    pub fn init(palette: Palette) -> ColorScheme {
+        let colors = if has_colors() { COLORS() } else { 0 };
+        let mut pairs: Vec<(i16, i16)> = Vec::new();
+        let mut to_attrs = |style: Style| -> attr_t {
+            if colors == 0 || (style.fg < 0 && style.bg < 0) {
+                return style.attrs;
+            }
+            let fg = if style.fg < 0 {
+                -1
+            } else {
+                fit_color(style.fg, colors)
+            };
+            let bg = if style.bg < 0 {
+                -1
+            } else {
+                fit_color(style.bg, colors)
+            };
+            let pair = match pairs.iter().position(|p| *p == (fg, bg)) {
+                Some(idx) => idx + 1,
+                None => {
+                    if pairs.len() + 1 >= COLOR_PAIRS() as usize {
+                        return style.attrs;
+                    }
+                    pairs.push((fg, bg));
+                    init_pair(pairs.len() as i16, fg, bg);
+                    pairs.len()
+                }
+            };
+            COLOR_PAIR(pair as i16) | style.attrs
+        };
+        let types = palette
+            .types
+            .into_iter()
+            .map(|(ind, style)| (ind, to_attrs(style)))
+            .collect();
+        let extensions = palette
+            .extensions
+            .into_iter()
+            .map(|(ending, style)| (ending, to_attrs(style)))
+            .collect();
+        ColorScheme { types, extensions }
+    }
+
+    /// The attributes to show an entry with. File name endings are only
+    /// looked at for plain files, and the longest matching ending wins.
+    pub fn attrs(&self, sn: &SysNode) -> Option<attr_t> {
+        let indicator = Indicator::of(sn);
+        if indicator == Indicator::File {
+            let name = sn.name.to_string_lossy().to_lowercase();
+            let by_ending = self
+                .extensions
+                .iter()
+                .filter(|(ending, _)| name.ends_with(ending.as_str()))
+                .max_by_key(|(ending, _)| ending.len());
+            if let Some((_, attrs)) = by_ending {
+                return Some(*attrs);
+            }
+        }
+        // kinds without a style of their own look like the general kind
+        let fallback = match indicator {
+            Indicator::Sticky | Indicator::OtherWritable | Indicator::StickyOtherWritable => {
+                Some(Indicator::Dir)
+            }
+            Indicator::Setuid | Indicator::Setgid if sn.mode & 0o111 != 0 => Some(Indicator::Exec),
+            _ => None,
+        };
+        self.types
+            .get(&indicator)
+            .or_else(|| fallback.and_then(|f| self.types.get(&f)))
+            .copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn style(fg: i16, bg: i16, attrs: attr_t) -> Option<Style> {
+        Some(Style { fg, bg, attrs })
+    }
+
+    #[test]
+    fn parses_styles() {
+        assert!(Style::parse("") == Some(Style::PLAIN));
+        assert!(Style::parse("01;34") == style(4, -1, A_BOLD));
+        assert!(Style::parse("4;91;101") == style(9, 9, A_UNDERLINE));
+        assert!(Style::parse("01;31;0;33") == style(3, -1, 0));
+        assert!(Style::parse("32;39;44;49") == Some(Style::PLAIN));
+        // codes it has no use for are skipped
+        assert!(Style::parse("22;36") == style(6, -1, 0));
+
+        assert!(Style::parse("38;5;208") == style(208, -1, 0));
+        assert!(Style::parse("38;5;231;48;5;160") == style(231, 160, 0));
+        assert!(Style::parse("38;2;255;0;0") == style(196, -1, 0));
+        assert!(Style::parse("48;2;0;128;255") == style(-1, 33, 0));
+
+        for bad in [
+            "x",
+            "01;x",
+            "38",
+            "38;5",
+            "38;5;256",
+            "38;5;-1",
+            "38;2;1;2",
+            "38;2;1;2;300",
+            "38;9;1",
+        ] {
+            assert!(Style::parse(bad).is_none(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn reads_ls_colors() {
+        let palette = Palette::from_ls_colors(
+            "rs=0:di=01;34:ln=target:ex=nope:xx=31:bogus:*.TAR=31:*.gz=38;5;999:\
+            *README=04::so=38;2;0;0;255",
+        );
+        assert!(palette.types.get(&Indicator::Dir).copied() == style(4, -1, A_BOLD));
+        assert!(palette.types.get(&Indicator::Socket).copied() == style(21, -1, 0));
+        assert!(!palette.types.contains_key(&Indicator::Link));
+        assert!(!palette.types.contains_key(&Indicator::Exec));
+        assert_eq!(palette.types.len(), 2);
+        let endings: Vec<&str> = palette.extensions.iter().map(|e| e.0.as_str()).collect();
+        assert_eq!(endings, [".tar", "readme"]);
+        assert!(Palette::from_ls_colors("").types.is_empty());
+    }
+
+    #[test]
+    fn reads_dircolors() {
+        let text = "# comment\n\
+            TERM xterm*\n\
+            COLOR tty\n\
+            NORMAL 00\n\
+            DIR 01;34 # directories\n\
+            \n\
+            LINK 01;36\n\
+            OWT 30;42\n\
+            .TAR 01;31\n\
+            *~ 00;90\n\
+            EXEC\n";
+        let palette = Palette::from_dircolors(text).unwrap();
+        assert!(palette.types.get(&Indicator::Dir).copied() == style(4, -1, A_BOLD));
+        assert!(palette.types.get(&Indicator::Link).copied() == style(6, -1, A_BOLD));
+        assert!(palette.types.get(&Indicator::StickyOtherWritable).copied() == style(0, 2, 0));
+        assert_eq!(palette.types.len(), 3);
+        let endings: Vec<&str> = palette.extensions.iter().map(|e| e.0.as_str()).collect();
+        assert_eq!(endings, [".tar", "~"]);
+
+        let err = Palette::from_dircolors("DIR 01;34\nLINK 38;5;x\n").err();
+        assert_eq!(err.as_deref(), Some("2: invalid color '38;5;x'"));
+        assert!(Palette::from_dircolors("*.gz 38;2;1\n").is_err());
+    }
+
+    #[test]
+    fn fits_colors_to_the_terminal() {
+        assert_eq!(fit_color(208, 256), 208);
+        assert_eq!(fit_color(12, 16), 12);
+        assert_eq!(fit_color(4, 8), 4);
+        // bright colors lose their brightness
+        assert_eq!(fit_color(12, 8), 4);
+        assert_eq!(fit_color(9, 8), 1);
+        // the closest of the 8 basic colors
+        assert_eq!(fit_color(196, 8), 1);
+        assert_eq!(fit_color(46, 8), 2);
+        assert_eq!(fit_color(21, 8), 4);
+        assert_eq!(fit_color(208, 16), 3);
+        assert_eq!(fit_color(232, 8), 0);
+        assert_eq!(fit_color(255, 8), 7);
+    }
+}
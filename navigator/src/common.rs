@@ -1,4 +1,3 @@
-use ncurses::*;
 use thiserror;
 
 #[derive(thiserror::Error, Debug)]
@@ -9,6 +8,12 @@ pub enum AppError {
     #[error("Path error: {0} ('{1}')")]
     PathError(String, String),
 
+    #[error("{0}")]
+    UsageError(String),
+
+    #[error("Config error: {0}")]
+    ConfigError(String),
+
     #[error("Błąd IO: {0}")]
     IoError(#[from] std::io::Error),
 }
@@ -24,13 +29,3 @@ impl Size {
         Size { width, height }
     }
 }
-
-pub enum AppColorTypes {
-    Dir = 10,
-    Exec = 11,
-}
-
-pub fn init_app_colors() {
-    init_pair(AppColorTypes::Dir as i16, COLOR_BLUE, -1);
-    init_pair(AppColorTypes::Exec as i16, COLOR_CYAN, -1);
-}
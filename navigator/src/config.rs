@@ -0,0 +1,292 @@
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::colors::Theme;
+use crate::common::*;
+use crate::graph::columns::*;
+use crate::keymap::*;
+use crate::screen::Layout;
+use crate::tree_node::*;
+
+/// Application settings. Read from the config file, then overridden
+/// by command line options.
+///
+/// The config file is a list of `key = value` lines. `#` starts a comment at
+/// the start of a line or after a space; values in double quotes may contain
+/// it:
+///
+/// ```text
+/// show_hidden = true
+/// sort = time
+/// reverse = false
+/// theme = ls
+/// dircolors = ~/.dircolors
+/// layout = both
+/// one_file_system = true
+/// columns = perms,owner,size,mtime,name
+/// human_sizes = true
+/// mouse = false
+/// git = false
+/// keymap = vim
+/// ssh_command = ssh -F ~/.ssh/work_config
+/// export_path = ~/trees/navigator.json
+/// map = list x down,enter   # comment
+/// ```
+///
+/// `map` may be given several times; every line adds a key binding.
+pub struct Config {
+    pub show_hidden: bool,
+    pub sort: SortOrder,
+    pub sort_reverse: bool,
+    pub theme: Theme,
+    /// dircolors database for the `ls` theme, instead of `LS_COLORS`.
+    pub dircolors: Option<PathBuf>,
+    pub layout: Layout,
+    pub one_file_system: bool,
+    pub columns: Vec<Column>,
+    pub human_sizes: bool,
+    pub mouse: bool,
+    /// Git status decorations.
+    pub git: bool,
+    pub keymap: Profile,
+    /// The ssh program, with options, for browsing `ssh://` URLs.
+    pub ssh_command: String,
+    /// Suggested file for exporting the expanded tree.
+    pub export_path: String,
+    pub bindings: Vec<Binding>,
+}
+
+impl Config {
+    pub fn new() -> Config {
+        Config {
+            show_hidden: true,
+            sort: SortOrder::Name,
+            sort_reverse: false,
+            theme: Theme::Default,
+            dircolors: None,
+            layout: Layout::Both,
+            one_file_system: false,
+            columns: Column::defaults(),
+            human_sizes: false,
+            mouse: true,
+            git: true,
+            keymap: Profile::Default,
+            ssh_command: "ssh".to_owned(),
+            export_path: "~/navigator-tree.json".to_owned(),
+            bindings: Vec::new(),
+        }
+    }
+
+    pub fn default_path() -> Option<PathBuf> {
+        let dir = match env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+        };
+        Some(dir.join("navigator").join("config"))
+    }
+
+    /// Loads the config from `path`, or from the default location if no path
+    /// is given. A missing default config file is not an error.
+    pub fn load(path: Option<&Path>) -> Result<Config, AppError> {
+        let mut config = Config::new();
+        let (path, required) = match path {
+            Some(p) => (p.to_path_buf(), true),
+            None => match Config::default_path() {
+                Some(p) => (p, false),
+                None => return Ok(config),
+            },
+        };
+
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound && !required => return Ok(config),
+            Err(err) => {
+                return Err(AppError::ConfigError(format!(
+                    "{}: {}",
+                    path.to_string_lossy(),
+                    err
+                )))
+            }
+        };
+
+        config.apply_text(&text).map_err(|(line, msg)| {
+            AppError::ConfigError(format!("{}:{}: {}", path.to_string_lossy(), line, msg))
+        })?;
+        Ok(config)
+    }
+
+    /// Sets what the lines of a config file say. Returns the number of the
+    /// first wrong line and what's wrong with it.
+    fn apply_text(&mut self, text: &str) -> Result<(), (usize, String)> {
+        for (i, line) in text.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            let result = match line.split_once('=') {
+                Some((key, value)) => self.set(key.trim(), unquote(value.trim())),
+                None => Err("expected 'key = value'".to_owned()),
+            };
+            result.map_err(|msg| (i + 1, msg))?;
+        }
+        Ok(())
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "show_hidden" => self.show_hidden = parse_bool(value)?,
+            "sort" => {
+                self.sort =
+                    SortOrder::parse(value).ok_or(format!("invalid sort order '{}'", value))?
+            }
+            "reverse" => self.sort_reverse = parse_bool(value)?,
+            "theme" => {
+                self.theme = Theme::parse(value).ok_or(format!("unknown theme '{}'", value))?
+            }
+            "dircolors" => self.dircolors = Some(expand_home(value)),
+            "layout" => {
+                self.layout = Layout::parse(value).ok_or(format!("invalid layout '{}'", value))?
+            }
+            "one_file_system" => self.one_file_system = parse_bool(value)?,
+            "columns" => self.columns = Column::parse_list(value)?,
+            "human_sizes" => self.human_sizes = parse_bool(value)?,
+            "mouse" => self.mouse = parse_bool(value)?,
+            "git" => self.git = parse_bool(value)?,
+            "keymap" => {
+                self.keymap = Profile::parse(value).ok_or(format!("unknown keymap '{}'", value))?
+            }
+            "ssh_command" => self.ssh_command = value.to_owned(),
+            "export_path" => self.export_path = value.to_owned(),
+            "map" => self.bindings.push(Binding::parse(value)?),
+            _ => return Err(format!("unknown setting '{}'", key)),
+        }
+        Ok(())
+    }
+
+    pub fn column_layout(&self) -> ColumnLayout {
+        ColumnLayout::new(&self.columns, self.human_sizes)
+    }
+
+    /// The key profile with the `map` lines applied.
+    pub fn key_bindings(&self) -> Keymap {
+        let mut keymap = Keymap::new(self.keymap);
+        for binding in &self.bindings {
+            keymap.bind(binding.clone());
+        }
+        keymap
+    }
+
+    pub fn load_options(&self) -> LoadOptions {
+        LoadOptions {
+            show_hidden: self.show_hidden,
+            sort: self.sort,
+            reverse: self.sort_reverse,
+        }
+    }
+}
+
+/// The line up to a `#` which starts a comment: at the start of the line or
+/// after whitespace, outside double quotes.
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    let mut prev = ' ';
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '#' if !quoted && prev.is_whitespace() => return &line[..i],
+            _ => {}
+        }
+        prev = c;
+    }
+    line
+}
+
+/// A value without the double quotes around it, if it has them.
+fn unquote(value: &str) -> &str {
+    match value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+        Some(inner) => inner,
+        None => value,
+    }
+}
+
+/// Expands a leading `~/` to the home directory.
+fn expand_home(value: &str) -> PathBuf {
+    match (value.strip_prefix("~/"), env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(value),
+    }
+}
+
+pub fn parse_bool(value: &str) -> Result<bool, String> {
+    match value {
+        "true" | "yes" | "on" | "1" => Ok(true),
+        "false" | "no" | "off" | "0" => Ok(false),
+        _ => Err(format!("expected a boolean, got '{}'", value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<Config, (usize, String)> {
+        let mut config = Config::new();
+        config.apply_text(text).map(|_| config)
+    }
+
+    #[test]
+    fn comments() {
+        let config = parse("# settings\n\n  # indented\nshow_hidden = true # trailing\n").unwrap();
+        assert!(config.show_hidden);
+    }
+
+    #[test]
+    fn hash_inside_values() {
+        let config = parse("ssh_command = \"ssh -o 'Foo #1'\"  # quoted\n").unwrap();
+        assert_eq!(config.ssh_command, "ssh -o 'Foo #1'");
+        let config = parse("ssh_command = ssh -F a#b\n").unwrap();
+        assert_eq!(config.ssh_command, "ssh -F a#b");
+    }
+
+    #[test]
+    fn comment_helpers() {
+        assert_eq!(strip_comment("#all"), "");
+        assert_eq!(strip_comment("a = b#c"), "a = b#c");
+        assert_eq!(strip_comment("a = \"b #c\" #d"), "a = \"b #c\" ");
+        assert_eq!(unquote("\"#ff0000\""), "#ff0000");
+        assert_eq!(unquote("\"half"), "\"half");
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        let err = parse("sort = name\nsort = sideways\n").err().unwrap();
+        assert_eq!(err.0, 2);
+        let err = parse("show_hidden\n").err().unwrap();
+        assert_eq!(err, (1, "expected 'key = value'".to_owned()));
+        assert!(parse("nonsense = 1\n").is_err());
+    }
+
+    #[test]
+    fn remapped_keys() {
+        let config = parse(
+            "keymap = vim\nmap = list x down,enter  # macro\nmap = global q none\nmap = tree <F3> up\n",
+        )
+        .unwrap();
+        let mut dispatcher = Dispatcher::new(config.key_bindings());
+        let mut actions = |key, context| match dispatcher.feed(key, context) {
+            Dispatch::Actions(actions, _) => actions,
+            _ => Vec::new(),
+        };
+        assert!(actions('x' as i32, Context::List) == [Action::Down, Action::Enter]);
+        assert!(actions('q' as i32, Context::List).is_empty());
+        assert!(actions(ncurses::KEY_F(3), Context::Tree) == [Action::Up]);
+        // the rest of the vim profile stays
+        assert!(actions('j' as i32, Context::Tree) == [Action::Down]);
+
+        let err = parse("map = list x up\nmap = list x fly\n").err().unwrap();
+        assert_eq!(err, (2, "unknown action 'fly'".to_owned()));
+        assert!(parse("keymap = emacs\n").is_err());
+    }
+}
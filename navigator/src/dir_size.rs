@@ -0,0 +1,306 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::mpsc::{channel, Receiver, Sender, TryRecvError},
+    thread,
+};
+
+use crate::{filesystem::*, tree_node::*};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum DirSize {
+    Unknown,
+    Computing,
+    Done(u64),
+    Failed,
+}
+
+const SPINNER: [char; 4] = ['|', '/', '-', '\\'];
+
+/// Walks the directories of one scan. Hard links are remembered across the
+/// whole scan, and the sizes of requested directories met on the way are
+/// kept.
+struct SizeWalker {
+    one_file_system: bool,
+    dev: u64,
+    seen: HashSet<(u64, u64)>,
+    wanted: HashSet<PathBuf>,
+    found: HashMap<PathBuf, u64>,
+}
+
+impl SizeWalker {
+    // the disk usage of a directory's entries, without the directory itself
+    fn walk(&mut self, path: &Path) -> io::Result<u64> {
+        let mut total = 0;
+        for entry in fs::read_dir(path)? {
+            let Ok(entry) = entry else { continue };
+            let Ok(md) = entry.metadata() else { continue };
+            if md.nlink() > 1 && !md.is_dir() && !self.seen.insert((md.dev(), md.ino())) {
+                continue;
+            }
+            let mut size = md.blocks() * 512;
+            if md.is_dir() && (!self.one_file_system || md.dev() == self.dev) {
+                let path = entry.path();
+                size += self.walk(&path).unwrap_or(0);
+                if self.wanted.contains(&path) {
+                    self.found.insert(path, size);
+                }
+            }
+            total += size;
+        }
+        Ok(total)
+    }
+}
+
+/// Sums the disk usage (allocated blocks) of directories with everything
+/// below them, like one `du` run with `dirs` as arguments: files with
+/// several hard links are counted once in all of them, and directories below
+/// others get their sizes from the same walk. With `one_file_system`,
+/// directories on other file systems are not entered. Unreadable
+/// subdirectories are skipped, an unreadable requested directory gets no
+/// size. `done` is called with the sizes as they are found.
+pub fn compute_sizes(
+    dirs: &[PathBuf],
+    one_file_system: bool,
+    mut done: impl FnMut(&Path, Option<u64>),
+) {
+    let mut walker = SizeWalker {
+        one_file_system,
+        dev: 0,
+        seen: HashSet::new(),
+        wanted: dirs.iter().cloned().collect(),
+        found: HashMap::new(),
+    };
+    // directories above others first, which then are reached on the way
+    let mut order = dirs.to_vec();
+    order.sort_by_key(|dir| dir.components().count());
+    for dir in &order {
+        if let Some(size) = walker.found.remove(dir) {
+            done(dir, Some(size));
+            continue;
+        }
+        let size = fs::metadata(dir).and_then(|md| {
+            walker.dev = md.dev();
+            Ok(md.blocks() * 512 + walker.walk(dir)?)
+        });
+        done(dir, size.ok());
+    }
+}
+
+/// Computes directory sizes in a background thread. Results are picked up by
+/// `poll`, which stores them in the `dir_size` of the requesting nodes.
+pub struct SizeJobs {
+    one_file_system: bool,
+    requests: Option<Sender<(usize, PathBuf)>>,
+    results: Option<Receiver<(usize, Option<u64>)>>,
+    pending: HashMap<usize, TreeNodeWeak>,
+    next_id: usize,
+    tick: usize,
+}
+
+impl SizeJobs {
+    pub fn new(one_file_system: bool) -> SizeJobs {
+        SizeJobs {
+            one_file_system,
+            requests: None,
+            results: None,
+            pending: HashMap::new(),
+            next_id: 0,
+            tick: 0,
+        }
+    }
+
+    fn start_worker(&mut self) {
+        let (req_tx, req_rx) = channel::<(usize, PathBuf)>();
+        let (res_tx, res_rx) = channel();
+        let one_file_system = self.one_file_system;
+        thread::spawn(move || {
+            // requests queued together are one scan
+            while let Ok(first) = req_rx.recv() {
+                let mut batch = vec![first];
+                batch.extend(req_rx.try_iter());
+                let dirs: Vec<PathBuf> = batch.iter().map(|(_, path)| path.clone()).collect();
+                let mut sent = true;
+                compute_sizes(&dirs, one_file_system, |dir, size| {
+                    for (id, _) in batch.iter().filter(|(_, path)| path == dir) {
+                        sent &= res_tx.send((*id, size)).is_ok();
+                    }
+                });
+                if !sent {
+                    break;
+                }
+            }
+        });
+        self.requests = Some(req_tx);
+        self.results = Some(res_rx);
+    }
+
+    /// Queues a size computation for a directory node. Nodes which are
+    /// already computed, being computed or failed are skipped; see `retry`.
+    pub fn request(&mut self, node: &TreeNodeRef) {
+        {
+            let n = node.borrow();
+            // sizes are computed on the local file system
+            if n.sys_node.typ != NodeType::Dir
+                || !n.location().0.is_local()
+                || matches!(
+                    n.dir_size,
+                    DirSize::Computing | DirSize::Done(_) | DirSize::Failed
+                )
+            {
+                return;
+            }
+        }
+        if self.requests.is_none() {
+            self.start_worker();
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        let path = node.borrow().get_path();
+        if let Some(requests) = &self.requests {
+            if requests.send((id, path)).is_ok() {
+                node.borrow_mut().dir_size = DirSize::Computing;
+                self.pending.insert(id, Rc::downgrade(node));
+            }
+        }
+    }
+
+    /// Like `request`, but also computes a failed directory again, when the
+    /// user asks for it.
+    pub fn retry(&mut self, node: &TreeNodeRef) {
+        if node.borrow().dir_size == DirSize::Failed {
+            node.borrow_mut().dir_size = DirSize::Unknown;
+        }
+        self.request(node);
+    }
+
+    pub fn busy(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Stores finished results. Returns true if any node got its size.
+    pub fn poll(&mut self) -> bool {
+        self.tick = self.tick.wrapping_add(1);
+        let mut changed = false;
+        while let Some(results) = &self.results {
+            match results.try_recv() {
+                Ok((id, size)) => {
+                    // the node may have been unloaded in the meantime
+                    if let Some(node) = self.pending.remove(&id).and_then(|w| w.upgrade()) {
+                        node.borrow_mut().dir_size = match size {
+                            Some(size) => DirSize::Done(size),
+                            None => DirSize::Failed,
+                        };
+                        changed = true;
+                    }
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    for node in self.pending.drain().filter_map(|(_, w)| w.upgrade()) {
+                        node.borrow_mut().dir_size = DirSize::Failed;
+                    }
+                    self.requests = None;
+                    self.results = None;
+                    changed = true;
+                }
+            }
+        }
+        changed
+    }
+
+    pub fn spinner(&self) -> char {
+        SPINNER[self.tick % SPINNER.len()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process;
+
+    fn sizes(dirs: &[PathBuf]) -> HashMap<PathBuf, u64> {
+        let mut sizes = HashMap::new();
+        compute_sizes(dirs, false, |dir, size| {
+            sizes.insert(dir.to_path_buf(), size.unwrap());
+        });
+        sizes
+    }
+
+    fn du(dir: &Path) -> u64 {
+        let out = process::Command::new("du")
+            .arg("-sk")
+            .arg(dir)
+            .output()
+            .unwrap();
+        let text = String::from_utf8(out.stdout).unwrap();
+        text.split_whitespace()
+            .next()
+            .unwrap()
+            .parse::<u64>()
+            .unwrap()
+            * 1024
+    }
+
+    #[test]
+    fn hard_links_count_once_per_scan() {
+        let top = std::env::temp_dir().join(format!("navigator-size-{}", process::id()));
+        let (a, b) = (top.join("a"), top.join("b"));
+        fs::create_dir_all(a.join("sub")).unwrap();
+        fs::create_dir_all(&b).unwrap();
+        fs::write(a.join("sub/data"), vec![1u8; 64 * 1024]).unwrap();
+        fs::hard_link(a.join("sub/data"), b.join("link")).unwrap();
+        fs::write(b.join("tiny"), b"x").unwrap();
+
+        // the same as du, in blocks, not apparent sizes
+        let all = sizes(std::slice::from_ref(&top));
+        assert_eq!(all[&top], du(&top));
+        let alone = sizes(std::slice::from_ref(&b));
+        assert_eq!(alone[&b], du(&b));
+
+        // like `du a b`: the link counts in the first one only
+        let both = sizes(&[a.clone(), b.clone()]);
+        assert_eq!(both[&a], du(&a));
+        assert_eq!(
+            both[&a] + both[&b],
+            all[&top] - fs::metadata(&top).unwrap().blocks() * 512
+        );
+
+        // below another one: taken from the same walk, where the link
+        // counts in the one read first
+        let nested = sizes(&[top.clone(), a.join("sub"), b.clone()]);
+        assert_eq!(nested[&top], all[&top]);
+        let dir_blocks = |dir: &Path| fs::metadata(dir).unwrap().blocks() * 512;
+        assert_eq!(
+            nested[&top],
+            dir_blocks(&top) + dir_blocks(&a) + nested[&a.join("sub")] + nested[&b]
+        );
+
+        fs::remove_dir_all(&top).unwrap();
+    }
+
+    #[test]
+    fn failed_sizes_wait_for_a_retry() {
+        let dir = std::env::temp_dir().join(format!("navigator-retry-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let node = TreeNode::from(SysNode::new(dir.as_os_str(), NodeType::Dir));
+        node.borrow_mut().dir_size = DirSize::Failed;
+
+        // as when the tree view asks for the sizes it shows
+        let mut jobs = SizeJobs::new(false);
+        jobs.request(&node);
+        assert!(!jobs.busy());
+        assert!(node.borrow().dir_size == DirSize::Failed);
+
+        jobs.retry(&node);
+        assert!(jobs.busy());
+        while jobs.busy() {
+            jobs.poll();
+            thread::sleep(std::time::Duration::from_millis(10));
+        }
+        assert!(matches!(node.borrow().dir_size, DirSize::Done(_)));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,290 @@
+use std::ffi::OsStr;
+use std::io::{self, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+use super::walk::*;
+use crate::{filesystem::*, tree_node::*};
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum JsonFormat {
+    /// One document, subdirectories nested in `children`.
+    Tree,
+    /// One object per line with a `path` relative to the root.
+    Lines,
+}
+
+/// Where the children of a directory come from.
+pub enum ChildSource<'a> {
+    /// Read the file system, as far as the walk options allow.
+    Load(&'a LoadOptions),
+    /// Only what is expanded in the tree (used to export from the UI).
+    Expanded,
+}
+
+pub struct JsonExporter<'a> {
+    out: &'a mut dyn Write,
+    format: JsonFormat,
+    opts: &'a WalkOptions,
+    source: ChildSource<'a>,
+}
+
+impl<'a> JsonExporter<'a> {
+    pub fn new(
+        out: &'a mut dyn Write,
+        format: JsonFormat,
+        opts: &'a WalkOptions,
+        source: ChildSource<'a>,
+    ) -> JsonExporter<'a> {
+        JsonExporter {
+            out,
+            format,
+            opts,
+            source,
+        }
+    }
+
+    pub fn export(&mut self, root: &TreeNodeRef) -> io::Result<()> {
+        match self.format {
+            JsonFormat::Tree => {
+                self.write_tree(root, 0)?;
+                writeln!(self.out)
+            }
+            JsonFormat::Lines => self.write_lines(root, Path::new("."), 0),
+        }
+    }
+
+    fn children(&self, node: &TreeNodeRef) -> Vec<TreeNodeRef> {
+        match self.source {
+            ChildSource::Load(load_opts) => visible_children(node, self.opts, load_opts),
+            ChildSource::Expanded => {
+                let n = node.borrow();
+                if !n.expanded || !n.loaded {
+                    return Vec::new();
+                }
+                n.subnodes
+                    .iter()
+                    .filter(|sn| {
+                        let sn = sn.borrow();
+                        let is_dir = sn.sys_node.typ == NodeType::Dir;
+                        (is_dir || !self.opts.dirs_only)
+                            && self.opts.filter.accepts(&sn.sys_node.name, is_dir)
+                    })
+                    .cloned()
+                    .collect()
+            }
+        }
+    }
+
+    // Nodes loaded only for the export are dropped afterwards.
+    fn release(&self, node: &TreeNodeRef) {
+        if let ChildSource::Load(_) = self.source {
+            node.borrow_mut().unload();
+        }
+    }
+
+    fn write_tree(&mut self, node: &TreeNodeRef, depth: usize) -> io::Result<()> {
+        write!(self.out, "{{")?;
+        write_fields(self.out, &node.borrow().sys_node)?;
+        if node.borrow().sys_node.typ == NodeType::Dir && self.opts.descend(depth) {
+            write!(self.out, ",\"children\":[")?;
+            for (i, child) in self.children(node).iter().enumerate() {
+                if i > 0 {
+                    write!(self.out, ",")?;
+                }
+                self.write_tree(child, depth + 1)?;
+            }
+            write!(self.out, "]")?;
+            self.release(node);
+        }
+        write!(self.out, "}}")
+    }
+
+    fn write_lines(&mut self, node: &TreeNodeRef, path: &Path, depth: usize) -> io::Result<()> {
+        write!(self.out, "{{")?;
+        write_os_str(self.out, "path", path.as_os_str())?;
+        write!(self.out, ",")?;
+        write_fields(self.out, &node.borrow().sys_node)?;
+        writeln!(self.out, "}}")?;
+        if node.borrow().sys_node.typ == NodeType::Dir && self.opts.descend(depth) {
+            for child in self.children(node) {
+                let child_path = path.join(&child.borrow().sys_node.name);
+                self.write_lines(&child, &child_path, depth + 1)?;
+            }
+            self.release(node);
+        }
+        Ok(())
+    }
+}
+
+fn type_name(typ: &NodeType) -> &'static str {
+    match typ {
+        NodeType::File => "file",
+        NodeType::Dir => "dir",
+        NodeType::SymLink => "symlink",
+        NodeType::BlockDevice => "block",
+        NodeType::CharDevice => "char",
+        NodeType::Fifo => "fifo",
+        NodeType::Socket => "socket",
+    }
+}
+
+fn write_fields(out: &mut dyn Write, sn: &SysNode) -> io::Result<()> {
+    write_os_str(out, "name", &sn.name)?;
+    write!(
+        out,
+        ",\"type\":\"{}\",\"mode\":\"{:04o}\",\"owner\":",
+        type_name(&sn.typ),
+        sn.mode & 0o7777
+    )?;
+    write_str(out, &sn.user.to_string_lossy())?;
+    write!(out, ",\"group\":")?;
+    write_str(out, &sn.group.to_string_lossy())?;
+    write!(
+        out,
+        ",\"size\":{},\"mtime\":\"{}\"",
+        sn.size,
+        sn.modified.to_rfc3339()
+    )
+}
+
+/// Writes `"key":"value"`. Values which are not valid UTF-8 also get a
+/// `key_bytes` array with their exact bytes.
+fn write_os_str(out: &mut dyn Write, key: &str, value: &OsStr) -> io::Result<()> {
+    write!(out, "\"{}\":", key)?;
+    write_str(out, &value.to_string_lossy())?;
+    if value.to_str().is_none() {
+        write!(out, ",\"{}_bytes\":[", key)?;
+        for (i, b) in value.as_bytes().iter().enumerate() {
+            write!(out, "{}{}", if i > 0 { "," } else { "" }, b)?;
+        }
+        write!(out, "]")?;
+    }
+    Ok(())
+}
+
+pub fn write_str(out: &mut dyn Write, s: &str) -> io::Result<()> {
+    write!(out, "\"")?;
+    for ch in s.chars() {
+        match ch {
+            '"' => write!(out, "\\\"")?,
+            '\\' => write!(out, "\\\\")?,
+            '\n' => write!(out, "\\n")?,
+            '\r' => write!(out, "\\r")?,
+            '\t' => write!(out, "\\t")?,
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32)?,
+            c => write!(out, "{}", c)?,
+        }
+    }
+    write!(out, "\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tree::Tree;
+    use std::fs::{self, File, Permissions};
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::PathBuf;
+    use std::time::{Duration, SystemTime};
+    use std::{env, process};
+
+    // top/{a"b\c<tab>d, bad<0xff>, sub/<0x01>ctl}, all modified at the same time
+    fn make_tree(name: &str) -> PathBuf {
+        let top = env::temp_dir()
+            .join(format!("navigator-json-{}-{}", name, process::id()))
+            .join("top");
+        let _ = fs::remove_dir_all(&top);
+        fs::create_dir_all(top.join("sub")).unwrap();
+        let files = [
+            (OsStr::new("a\"b\\c\td"), 0o644),
+            (OsStr::from_bytes(b"bad\xff"), 0o600),
+            (OsStr::new("sub/\u{1}ctl"), 0o640),
+            (OsStr::new("sub"), 0o700),
+            (OsStr::new(""), 0o755),
+        ];
+        let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        for (name, mode) in files {
+            let path = top.join(name);
+            if !path.exists() {
+                fs::write(&path, "xy").unwrap();
+            }
+            File::open(&path).unwrap().set_modified(time).unwrap();
+            fs::set_permissions(&path, Permissions::from_mode(mode)).unwrap();
+        }
+        top
+    }
+
+    fn export(top: &Path, format: JsonFormat) -> String {
+        let load_opts = LoadOptions {
+            show_hidden: false,
+            sort: SortOrder::Name,
+            reverse: false,
+        };
+        let mut tree = Tree::new(load_opts.clone(), false, false);
+        tree.go_to_path(top).unwrap();
+        let mut out = Vec::new();
+        let opts = WalkOptions::default();
+        JsonExporter::new(&mut out, format, &opts, ChildSource::Load(&load_opts))
+            .export(&tree.curr_dir())
+            .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    // fills in what depends on the system: owner, group, time, directory sizes
+    fn expected(top: &Path, text: &str) -> String {
+        let sn = SysNode::from_path(top);
+        let size = |dir: &Path| fs::metadata(dir).unwrap().len().to_string();
+        text.replace("$OWNER", &sn.user.to_string_lossy())
+            .replace("$GROUP", &sn.group.to_string_lossy())
+            .replace("$TIME", &sn.modified.to_rfc3339())
+            .replace("$TOP", &size(top))
+            .replace("$SUB", &size(&top.join("sub")))
+    }
+
+    #[test]
+    fn lines_keep_odd_names() {
+        let top = make_tree("lines");
+        let fields = "\"owner\":\"$OWNER\",\"group\":\"$GROUP\"";
+        let text = [
+            r#"{"path":".","name":"top","type":"dir","mode":"0755",F,"size":$TOP,"mtime":"$TIME"}"#,
+            r#"{"path":"./sub","name":"sub","type":"dir","mode":"0700",F,"size":$SUB,"mtime":"$TIME"}"#,
+            r#"{"path":"./sub/\u0001ctl","name":"\u0001ctl","type":"file","mode":"0640",F,"size":2,"mtime":"$TIME"}"#,
+            r#"{"path":"./a\"b\\c\td","name":"a\"b\\c\td","type":"file","mode":"0644",F,"size":2,"mtime":"$TIME"}"#,
+            r#"{"path":"./bad�","path_bytes":[46,47,98,97,100,255],"name":"bad�","name_bytes":[98,97,100,255],"type":"file","mode":"0600",F,"size":2,"mtime":"$TIME"}"#,
+            "",
+        ]
+        .join("\n")
+        .replace(",F,", &format!(",{},", fields));
+        assert_eq!(export(&top, JsonFormat::Lines), expected(&top, &text));
+        fs::remove_dir_all(top.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn tree_nests_children() {
+        let top = make_tree("tree");
+        let text = export(&top, JsonFormat::Tree);
+        let fields = "\"owner\":\"$OWNER\",\"group\":\"$GROUP\"";
+        let tree = concat!(
+            r#"{"name":"top","type":"dir","mode":"0755",F,"size":$TOP,"mtime":"$TIME","children":["#,
+            r#"{"name":"sub","type":"dir","mode":"0700",F,"size":$SUB,"mtime":"$TIME","children":["#,
+            r#"{"name":"\u0001ctl","type":"file","mode":"0640",F,"size":2,"mtime":"$TIME"}]},"#,
+            r#"{"name":"a\"b\\c\td","type":"file","mode":"0644",F,"size":2,"mtime":"$TIME"},"#,
+            r#"{"name":"bad�","name_bytes":[98,97,100,255],"type":"file","mode":"0600",F,"size":2,"mtime":"$TIME"}]}"#,
+            "\n"
+        )
+        .replace(",F,", &format!(",{},", fields));
+        assert_eq!(text, expected(&top, &tree));
+        fs::remove_dir_all(top.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn escapes_strings() {
+        let mut out = Vec::new();
+        write_str(&mut out, "q\"b\\n\nr\rt\t\u{0}\u{1f}\u{7f}é").unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            r#""q\"b\\n\nr\rt\t\u0000\u001f"#.to_owned() + "\u{7f}é\""
+        );
+    }
+}
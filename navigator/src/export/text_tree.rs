@@ -0,0 +1,180 @@
+use std::io::{self, Write};
+
+use super::walk::*;
+use crate::graph::tree_view::*;
+use crate::{filesystem::*, tree_node::*};
+
+/// Writes a subtree in the style of the `tree` command.
+pub struct TreePrinter<'a> {
+    out: &'a mut dyn Write,
+    style: &'a LineStyle,
+    opts: &'a WalkOptions,
+    load_opts: &'a LoadOptions,
+    dirs: usize,
+    files: usize,
+}
+
+impl<'a> TreePrinter<'a> {
+    pub fn new(
+        out: &'a mut dyn Write,
+        style: &'a LineStyle,
+        opts: &'a WalkOptions,
+        load_opts: &'a LoadOptions,
+    ) -> TreePrinter<'a> {
+        TreePrinter {
+            out,
+            style,
+            opts,
+            load_opts,
+            dirs: 0,
+            files: 0,
+        }
+    }
+
+    /// Prints the subtree of `root`, with `label` in place of the root's name,
+    /// followed by a directory and file count.
+    pub fn print(&mut self, root: &TreeNodeRef, label: &str) -> io::Result<()> {
+        writeln!(self.out, "{}", label)?;
+        let mut prevs_stack: Vec<bool> = Vec::new();
+        self.print_children(root, 0, &mut prevs_stack)?;
+        writeln!(self.out)?;
+        writeln!(
+            self.out,
+            "{} director{}, {} file{}",
+            self.dirs,
+            if self.dirs == 1 { "y" } else { "ies" },
+            self.files,
+            if self.files == 1 { "" } else { "s" }
+        )
+    }
+
+    fn print_children(
+        &mut self,
+        node: &TreeNodeRef,
+        depth: usize,
+        prevs_stack: &mut Vec<bool>,
+    ) -> io::Result<()> {
+        if !self.opts.descend(depth) {
+            return Ok(());
+        }
+        let children = visible_children(node, self.opts, self.load_opts);
+        let len = children.len();
+        for (i, child) in children.iter().enumerate() {
+            let tbc = i < len - 1;
+            let is_dir = {
+                let c = child.borrow();
+                let prefix = branch_prefix(self.style, prevs_stack, Some(tbc));
+                write!(self.out, "{}{}", prefix, escape_name(&c.sys_node.name))?;
+                if c.sys_node.typ == NodeType::SymLink {
+                    if let Ok(target) = c.location().0.read_link(&c.location().1) {
+                        write!(self.out, " -> {}", escape_name(target.as_os_str()))?;
+                    }
+                }
+                writeln!(self.out)?;
+                c.sys_node.typ == NodeType::Dir
+            };
+            if is_dir {
+                self.dirs += 1;
+                prevs_stack.push(tbc);
+                self.print_children(child, depth + 1, prevs_stack)?;
+                prevs_stack.pop();
+                // printed subtrees are not needed any more
+                child.borrow_mut().unload();
+            } else {
+                self.files += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tree::Tree;
+    use std::os::unix::fs::symlink;
+    use std::path::{Path, PathBuf};
+    use std::{env, fs, process};
+
+    fn load_opts() -> LoadOptions {
+        LoadOptions {
+            show_hidden: false,
+            sort: SortOrder::Name,
+            reverse: false,
+        }
+    }
+
+    // top/{a.txt, b/{c.log, d/e.txt}, link -> a.txt, z.txt, .hidden}, listed
+    // directories first
+    fn make_tree(name: &str) -> PathBuf {
+        let top = env::temp_dir().join(format!("navigator-print-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&top);
+        fs::create_dir_all(top.join("b").join("d")).unwrap();
+        for file in ["a.txt", "b/c.log", "b/d/e.txt", "z.txt", ".hidden"] {
+            fs::write(top.join(file), "").unwrap();
+        }
+        symlink("a.txt", top.join("link")).unwrap();
+        top
+    }
+
+    fn print(top: &Path, style: &LineStyle, opts: &WalkOptions) -> String {
+        let mut tree = Tree::new(load_opts(), false, false);
+        tree.go_to_path(top).unwrap();
+        let mut out = Vec::new();
+        TreePrinter::new(&mut out, style, opts, &load_opts())
+            .print(&tree.curr_dir(), "top")
+            .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn draws_branches() {
+        let top = make_tree("unicode");
+        let text = print(&top, &UNICODE_LINES, &WalkOptions::default());
+        assert_eq!(
+            text,
+            "top\n\
+            ├── b\n\
+            │   ├── d\n\
+            │   │   └── e.txt\n\
+            │   └── c.log\n\
+            ├── a.txt\n\
+            ├── link -> a.txt\n\
+            └── z.txt\n\
+            \n\
+            2 directories, 5 files\n"
+        );
+        fs::remove_dir_all(&top).unwrap();
+    }
+
+    #[test]
+    fn limits_and_filters() {
+        let top = make_tree("ascii");
+        let opts = WalkOptions {
+            max_depth: Some(1),
+            ..WalkOptions::default()
+        };
+        let text = print(&top, &ASCII_LINES, &opts);
+        assert_eq!(
+            text,
+            "top\n|-- b\n|-- a.txt\n|-- link -> a.txt\n`-- z.txt\n\n1 directory, 3 files\n"
+        );
+
+        let mut opts = WalkOptions::default();
+        opts.filter.include.push(Pattern::new("*.txt"));
+        opts.filter.exclude.push(Pattern::new("z*"));
+        let text = print(&top, &ASCII_LINES, &opts);
+        assert_eq!(
+            text,
+            "top\n|-- b\n|   `-- d\n|       `-- e.txt\n`-- a.txt\n\n2 directories, 2 files\n"
+        );
+
+        let opts = WalkOptions {
+            dirs_only: true,
+            ..WalkOptions::default()
+        };
+        let text = print(&top, &ASCII_LINES, &opts);
+        assert_eq!(text, "top\n`-- b\n    `-- d\n\n2 directories, 0 files\n");
+        fs::remove_dir_all(&top).unwrap();
+    }
+}
@@ -0,0 +1,176 @@
+use std::ffi::OsStr;
+
+use crate::{filesystem::*, tree_node::*};
+
+/// Shell-like wildcard pattern: `*`, `?` and `[...]` classes (`[!...]`
+/// negates a class).
+pub struct Pattern {
+    chars: Vec<char>,
+}
+
+impl Pattern {
+    pub fn new(pattern: &str) -> Pattern {
+        Pattern {
+            chars: pattern.chars().collect(),
+        }
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        let name: Vec<char> = name.chars().collect();
+        Pattern::match_from(&self.chars, &name)
+    }
+
+    fn match_from(pat: &[char], name: &[char]) -> bool {
+        match pat.first() {
+            None => name.is_empty(),
+            Some('*') => (0..=name.len()).any(|i| Pattern::match_from(&pat[1..], &name[i..])),
+            Some('?') => !name.is_empty() && Pattern::match_from(&pat[1..], &name[1..]),
+            Some('[') => match Pattern::match_class(&pat[1..], name.first()) {
+                Some((matched, rest)) => matched && Pattern::match_from(rest, &name[1..]),
+                // no closing bracket - treat '[' literally
+                None => name.first() == Some(&'[') && Pattern::match_from(&pat[1..], &name[1..]),
+            },
+            Some(c) => name.first() == Some(c) && Pattern::match_from(&pat[1..], &name[1..]),
+        }
+    }
+
+    // Returns whether `ch` matches the class and the pattern after the class.
+    fn match_class<'a>(pat: &'a [char], ch: Option<&char>) -> Option<(bool, &'a [char])> {
+        let (negate, mut i) = match pat.first() {
+            Some('!') | Some('^') => (true, 1),
+            _ => (false, 0),
+        };
+        let start = i;
+        let mut found = false;
+        while i < pat.len() {
+            if pat[i] == ']' && i > start {
+                let matched = ch.is_some() && (found != negate);
+                return Some((matched, &pat[i + 1..]));
+            }
+            if let Some(&ch) = ch {
+                if i + 2 < pat.len() && pat[i + 1] == '-' && pat[i + 2] != ']' {
+                    found |= pat[i] <= ch && ch <= pat[i + 2];
+                    i += 3;
+                    continue;
+                }
+                found |= pat[i] == ch;
+            }
+            i += 1;
+        }
+        None
+    }
+}
+
+/// Name filters. Include patterns apply to files only, so that directories
+/// leading to matching files are still walked; exclude patterns apply to all.
+#[derive(Default)]
+pub struct Filter {
+    pub include: Vec<Pattern>,
+    pub exclude: Vec<Pattern>,
+}
+
+impl Filter {
+    pub fn accepts(&self, name: &OsStr, is_dir: bool) -> bool {
+        let name = name.to_string_lossy();
+        if self.exclude.iter().any(|p| p.matches(&name)) {
+            return false;
+        }
+        is_dir || self.include.is_empty() || self.include.iter().any(|p| p.matches(&name))
+    }
+}
+
+#[derive(Default)]
+pub struct WalkOptions {
+    pub max_depth: Option<usize>,
+    pub dirs_only: bool,
+    pub filter: Filter,
+}
+
+impl WalkOptions {
+    pub fn descend(&self, depth: usize) -> bool {
+        match self.max_depth {
+            Some(max) => depth < max,
+            None => true,
+        }
+    }
+}
+
+/// Loads `node` and returns the subnodes that pass the walk options.
+pub fn visible_children(
+    node: &TreeNodeRef,
+    opts: &WalkOptions,
+    load_opts: &LoadOptions,
+) -> Vec<TreeNodeRef> {
+    let _ = TreeNode::load(node, load_opts); // Error ignored
+    node.borrow()
+        .subnodes
+        .iter()
+        .filter(|sn| {
+            let sn = sn.borrow();
+            let is_dir = sn.sys_node.typ == NodeType::Dir;
+            (is_dir || !opts.dirs_only) && opts.filter.accepts(&sn.sys_node.name, is_dir)
+        })
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, name: &str) -> bool {
+        Pattern::new(pattern).matches(name)
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(matches("*.rs", "main.rs"));
+        assert!(matches("*.rs", ".rs"));
+        assert!(!matches("*.rs", "main.rs.orig"));
+        assert!(matches("*", ""));
+        assert!(matches("a*b*c", "abbbc"));
+        assert!(!matches("a*b*c", "acb"));
+        assert!(matches("?.txt", "é.txt"));
+        assert!(!matches("?.txt", ".txt"));
+        assert!(!matches("?", "ab"));
+        assert!(matches("Makefile", "Makefile"));
+        assert!(!matches("makefile", "Makefile"));
+    }
+
+    #[test]
+    fn classes() {
+        assert!(matches("[abc].o", "b.o"));
+        assert!(!matches("[abc].o", "d.o"));
+        assert!(matches("file[0-9]", "file7"));
+        assert!(!matches("file[0-9]", "filex"));
+        assert!(matches("[a-cx-z]", "y"));
+        assert!(matches("[!0-9]*", "x1"));
+        assert!(!matches("[^0-9]*", "1x"));
+        // a ']' first is part of the class, a '-' last is literal
+        assert!(matches("[]]", "]"));
+        assert!(matches("[!]]", "a"));
+        assert!(matches("[a-]", "-"));
+        // without a closing bracket, '[' is just a character
+        assert!(matches("[ab", "[ab"));
+        assert!(!matches("[ab", "a"));
+        assert!(!matches("[ab]", ""));
+        assert!(!matches("x[!a]", "x"));
+    }
+
+    #[test]
+    fn filters() {
+        let filter = Filter {
+            include: vec![Pattern::new("*.txt"), Pattern::new("*.md")],
+            exclude: vec![Pattern::new("target"), Pattern::new(".*")],
+        };
+        assert!(filter.accepts(OsStr::new("a.txt"), false));
+        assert!(filter.accepts(OsStr::new("README.md"), false));
+        assert!(!filter.accepts(OsStr::new("main.rs"), false));
+        // directories are walked to find the files
+        assert!(filter.accepts(OsStr::new("src"), true));
+        assert!(!filter.accepts(OsStr::new("target"), true));
+        assert!(!filter.accepts(OsStr::new(".git"), true));
+        assert!(!filter.accepts(OsStr::new(".notes.txt"), false));
+        assert!(Filter::default().accepts(OsStr::new("anything"), false));
+    }
+}
@@ -0,0 +1,105 @@
+use std::ffi::CString;
+use std::fs::{self, Permissions};
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{symlink, MetadataExt, PermissionsExt};
+use std::path::Path;
+
+/// Copies a file, symlink or directory with everything inside. Symlinks are
+/// copied as links, permissions are kept. FIFOs, sockets and devices are
+/// created anew with `mknod`, like `cp -a`. Nothing may be at `to` yet.
+pub fn copy_all(from: &Path, to: &Path) -> io::Result<()> {
+    if exists(to) {
+        return Err(io::ErrorKind::AlreadyExists.into());
+    }
+    let md = fs::symlink_metadata(from)?;
+    let ft = md.file_type();
+    if ft.is_symlink() {
+        symlink(fs::read_link(from)?, to)
+    } else if ft.is_dir() {
+        fs::create_dir(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            copy_all(&entry.path(), &to.join(entry.file_name()))?;
+        }
+        fs::set_permissions(to, Permissions::from_mode(md.permissions().mode()))
+    } else if ft.is_file() {
+        fs::copy(from, to).map(|_| ())
+    } else {
+        make_node(to, md.mode(), md.rdev())
+    }
+}
+
+fn make_node(path: &Path, mode: u32, rdev: u64) -> io::Result<()> {
+    let cpath = CString::new(path.as_os_str().as_bytes())?;
+    if unsafe { libc::mknod(cpath.as_ptr(), mode as libc::mode_t, rdev as libc::dev_t) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Moves an entry, also to another file system: then it is copied and the
+/// original removed; if the copy fails, what was copied so far is removed
+/// and the original left alone. Unlike `rename`, never replaces what is at
+/// `to`.
+pub fn move_entry(from: &Path, to: &Path) -> io::Result<()> {
+    if exists(to) {
+        return Err(io::ErrorKind::AlreadyExists.into());
+    }
+    match fs::rename(from, to) {
+        Err(err) if err.raw_os_error() == Some(libc::EXDEV) => {
+            if let Err(err) = copy_all(from, to) {
+                if exists(to) {
+                    let _ = remove_all(to);
+                }
+                return Err(err);
+            }
+            remove_all(from)
+        }
+        result => result,
+    }
+}
+
+/// Removes a file, or a directory with everything inside.
+pub fn remove_all(path: &Path) -> io::Result<()> {
+    match fs::symlink_metadata(path)?.is_dir() {
+        true => fs::remove_dir_all(path),
+        false => fs::remove_file(path),
+    }
+}
+
+/// Whether anything is at `path`, also a dangling symlink.
+pub fn exists(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::FileTypeExt;
+    use std::process;
+
+    #[test]
+    fn special_files_are_recreated() {
+        let top = std::env::temp_dir().join(format!("navigator-copy-{}", process::id()));
+        let from = top.join("from");
+        fs::create_dir_all(&from).unwrap();
+        make_node(&from.join("fifo"), libc::S_IFIFO | 0o640, 0).unwrap();
+        fs::write(from.join("file"), b"data").unwrap();
+        symlink("file", from.join("link")).unwrap();
+
+        let to = top.join("to");
+        copy_all(&from, &to).unwrap();
+        let md = fs::symlink_metadata(to.join("fifo")).unwrap();
+        assert!(md.file_type().is_fifo());
+        assert_eq!(md.permissions().mode() & 0o777, 0o640);
+        assert_eq!(fs::read(to.join("file")).unwrap(), b"data");
+        assert_eq!(fs::read_link(to.join("link")).unwrap(), Path::new("file"));
+        assert_eq!(
+            copy_all(&from, &to).unwrap_err().kind(),
+            io::ErrorKind::AlreadyExists
+        );
+
+        fs::remove_dir_all(&top).unwrap();
+    }
+}
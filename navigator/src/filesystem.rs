@@ -1,14 +1,22 @@
 use std::ffi::{OsStr, OsString};
-use std::fs::DirEntry;
-use std::os::unix::fs::MetadataExt;
+use std::fmt::Write;
+use std::fs::{self, DirEntry, Metadata};
+use std::io;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::path::{Path, PathBuf};
 use std::time::{SystemTime, UNIX_EPOCH};
-use users::get_user_by_uid;
+use users::{get_group_by_gid, get_user_by_uid};
 
-#[derive(PartialEq)]
+#[derive(Copy, Clone, PartialEq)]
 pub enum NodeType {
     File,
     Dir,
     SymLink,
+    BlockDevice,
+    CharDevice,
+    Fifo,
+    Socket,
     //UpDir,
 }
 
@@ -19,47 +27,95 @@ impl NodeType {
             NodeType::Dir => 1,
             NodeType::File => 2,
             NodeType::SymLink => 2,
+            NodeType::BlockDevice => 2,
+            NodeType::CharDevice => 2,
+            NodeType::Fifo => 2,
+            NodeType::Socket => 2,
         }
     }
+
+    pub fn from(ft: std::fs::FileType) -> NodeType {
+        if ft.is_dir() {
+            NodeType::Dir
+        } else if ft.is_symlink() {
+            NodeType::SymLink
+        } else if ft.is_block_device() {
+            NodeType::BlockDevice
+        } else if ft.is_char_device() {
+            NodeType::CharDevice
+        } else if ft.is_fifo() {
+            NodeType::Fifo
+        } else if ft.is_socket() {
+            NodeType::Socket
+        } else {
+            NodeType::File
+        }
+    }
+
+    pub fn is_device(&self) -> bool {
+        matches!(self, NodeType::BlockDevice | NodeType::CharDevice)
+    }
 }
 
+#[derive(Clone)]
 pub struct SysNode {
     pub name: OsString,
     pub typ: NodeType,
     pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
     pub user: OsString,
     pub group: OsString,
     pub size: u64,
     pub modified: chrono::DateTime<chrono::Local>,
+    pub accessed: chrono::DateTime<chrono::Local>,
+    pub changed: chrono::DateTime<chrono::Local>,
+    pub inode: u64,
+    pub nlink: u64,
+    /// Device number, for block and character devices.
+    pub rdev: u64,
 }
 
 // TODO: zrobić to ładniej, bo jest łopatologicznie?
 // unwrap_or_default - sprawdzić to
 impl SysNode {
     pub fn from(entry: &DirEntry) -> Self {
-        let name = entry.file_name();
+        SysNode::with_metadata(entry.file_name(), entry.metadata())
+    }
+
+    /// Reads the node at `path` again, e.g. after its attributes changed.
+    /// Symlinks are not followed.
+    pub fn from_path(path: &Path) -> Self {
+        let name = path.file_name().unwrap_or(path.as_os_str()).to_os_string();
+        SysNode::with_metadata(name, fs::symlink_metadata(path))
+    }
+
+    pub fn with_metadata(name: OsString, metadata: io::Result<Metadata>) -> Self {
         let mut typ = NodeType::File;
         let mut mode = 0;
+        let mut uid = 0;
+        let mut gid = 0;
         let mut user = OsString::from("");
         let mut group = OsString::from("");
         let mut size: u64 = 0;
         let mut modified: chrono::DateTime<chrono::Local> = SystemTime::now().into();
+        let mut accessed = modified;
+        let mut changed = modified;
+        let mut inode = 0;
+        let mut nlink = 0;
+        let mut rdev = 0;
 
-        if let Ok(md) = entry.metadata() {
-            typ = if md.is_dir() {
-                NodeType::Dir
-            } else if md.is_symlink() {
-                NodeType::SymLink
-            } else {
-                NodeType::File
-            };
+        if let Ok(md) = metadata {
+            typ = NodeType::from(md.file_type());
             mode = md.mode();
             size = md.len();
+            uid = md.uid();
+            gid = md.gid();
             user = match get_user_by_uid(md.uid()) {
                 Some(usr) => usr.name().to_os_string(),
                 None => md.uid().to_string().into(),
             };
-            group = match get_user_by_uid(md.gid()) {
+            group = match get_group_by_gid(md.gid()) {
                 Some(grp) => grp.name().to_os_string(),
                 None => md.gid().to_string().into(),
             };
@@ -68,16 +124,30 @@ impl SysNode {
                 let modified_time = SystemTime::UNIX_EPOCH + m;
                 modified = modified_time.into();
             };
+            if let Ok(a) = md.accessed() {
+                accessed = a.into();
+            }
+            changed = unix_time_to_local(md.ctime(), md.ctime_nsec());
+            inode = md.ino();
+            nlink = md.nlink();
+            rdev = md.rdev();
         }
 
         Self {
             name,
             typ,
             mode,
+            uid,
+            gid,
             user,
             group,
             size,
             modified,
+            accessed,
+            changed,
+            inode,
+            nlink,
+            rdev,
         }
     }
 
@@ -86,10 +156,17 @@ impl SysNode {
             name: name.to_os_string(),
             typ,
             mode: 0,
+            uid: 0,
+            gid: 0,
             user: OsString::from(""),
             group: OsString::from(""),
             size: 0,
             modified: SystemTime::now().into(),
+            accessed: SystemTime::now().into(),
+            changed: SystemTime::now().into(),
+            inode: 0,
+            nlink: 0,
+            rdev: 0,
         }
     }
 }
@@ -99,25 +176,166 @@ pub fn file_type_to_str(typ: &NodeType) -> &str {
         NodeType::File => "-",
         NodeType::Dir => "d",
         NodeType::SymLink => "l",
+        NodeType::BlockDevice => "b",
+        NodeType::CharDevice => "c",
+        NodeType::Fifo => "p",
+        NodeType::Socket => "s",
         //NodeType::UpDir => "d",
     }
 }
 
+/// Formats permissions like `ls -l`. The execute position shows setuid,
+/// setgid and sticky bits: `s`/`t` with, `S`/`T` without execute permission.
 pub fn permissions_to_str(mode: u32) -> String {
+    let exec = |x: u32, special: u32, set: char| match (mode & x != 0, mode & special != 0) {
+        (true, true) => set,
+        (false, true) => set.to_ascii_uppercase(),
+        (true, false) => 'x',
+        (false, false) => '-',
+    };
     format!(
         "{}{}{}{}{}{}{}{}{}",
         if mode & 0o400 != 0 { 'r' } else { '-' },
         if mode & 0o200 != 0 { 'w' } else { '-' },
-        if mode & 0o100 != 0 { 'x' } else { '-' },
+        exec(0o100, 0o4000, 's'),
         if mode & 0o040 != 0 { 'r' } else { '-' },
         if mode & 0o020 != 0 { 'w' } else { '-' },
-        if mode & 0o010 != 0 { 'x' } else { '-' },
+        exec(0o010, 0o2000, 's'),
         if mode & 0o004 != 0 { 'r' } else { '-' },
         if mode & 0o002 != 0 { 'w' } else { '-' },
-        if mode & 0o001 != 0 { 'x' } else { '-' },
+        exec(0o001, 0o1000, 't'),
     )
 }
 
+/// Splits a device number into major and minor.
+pub fn major_minor(rdev: u64) -> (u64, u64) {
+    let rdev = rdev as libc::dev_t;
+    (libc::major(rdev) as u64, libc::minor(rdev) as u64)
+}
+
+pub fn unix_time_to_local(secs: i64, nsecs: i64) -> chrono::DateTime<chrono::Local> {
+    match chrono::DateTime::from_timestamp(secs, nsecs as u32) {
+        Some(t) => t.into(),
+        None => SystemTime::now().into(),
+    }
+}
+
+/// Makes a file name or path printable, like `ls -b`: bytes which are not
+/// valid UTF-8 and control characters are shown as octal escapes (`\377`),
+/// backslashes are doubled.
+pub fn escape_name(name: &OsStr) -> String {
+    let mut result = String::new();
+    for chunk in name.as_bytes().utf8_chunks() {
+        for ch in chunk.valid().chars() {
+            match ch {
+                '\\' => result.push_str("\\\\"),
+                c if c.is_control() => {
+                    let mut buf = [0; 4];
+                    for b in c.encode_utf8(&mut buf).bytes() {
+                        let _ = write!(result, "\\{:03o}", b);
+                    }
+                }
+                c => result.push(c),
+            }
+        }
+        for b in chunk.invalid() {
+            let _ = write!(result, "\\{:03o}", b);
+        }
+    }
+    result
+}
+
+/// Percent-encodes a path like in a URL, e.g. for the trash info files.
+pub fn percent_encode(path: &Path) -> String {
+    let mut result = String::new();
+    for b in path.as_os_str().as_bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                result.push(*b as char)
+            }
+            _ => result.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    result
+}
+
+/// Decodes `%XX` escapes; other text is kept as it is.
+pub fn percent_decode(s: &str) -> PathBuf {
+    let bytes = s.as_bytes();
+    let mut result = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .filter(|h| h.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|h| u8::from_str_radix(std::str::from_utf8(h).ok()?, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(b)) => {
+                result.push(b);
+                i += 3;
+            }
+            (b, _) => {
+                result.push(b);
+                i += 1;
+            }
+        }
+    }
+    PathBuf::from(OsString::from_vec(result))
+}
+
 pub fn datetime_to_str(datetime: chrono::DateTime<chrono::Local>) -> String {
     datetime.format("%b %d %H:%M").to_string()
 }
+
+/// Formats a size with binary units, e.g. `1.5 MiB`.
+pub fn human_size(size: u64) -> String {
+    const UNITS: [&str; 6] = ["KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+    if size < 1024 {
+        return format!("{} B", size);
+    }
+    let mut value = size as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_large_device_numbers() {
+        assert_eq!(major_minor(0x0803), (8, 3));
+        assert_eq!(major_minor(0x0001_2000_6783_459a), (0x12345, 0x6789a));
+        assert_eq!(major_minor(libc::makedev(4096, 256)), (4096, 256));
+    }
+
+    #[test]
+    fn escapes_names() {
+        let name = OsStr::from_bytes(b"a\tb\nc\\d\x01\xffe\xc3\xa9");
+        assert_eq!(escape_name(name), "a\\011b\\012c\\\\d\\001\\377e\u{e9}");
+        // C1 controls are escaped byte by byte
+        assert_eq!(escape_name(OsStr::new("x\u{85}")), "x\\302\\205");
+        assert_eq!(escape_name(OsStr::new("100% plain")), "100% plain");
+    }
+
+    #[test]
+    fn percent_encoding_round_trips() {
+        let path = Path::new(OsStr::from_bytes(b"/tmp/a b%20\t\n\x01\xff/\xc3\xa9~.-_"));
+        let encoded = percent_encode(path);
+        assert_eq!(encoded, "/tmp/a%20b%2520%09%0A%01%FF/%C3%A9~.-_");
+        assert!(encoded.bytes().all(|b| b.is_ascii_graphic()));
+        assert_eq!(percent_decode(&encoded), path);
+    }
+
+    #[test]
+    fn percent_decode_keeps_bad_escapes() {
+        assert_eq!(percent_decode("100%"), Path::new("100%"));
+        assert_eq!(percent_decode("%zz%4"), Path::new("%zz%4"));
+        assert_eq!(percent_decode("%+1%41"), Path::new("%+1A"));
+        assert_eq!(percent_decode("%e9"), Path::new(OsStr::from_bytes(b"\xe9")));
+    }
+}
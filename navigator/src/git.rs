@@ -0,0 +1,265 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::thread;
+
+use git2::{Repository, Status, StatusOptions};
+
+/// Keeps the status of the repository the current directory is in. The
+/// status is read in a background thread, as it can take long in large
+/// repositories, when the current directory leaves the repository and after
+/// `invalidate`.
+pub struct GitState {
+    enabled: bool,
+    repo: Option<GitRepo>,
+    // the directory the status was last asked for
+    checked: Option<PathBuf>,
+    stale: bool,
+    requests: Option<Sender<PathBuf>>,
+    // answers tell how many requests they answer
+    results: Option<Receiver<(PathBuf, Option<GitRepo>, usize)>>,
+    pending: usize,
+}
+
+impl GitState {
+    pub fn new(enabled: bool) -> GitState {
+        GitState {
+            enabled,
+            repo: None,
+            checked: None,
+            stale: false,
+            requests: None,
+            results: None,
+            pending: 0,
+        }
+    }
+
+    pub fn repo(&self) -> Option<&GitRepo> {
+        self.repo.as_ref()
+    }
+
+    /// Whether a status is being read.
+    pub fn busy(&self) -> bool {
+        self.pending > 0
+    }
+
+    /// Has the status read again on the next `update`, e.g. after files
+    /// were changed.
+    pub fn invalidate(&mut self) {
+        self.stale = true;
+    }
+
+    fn start_worker(&mut self) {
+        let (req_tx, req_rx) = channel::<PathBuf>();
+        let (res_tx, res_rx) = channel();
+        thread::spawn(move || {
+            while let Ok(mut path) = req_rx.recv() {
+                // only the last of the queued requests matters
+                let mut count = 1;
+                for next in req_rx.try_iter() {
+                    path = next;
+                    count += 1;
+                }
+                let repo = GitRepo::discover(&path);
+                if res_tx.send((path, repo, count)).is_err() {
+                    break;
+                }
+            }
+        });
+        self.requests = Some(req_tx);
+        self.results = Some(res_rx);
+    }
+
+    /// Picks up a finished status, and asks for a new one if `path` left the
+    /// repository or the status is stale. Returns whether the status changed.
+    pub fn update(&mut self, path: &Path) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        let mut changed = false;
+        while let Some(results) = &self.results {
+            match results.try_recv() {
+                Ok((checked, repo, count)) => {
+                    self.pending -= count;
+                    // answers for directories since left are dropped
+                    if self.checked.as_deref() == Some(&checked) {
+                        changed |= self.repo.is_some() || repo.is_some();
+                        self.repo = repo;
+                    }
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.requests = None;
+                    self.results = None;
+                    self.pending = 0;
+                }
+            }
+        }
+
+        let known = match &self.repo {
+            Some(repo) => path.starts_with(&repo.workdir),
+            None => self.checked.as_deref() == Some(path),
+        };
+        if known && !self.stale {
+            return changed;
+        }
+        if self.requests.is_none() {
+            self.start_worker();
+        }
+        if let Some(requests) = &self.requests {
+            if requests.send(path.to_path_buf()).is_ok() {
+                self.pending += 1;
+                self.checked = Some(path.to_path_buf());
+                self.stale = false;
+            }
+        }
+        changed
+    }
+}
+
+/// The status of a git repository: the branch and every path which is not
+/// clean, read from the index and the working tree.
+pub struct GitRepo {
+    workdir: PathBuf,
+    pub branch: String,
+    files: HashMap<PathBuf, Status>,
+    // changes inside each directory, merged
+    dirs: HashMap<PathBuf, Status>,
+}
+
+impl GitRepo {
+    /// Finds the repository containing `path` and reads its status. Bare
+    /// repositories have no files to decorate.
+    pub fn discover(path: &Path) -> Option<GitRepo> {
+        let repo = Repository::discover(path).ok()?;
+        let workdir = repo.workdir()?.to_path_buf();
+
+        let mut opts = StatusOptions::new();
+        opts.include_untracked(true)
+            .include_ignored(true)
+            .recurse_untracked_dirs(false)
+            .recurse_ignored_dirs(false)
+            .renames_head_to_index(true);
+        let statuses = repo.statuses(Some(&mut opts)).ok()?;
+
+        let mut files = HashMap::new();
+        let mut dirs: HashMap<PathBuf, Status> = HashMap::new();
+        for entry in statuses.iter() {
+            let Some(rel) = entry.path() else { continue };
+            // untracked and ignored directories are listed as `dir/`
+            let path = workdir.join(rel.trim_end_matches('/'));
+            let status = entry.status();
+            if !status.is_ignored() {
+                for dir in path.ancestors().skip(1) {
+                    if dir.starts_with(&workdir) {
+                        *dirs.entry(dir.to_path_buf()).or_insert(Status::empty()) |= status;
+                    }
+                    if dir == workdir {
+                        break;
+                    }
+                }
+            }
+            files.insert(path, status);
+        }
+
+        Some(GitRepo {
+            workdir,
+            branch: branch_name(&repo),
+            files,
+            dirs,
+        })
+    }
+
+    /// The status of a file, like `git status --short` shows it: index and
+    /// working tree state, e.g. `M `, ` M`, `??` or `UU`. Empty if clean.
+    pub fn file_code(&self, path: &Path) -> String {
+        if let Some(status) = self.files.get(path) {
+            return status_code(*status);
+        }
+        // inside an untracked or ignored directory, which is listed alone
+        for dir in path
+            .ancestors()
+            .skip(1)
+            .take_while(|dir| *dir != self.workdir)
+        {
+            match self.files.get(dir) {
+                Some(status) if status.is_wt_new() || status.is_ignored() => {
+                    return status_code(*status)
+                }
+                _ => {}
+            }
+        }
+        String::new()
+    }
+
+    /// The marker of a directory in the tree: `!` if it contains conflicts,
+    /// `*` other changes, or is itself untracked.
+    pub fn dir_marker(&self, path: &Path) -> Option<char> {
+        let inside = self.dirs.get(path).copied().unwrap_or(Status::empty());
+        let own = self.files.get(path).copied().unwrap_or(Status::empty());
+        let status = (inside | own) - Status::IGNORED;
+        if status.is_conflicted() {
+            Some('!')
+        } else if !status.is_empty() {
+            Some('*')
+        } else {
+            None
+        }
+    }
+}
+
+fn status_code(status: Status) -> String {
+    if status.is_conflicted() {
+        return "UU".to_owned();
+    }
+    if status.is_wt_new() {
+        return "??".to_owned();
+    }
+    if status.is_ignored() {
+        return "!!".to_owned();
+    }
+    let index = if status.is_index_new() {
+        'A'
+    } else if status.is_index_modified() {
+        'M'
+    } else if status.is_index_deleted() {
+        'D'
+    } else if status.is_index_renamed() {
+        'R'
+    } else if status.is_index_typechange() {
+        'T'
+    } else {
+        ' '
+    };
+    let worktree = if status.is_wt_modified() {
+        'M'
+    } else if status.is_wt_deleted() {
+        'D'
+    } else if status.is_wt_renamed() {
+        'R'
+    } else if status.is_wt_typechange() {
+        'T'
+    } else {
+        ' '
+    };
+    format!("{}{}", index, worktree)
+}
+
+/// The checked out branch, or the commit if the head is detached.
+fn branch_name(repo: &Repository) -> String {
+    match repo.head() {
+        Ok(head) if head.is_branch() => head.shorthand().unwrap_or("?").to_owned(),
+        Ok(head) => match head.target() {
+            Some(oid) => format!("detached at {:.7}", oid),
+            None => "detached".to_owned(),
+        },
+        // a new repository, the branch has no commits yet
+        Err(_) => {
+            let head = repo.find_reference("HEAD").ok();
+            match head.as_ref().and_then(|r| r.symbolic_target()) {
+                Some(target) => target.trim_start_matches("refs/heads/").to_owned(),
+                None => "?".to_owned(),
+            }
+        }
+    }
+}
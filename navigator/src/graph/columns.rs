@@ -0,0 +1,196 @@
+use super::display::str_width;
+use crate::dir_size::DirSize;
+use crate::filesystem::*;
+use crate::git::GitRepo;
+use crate::tree_node::*;
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Column {
+    Type,
+    Permissions,
+    Owner,
+    Group,
+    Size,
+    Modified,
+    Accessed,
+    Changed,
+    Inode,
+    Links,
+    /// Git status, only shown inside a repository.
+    Git,
+    Name,
+}
+
+impl Column {
+    pub fn parse(s: &str) -> Option<Column> {
+        match s {
+            "type" => Some(Column::Type),
+            "perms" | "permissions" => Some(Column::Permissions),
+            "owner" | "user" => Some(Column::Owner),
+            "group" => Some(Column::Group),
+            "size" => Some(Column::Size),
+            "mtime" => Some(Column::Modified),
+            "atime" => Some(Column::Accessed),
+            "ctime" => Some(Column::Changed),
+            "inode" => Some(Column::Inode),
+            "links" | "nlink" => Some(Column::Links),
+            "git" => Some(Column::Git),
+            "name" => Some(Column::Name),
+            _ => None,
+        }
+    }
+
+    /// Parses a comma separated column list, e.g. `type,perms,size,name`.
+    pub fn parse_list(s: &str) -> Result<Vec<Column>, String> {
+        s.split(',')
+            .map(|c| Column::parse(c.trim()).ok_or(format!("unknown column '{}'", c.trim())))
+            .collect()
+    }
+
+    /// The `ls -l` like default.
+    pub fn defaults() -> Vec<Column> {
+        vec![
+            Column::Type,
+            Column::Permissions,
+            Column::Owner,
+            Column::Group,
+            Column::Size,
+            Column::Modified,
+            Column::Git,
+            Column::Name,
+        ]
+    }
+
+    fn right_aligned(&self) -> bool {
+        matches!(self, Column::Size | Column::Inode | Column::Links)
+    }
+}
+
+/// Lays out the list pane: which columns are shown, in which order, and how
+/// wide they are.
+pub struct ColumnLayout {
+    columns: Vec<Column>,
+    pub human_sizes: bool,
+}
+
+/// A formatted row and the position of the name in it.
+pub struct Row {
+    pub text: String,
+    pub name_x1: usize,
+    pub name_x2: usize,
+}
+
+impl ColumnLayout {
+    pub fn new(columns: &[Column], human_sizes: bool) -> ColumnLayout {
+        let mut columns = columns.to_vec();
+        // the name is what the cursor highlights, it can't be left out
+        if !columns.contains(&Column::Name) {
+            columns.push(Column::Name);
+        }
+        ColumnLayout {
+            columns,
+            human_sizes,
+        }
+    }
+
+    fn cell(&self, col: Column, n: &TreeNode, spinner: char, git: Option<&GitRepo>) -> String {
+        let sn = &n.sys_node;
+        let size = |size: u64| match self.human_sizes {
+            true => human_size(size),
+            false => size.to_string(),
+        };
+        match col {
+            Column::Type => file_type_to_str(&sn.typ).to_owned(),
+            Column::Permissions => permissions_to_str(sn.mode),
+            Column::Owner => escape_name(&sn.user),
+            Column::Group => escape_name(&sn.group),
+            Column::Size if sn.typ.is_device() => {
+                let (major, minor) = major_minor(sn.rdev);
+                format!("{}, {}", major, minor)
+            }
+            Column::Size => match n.dir_size {
+                DirSize::Done(s) => size(s),
+                DirSize::Computing => spinner.to_string(),
+                DirSize::Failed => "?".to_owned(),
+                DirSize::Unknown => size(sn.size),
+            },
+            Column::Modified => datetime_to_str(sn.modified),
+            Column::Accessed => datetime_to_str(sn.accessed),
+            Column::Changed => datetime_to_str(sn.changed),
+            Column::Inode => sn.inode.to_string(),
+            Column::Links => sn.nlink.to_string(),
+            Column::Git => git.map_or(String::new(), |git| git.file_code(&n.get_path())),
+            Column::Name => escape_name(&sn.name),
+        }
+    }
+
+    /// Formats the nodes into rows, each column as wide as its widest cell.
+    /// The git column is left out outside of repositories.
+    pub fn rows(&self, nodes: &[TreeNodeRef], spinner: char, git: Option<&GitRepo>) -> Vec<Row> {
+        let columns: Vec<Column> = self
+            .columns
+            .iter()
+            .filter(|col| **col != Column::Git || git.is_some())
+            .copied()
+            .collect();
+        let cells: Vec<Vec<String>> = nodes
+            .iter()
+            .map(|node| {
+                let n = node.borrow();
+                columns
+                    .iter()
+                    .map(|col| self.cell(*col, &n, spinner, git))
+                    .collect()
+            })
+            .collect();
+
+        let mut widths = vec![0; columns.len()];
+        for row in &cells {
+            for (i, cell) in row.iter().enumerate() {
+                widths[i] = widths[i].max(str_width(cell));
+            }
+        }
+
+        let last = columns.len() - 1;
+        cells
+            .into_iter()
+            .map(|row| {
+                let mut text = String::new();
+                let mut len = 0;
+                let (mut name_x1, mut name_x2) = (0, 0);
+                for (i, cell) in row.into_iter().enumerate() {
+                    let col = columns[i];
+                    let cell_len = str_width(&cell);
+                    let pad = widths[i] - cell_len;
+                    if col.right_aligned() {
+                        text.extend(std::iter::repeat_n(' ', pad));
+                        len += pad;
+                    }
+                    if col == Column::Name {
+                        name_x1 = len;
+                        name_x2 = len + cell_len;
+                    }
+                    text += &cell;
+                    len += cell_len;
+                    if i == last {
+                        break;
+                    }
+                    if !col.right_aligned() {
+                        text.extend(std::iter::repeat_n(' ', pad));
+                        len += pad;
+                    }
+                    // type and permissions are shown together, like in `ls -l`
+                    if !(col == Column::Type && columns[i + 1] == Column::Permissions) {
+                        text.push(' ');
+                        len += 1;
+                    }
+                }
+                Row {
+                    text,
+                    name_x1,
+                    name_x2,
+                }
+            })
+            .collect()
+    }
+}
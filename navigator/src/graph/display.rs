@@ -1,9 +1,12 @@
 use std::{cell::RefCell, rc::Rc};
 
 use crate::common::*;
+use crate::keymap::*;
 use crate::tree_node::*;
 
 use ncurses::*;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 
 #[derive(Default)]
 pub struct DisplInfo {
@@ -14,11 +17,13 @@ pub struct DisplInfo {
 }
 
 /* #region ViewLine */
+/// A line of a view. `x1..x2` is the highlighted part (the node name), in
+/// terminal columns, shown with `node_attrs` (color and e.g. bold).
 pub struct ViewLine {
     pub content: String,
     pub x1: i32,
     pub x2: i32,
-    pub node_color: Option<i16>,
+    pub node_attrs: Option<attr_t>,
     pub src_node: TreeNodeRef,
 }
 
@@ -27,14 +32,14 @@ impl ViewLine {
         content: &str,
         x1: i32,
         x2: i32,
-        node_color: Option<i16>,
+        node_attrs: Option<attr_t>,
         src_node: &TreeNodeRef,
     ) -> ViewLine {
         ViewLine {
             content: content.to_owned(),
             x1,
             x2,
-            node_color,
+            node_attrs,
             src_node: src_node.clone(),
         }
     }
@@ -44,7 +49,15 @@ impl ViewLine {
 pub trait DisplContent {
     fn prepare(&mut self, info: &mut DisplInfo) -> Result<(), AppError>;
     fn get_line(&self, y: usize) -> Result<&ViewLine, AppError>;
-    fn process_key(&mut self, key: i32) -> Result<(), AppError>;
+    /// Which key bindings apply, besides the global ones.
+    fn context(&self) -> Context;
+    /// Performs an action `count` times; `page` is the number of lines a page
+    /// movement goes by.
+    fn process_action(&mut self, action: Action, count: usize, page: usize)
+        -> Result<(), AppError>;
+    /// Moves the cursor to the clicked line; a double click also enters or
+    /// expands it.
+    fn click(&mut self, y: usize, double: bool) -> Result<(), AppError>;
     fn modified(&self) -> bool;
     fn reset_modified(&mut self);
 }
@@ -126,8 +139,49 @@ impl Display {
         Ok(())
     }
 
-    pub fn process_key(&self, key: i32) -> Result<(), AppError> {
-        self.content.borrow_mut().process_key(key)
+    pub fn context(&self) -> Context {
+        self.content.borrow().context()
+    }
+
+    /// Shows other content in the window, e.g. the trash instead of the list.
+    /// Returns the previous content.
+    pub fn set_content(
+        &mut self,
+        content: Rc<RefCell<dyn DisplContent>>,
+    ) -> Rc<RefCell<dyn DisplContent>> {
+        self.offset_y = 0;
+        std::mem::replace(&mut self.content, content)
+    }
+
+    pub fn process_action(&self, action: Action, count: usize) -> Result<(), AppError> {
+        // one line of the previous page stays visible
+        let page = (self.size.height - 1).max(1) as usize;
+        self.content
+            .borrow_mut()
+            .process_action(action, count, page)
+    }
+
+    /// Whether the screen position is inside this display.
+    pub fn encloses(&self, y: i32, x: i32) -> bool {
+        let (mut top, mut left) = (0, 0);
+        getbegyx(self.window, &mut top, &mut left);
+        y >= top && y < top + self.size.height && x >= left && x < left + self.size.width
+    }
+
+    /// Handles a click at screen row `y`.
+    pub fn click(&self, y: i32, double: bool) -> Result<(), AppError> {
+        let (mut top, mut left) = (0, 0);
+        getbegyx(self.window, &mut top, &mut left);
+        let line = y - top + self.offset_y;
+        if line < 0 {
+            return Ok(());
+        }
+        let mut content = self.content.borrow_mut();
+        // clicks below the last line are ignored
+        if content.get_line(line as usize).is_err() {
+            return Ok(());
+        }
+        content.click(line as usize, double)
     }
 
     fn print_line(
@@ -140,45 +194,137 @@ impl Display {
         container_active: bool,
     ) {
         let mut attributor =
-            Attributor::new(self.window, container_active, cursor, vline.node_color);
+            Attributor::new(self.window, container_active, cursor, vline.node_attrs);
         wmove(self.window, y, x);
 
-        for (i, ch) in vline
-            .content
-            .chars()
-            .enumerate()
-            .skip(offs as usize)
-            .take(self.size.width as usize)
-        {
-            if i >= vline.x2 as usize {
+        let pieces = visible_pieces(&vline.content, offs as usize, self.size.width as usize);
+        for (col, piece) in pieces {
+            if col >= vline.x2 as usize {
                 attributor.node_off();
-            } else if i >= vline.x1 as usize {
+            } else if col >= vline.x1 as usize {
                 attributor.node_on();
             }
-            let ch32 = match ch {
-                '├' => ACS_LTEE(),
-                '└' => ACS_LLCORNER(),
-                '│' => ACS_VLINE(),
-                _ => ch as u32,
-            };
-            waddch(self.window, ch32);
+            match piece {
+                Piece::Grapheme(g) => put_grapheme(self.window, g),
+                Piece::Blank(n) => {
+                    for _ in 0..n {
+                        waddch(self.window, ' ' as u32);
+                    }
+                }
+            }
         }
     }
 }
 /* #endregion */
 
+/// A part of a line shown in a window.
+#[derive(Debug, PartialEq)]
+enum Piece<'a> {
+    Grapheme(&'a str),
+    /// A wide character cut by the window edge, as spaces for its columns
+    /// inside the window.
+    Blank(usize),
+}
+
+/// The pieces of `line` shown from column `start` on in `width` columns,
+/// with the column of the line each one begins at.
+fn visible_pieces(line: &str, start: usize, width: usize) -> Vec<(usize, Piece<'_>)> {
+    let end = start + width;
+    let mut pieces = Vec::new();
+    let mut col = 0;
+    for g in line.graphemes(true) {
+        let w = grapheme_width(g);
+        if col + w <= start {
+            col += w;
+            continue;
+        }
+        if col >= end {
+            break;
+        }
+        let piece = match col < start || col + w > end {
+            true => Piece::Blank((col + w).min(end) - col.max(start)),
+            false => Piece::Grapheme(g),
+        };
+        pieces.push((col, piece));
+        col += w;
+    }
+    pieces
+}
+
+/// Finds the next line (the previous one if not `forward`) after `from` whose
+/// node name contains `pattern`, ignoring case. Wraps around the ends.
+pub fn find_line(lines: &[ViewLine], from: usize, pattern: &str, forward: bool) -> Option<usize> {
+    let pattern = pattern.to_lowercase();
+    let len = lines.len();
+    (1..=len)
+        .map(|i| match forward {
+            true => (from + i) % len,
+            false => (from + len - i) % len,
+        })
+        .find(|y| {
+            let name = lines[*y]
+                .src_node
+                .borrow()
+                .sys_node
+                .name
+                .to_string_lossy()
+                .to_lowercase();
+            name.contains(&pattern)
+        })
+}
+
 fn fit_str(x1: i32, x2: i32, width: i32) -> i32 {
     (x2 - width).clamp(0, x1)
 }
 
+/// Width of a grapheme in terminal columns. Control characters are shown as
+/// one column wide `?`.
+pub fn grapheme_width(g: &str) -> usize {
+    match g.chars().next() {
+        Some(c) if c.is_control() => 1,
+        _ => g.width(),
+    }
+}
+
+/// Width of a string in terminal columns.
+pub fn str_width(s: &str) -> usize {
+    s.graphemes(true).map(grapheme_width).sum()
+}
+
+fn put_grapheme(window: WINDOW, g: &str) {
+    match g {
+        "├" => waddch(window, ACS_LTEE()),
+        "└" => waddch(window, ACS_LLCORNER()),
+        "│" => waddch(window, ACS_VLINE()),
+        _ if g.starts_with(char::is_control) => waddch(window, '?' as u32),
+        _ => waddstr(window, g).unwrap_or(ERR),
+    };
+}
+
+/// Prints `text` cut or padded with spaces to exactly `width` columns.
+pub fn print_fitted(window: WINDOW, text: &str, width: usize) {
+    let mut col = 0;
+    for g in text.graphemes(true) {
+        let w = grapheme_width(g);
+        if col + w > width {
+            break;
+        }
+        put_grapheme(window, g);
+        col += w;
+    }
+    for _ in col..width {
+        waddch(window, ' ' as u32);
+    }
+}
+
 /* #region Attributor */
 
 pub struct Attributor {
     window: WINDOW,
     container_active: bool,
     at_cursor: bool,
-    node_color: Option<i16>,
-    current_color: Option<i16>,
+    node_attrs: Option<attr_t>,
+    current_attrs: Option<attr_t>,
     current_reverse: bool,
 }
 
@@ -187,14 +333,14 @@ impl Attributor {
         window: WINDOW,
         container_active: bool,
         at_cursor: bool,
-        node_color: Option<i16>,
+        node_attrs: Option<attr_t>,
     ) -> Attributor {
         Attributor {
             window,
             container_active,
             at_cursor,
-            node_color,
-            current_color: None,
+            node_attrs,
+            current_attrs: None,
             current_reverse: false,
         }
     }
@@ -203,14 +349,14 @@ impl Attributor {
         if self.container_active && self.at_cursor {
             self.set_curr_reverse();
         }
-        self.set_curr_color(self.node_color);
+        self.set_curr_attrs(self.node_attrs);
     }
 
     fn node_off(&mut self) {
         if self.container_active && self.at_cursor {
             self.reset_curr_reverse();
         }
-        self.reset_curr_color();
+        self.reset_curr_attrs();
     }
 
     fn set_curr_reverse(&mut self) {
@@ -227,29 +373,136 @@ impl Attributor {
         }
     }
 
-    fn set_curr_color(&mut self, color: Option<i16>) {
-        if color != self.current_color {
-            self.reset_curr_color();
-            if let Some(color) = color {
-                wattron(self.window, COLOR_PAIR(color));
+    fn set_curr_attrs(&mut self, attrs: Option<attr_t>) {
+        if attrs != self.current_attrs {
+            self.reset_curr_attrs();
+            if let Some(attrs) = attrs {
+                wattr_on(self.window, attrs);
             }
-            self.current_color = color;
+            self.current_attrs = attrs;
         }
     }
 
-    fn reset_curr_color(&mut self) {
-        if let Some(color) = self.current_color {
-            wattroff(self.window, COLOR_PAIR(color));
+    fn reset_curr_attrs(&mut self) {
+        if let Some(attrs) = self.current_attrs {
+            wattr_off(self.window, attrs);
+            // the attributes may include reverse, which the cursor still needs
+            if self.current_reverse {
+                wattr_on(self.window, A_REVERSE);
+            }
         }
-        self.current_color = None;
+        self.current_attrs = None;
     }
 }
 
 impl Drop for Attributor {
     fn drop(&mut self) {
-        self.reset_curr_color();
+        self.reset_curr_attrs();
         self.reset_curr_reverse();
     }
 }
 
 /* #endregion */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grapheme_widths() {
+        assert_eq!(grapheme_width("a"), 1);
+        assert_eq!(grapheme_width("日"), 2);
+        assert_eq!(grapheme_width("👍"), 2);
+        // combining accent, ZWJ family, flag: one grapheme each
+        assert_eq!(grapheme_width("e\u{301}"), 1);
+        assert_eq!(grapheme_width("👨\u{200d}👩\u{200d}👧"), 2);
+        assert_eq!(grapheme_width("🇵🇱"), 2);
+        // shown as `?`
+        assert_eq!(grapheme_width("\u{7}"), 1);
+    }
+
+    #[test]
+    fn string_widths() {
+        assert_eq!(str_width(""), 0);
+        assert_eq!(str_width("abc"), 3);
+        assert_eq!(str_width("日本語.txt"), 10);
+        assert_eq!(str_width("cafe\u{301}"), 4);
+        assert_eq!(str_width("a👨\u{200d}👩\u{200d}👧b"), 4);
+        assert_eq!(str_width("x\ty"), 3);
+    }
+
+    #[test]
+    fn pieces_fitting() {
+        let pieces = visible_pieces("a日e\u{301}", 0, 10);
+        assert_eq!(
+            pieces,
+            vec![
+                (0, Piece::Grapheme("a")),
+                (1, Piece::Grapheme("日")),
+                (3, Piece::Grapheme("e\u{301}")),
+            ]
+        );
+    }
+
+    #[test]
+    fn wide_glyph_cut_at_the_right_edge() {
+        // 日 takes columns 2 and 3, only 2 is inside
+        let pieces = visible_pieces("ab日c", 0, 3);
+        assert_eq!(
+            pieces,
+            vec![
+                (0, Piece::Grapheme("a")),
+                (1, Piece::Grapheme("b")),
+                (2, Piece::Blank(1))
+            ]
+        );
+        let pieces = visible_pieces("ab👍", 0, 4);
+        assert_eq!(pieces.last(), Some(&(2, Piece::Grapheme("👍"))));
+    }
+
+    #[test]
+    fn wide_glyph_cut_at_the_left_edge() {
+        let pieces = visible_pieces("a日本", 2, 10);
+        assert_eq!(
+            pieces,
+            vec![(1, Piece::Blank(1)), (3, Piece::Grapheme("本"))]
+        );
+        // scrolled past everything
+        assert!(visible_pieces("ab", 2, 5).is_empty());
+    }
+
+    #[test]
+    fn pieces_fill_the_window() {
+        for line in ["日本語のファイル名", "a日b本c語", "👍x👍y👍"] {
+            for start in 0..6 {
+                let width: usize = visible_pieces(line, start, 5)
+                    .iter()
+                    .map(|(_, p)| match p {
+                        Piece::Grapheme(g) => grapheme_width(g),
+                        Piece::Blank(n) => *n,
+                    })
+                    .sum();
+                assert_eq!(width, (str_width(line) - start).min(5));
+            }
+        }
+    }
+
+    #[test]
+    fn offsets_show_the_name() {
+        // the name (x1..x2) fits: no scrolling
+        assert_eq!(fit_str(10, 20, 40), 0);
+        // scrolled just enough to show the end of the name
+        assert_eq!(fit_str(10, 30, 25), 5);
+        // a name wider than the window is shown from its start
+        assert_eq!(fit_str(10, 60, 20), 10);
+        // wide glyphs: x1/x2 are columns, not bytes or chars
+        let line = "drwx 日本語ディレクトリ";
+        let x1 = str_width("drwx ") as i32;
+        let x2 = str_width(line) as i32;
+        let offs = fit_str(x1, x2, 20);
+        assert_eq!(offs, 3);
+        let pieces = visible_pieces(line, offs as usize, 20);
+        assert_eq!(pieces.first(), Some(&(3, Piece::Grapheme("x"))));
+        assert_eq!(pieces.last(), Some(&(21, Piece::Grapheme("リ"))));
+    }
+}
@@ -0,0 +1,37 @@
+use super::display::str_width;
+use super::popup::{show_popup, PopupLine};
+use crate::keymap::*;
+
+fn help_lines(keymap: &Keymap) -> Vec<PopupLine> {
+    let mut lines = Vec::new();
+    for section in keymap.help() {
+        if section.entries.is_empty() {
+            continue;
+        }
+        if !lines.is_empty() {
+            lines.push(PopupLine::Blank);
+        }
+        lines.push(PopupLine::Title(section.title.to_owned()));
+        let width = section
+            .entries
+            .iter()
+            .map(|e| str_width(&e.keys))
+            .max()
+            .unwrap_or(0);
+        for e in section.entries {
+            let pad = width - str_width(&e.keys);
+            lines.push(PopupLine::Text(format!(
+                "  {}{}  {}",
+                e.keys,
+                " ".repeat(pad),
+                e.description
+            )));
+        }
+    }
+    lines
+}
+
+/// Shows the key bindings of `keymap` in a popup window.
+pub fn show_help(keymap: &Keymap) {
+    show_popup("Key bindings", &help_lines(keymap));
+}
@@ -0,0 +1,40 @@
+use std::path::Path;
+
+use super::display::str_width;
+use super::popup::{show_popup, PopupLine};
+use crate::common::AppError;
+use crate::filesystem::escape_name;
+use crate::inspect::inspect;
+
+/// Shows the metadata of the file at `path` in a popup window.
+pub fn show_inspector(path: &Path) -> Result<(), AppError> {
+    let mut lines = Vec::new();
+    for section in inspect(path)? {
+        if !lines.is_empty() {
+            lines.push(PopupLine::Blank);
+        }
+        lines.push(PopupLine::Title(section.title.to_owned()));
+        let width = section
+            .fields
+            .iter()
+            .map(|f| str_width(&f.0))
+            .max()
+            .unwrap_or(0);
+        for (label, value) in section.fields {
+            let line = match width {
+                // fields without labels, e.g. ACL entries
+                0 => format!("  {}", value),
+                _ => format!(
+                    "  {}{}  {}",
+                    label,
+                    " ".repeat(width - str_width(&label)),
+                    value
+                ),
+            };
+            lines.push(PopupLine::Text(line));
+        }
+    }
+    let name = path.file_name().unwrap_or(path.as_os_str());
+    show_popup(&format!("Info: {}", escape_name(name)), &lines);
+    Ok(())
+}
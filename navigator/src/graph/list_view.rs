@@ -1,23 +1,27 @@
 use std::{cell::RefCell, rc::Rc};
 
+use super::columns::*;
 use super::display::*;
+use crate::colors::ColorScheme;
 use crate::common::*;
-use crate::filesystem::*;
+use crate::keymap::*;
 use crate::tree::*;
 
-use ncurses::*;
-
 pub struct ListView {
     tree: Rc<RefCell<Tree>>,
     lines: Vec<ViewLine>,
+    layout: ColumnLayout,
+    colors: Rc<ColorScheme>,
     pub modif_flags: ModifFlags,
 }
 
 impl ListView {
-    pub fn new(tree: Rc<RefCell<Tree>>) -> ListView {
+    pub fn new(tree: Rc<RefCell<Tree>>, layout: ColumnLayout, colors: Rc<ColorScheme>) -> ListView {
         ListView {
             tree,
             lines: Vec::new(),
+            layout,
+            colors,
             modif_flags: ModifFlags::new(),
         }
     }
@@ -25,42 +29,74 @@ impl ListView {
     fn list_curr_node(&mut self) {
         self.lines.clear();
         let cd = self.tree.borrow().curr_dir();
-        for node in &cd.borrow().subnodes {
-            let n = node.borrow();
-
-            let line_str = format!(
-                "{}{} {:>8} {:>8} {:>10} {} {}",
-                file_type_to_str(&n.sys_node.typ),
-                permissions_to_str(n.sys_node.mode),
-                n.sys_node.user.to_string_lossy().to_string(),
-                n.sys_node.group.to_string_lossy().to_string(),
-                n.sys_node.size,
-                datetime_to_str(n.sys_node.modified),
-                n.sys_node.name.to_string_lossy().to_string()
-            );
-
-            let ncolor = match n.sys_node.typ {
-                NodeType::File => {
-                    let exec = (n.sys_node.mode & 0o111) != 0;
-                    match exec {
-                        true => Some(AppColorTypes::Exec as i16),
-                        false => None,
-                    }
-                }
-                NodeType::Dir => Some(AppColorTypes::Dir as i16),
-                NodeType::SymLink => None,
+        let spinner = self.tree.borrow().size_jobs.spinner();
+        let nodes = cd.borrow().subnodes.clone();
+        let rows = self
+            .layout
+            .rows(&nodes, spinner, self.tree.borrow().git.repo());
+        // marks take a column only while there are any
+        let marks = nodes.iter().any(|n| n.borrow().marked);
+        for (node, row) in nodes.iter().zip(rows) {
+            let attrs = self.colors.attrs(&node.borrow().sys_node);
+            let (text, shift) = match (marks, node.borrow().marked) {
+                (false, _) => (row.text, 0),
+                (true, true) => (format!("* {}", row.text), 2),
+                (true, false) => (format!("  {}", row.text), 2),
             };
-
             self.lines.push(ViewLine::new(
-                &line_str,
-                53,
-                line_str.chars().count() as i32,
-                ncolor,
-                &node,
+                &text,
+                (row.name_x1 + shift) as i32,
+                (row.name_x2 + shift) as i32,
+                attrs,
+                node,
             ));
         }
     }
 
+    fn refresh_lines(&mut self) {
+        if self.modif_flags.render {
+            self.list_curr_node();
+            self.modif_flags.render = false;
+        }
+    }
+
+    fn goto_line(&mut self, y: usize) -> Result<(), AppError> {
+        let Some(line) = self.lines.get(y) else {
+            return Ok(());
+        };
+        let tree = self.tree.clone();
+        let dest = line.src_node.clone();
+        tree.borrow_mut().lv_goto(&dest, self)?;
+        Ok(())
+    }
+
+    /// Moves the cursor by `delta` lines, stopping at the first and last one.
+    fn move_by(&mut self, delta: i64) -> Result<(), AppError> {
+        self.refresh_lines();
+        if let Some(curs_y) = self.find_cursor() {
+            let last = self.lines.len() as i64 - 1;
+            self.goto_line((curs_y as i64 + delta).clamp(0, last) as usize)?;
+        }
+        Ok(())
+    }
+
+    fn search(&mut self, forward: bool) -> Result<(), AppError> {
+        self.refresh_lines();
+        let Some(pattern) = self.tree.borrow().search.clone() else {
+            return Ok(());
+        };
+        let Some(curs_y) = self.find_cursor() else {
+            return Ok(());
+        };
+        match find_line(&self.lines, curs_y as usize, &pattern, forward) {
+            Some(y) => self.goto_line(y)?,
+            None => {
+                self.tree.borrow_mut().status_msg = Some(format!("Pattern not found: {}", pattern))
+            }
+        }
+        Ok(())
+    }
+
     // TODO: to ma zwracać Option(i32 lub usize) i tegoż typu ma być DisplInfo::curs_line
     fn find_cursor(&self) -> Option<i32> {
         if let Some(cf) = self.tree.borrow().curr_file() {
@@ -86,10 +122,7 @@ impl DisplContent for ListView {
     }
 
     fn prepare(&mut self, info: &mut DisplInfo) -> Result<(), AppError> {
-        if self.modif_flags.render {
-            self.list_curr_node();
-            self.modif_flags.render = false;
-        }
+        self.refresh_lines();
         info.lines_count = self.lines.len() as i32;
         info.curs_line = self.find_cursor();
         match info.curs_line {
@@ -118,33 +151,65 @@ impl DisplContent for ListView {
         }
     }
 
-    fn process_key(&mut self, key: i32) -> Result<(), AppError> {
-        match key {
-            KEY_UP => {
-                if let Some(curs_y) = self.find_cursor() {
-                    if let Some(line) = self.lines.get((curs_y - 1) as usize) {
-                        let tree = self.tree.clone();
-                        let dest = line.src_node.clone();
-                        tree.borrow_mut().lv_goto(&dest, self)?;
-                    }
-                }
+    fn click(&mut self, y: usize, double: bool) -> Result<(), AppError> {
+        let Some(line) = self.lines.get(y) else {
+            return Ok(());
+        };
+        let tree = self.tree.clone();
+        let dest = line.src_node.clone();
+        tree.borrow_mut().lv_goto(&dest, self)?;
+        if double {
+            tree.borrow_mut().lv_enter(self)?;
+        }
+        Ok(())
+    }
+
+    fn context(&self) -> Context {
+        Context::List
+    }
+
+    fn process_action(
+        &mut self,
+        action: Action,
+        count: usize,
+        page: usize,
+    ) -> Result<(), AppError> {
+        let tree = self.tree.clone();
+        let count = count as i64;
+        match action {
+            Action::Up => self.move_by(-count)?,
+            Action::Down => self.move_by(count)?,
+            Action::PageUp => self.move_by(-count * page as i64)?,
+            Action::PageDown => self.move_by(count * page as i64)?,
+            Action::HalfPageUp => self.move_by(-count * page.div_ceil(2) as i64)?,
+            Action::HalfPageDown => self.move_by(count * page.div_ceil(2) as i64)?,
+            Action::Top => {
+                self.refresh_lines();
+                self.goto_line(0)?;
+            }
+            Action::Bottom => {
+                self.refresh_lines();
+                self.goto_line(self.lines.len().saturating_sub(1))?;
             }
-            KEY_DOWN => {
-                if let Some(curs_y) = self.find_cursor() {
-                    if let Some(line) = self.lines.get((curs_y + 1) as usize) {
-                        let tree = self.tree.clone();
-                        let dest = line.src_node.clone();
-                        tree.borrow_mut().lv_goto(&dest, self)?;
-                    }
+            Action::SearchNext | Action::SearchPrev => {
+                for _ in 0..count {
+                    self.search(action == Action::SearchNext)?;
                 }
             }
-            10 => {
-                let tree = self.tree.clone();
-                tree.borrow_mut().lv_enter(self)?;
+            Action::Enter => tree.borrow_mut().lv_enter(self)?,
+            Action::Parent => {
+                for _ in 0..count {
+                    tree.borrow_mut().lv_move_up(self)?;
+                }
             }
-            KEY_BACKSPACE => {
-                let tree = self.tree.clone();
-                tree.borrow_mut().lv_move_up(self)?;
+            Action::ComputeSize => tree.borrow_mut().lv_compute_size(self)?,
+            Action::ComputeAllSizes => tree.borrow_mut().lv_compute_all_sizes(self)?,
+            Action::MoveToTrash => tree.borrow_mut().lv_trash(self)?,
+            Action::ToggleMark => {
+                for _ in 0..count {
+                    tree.borrow_mut().lv_toggle_mark(self)?;
+                    self.move_by(1)?;
+                }
             }
             _ => {}
         };
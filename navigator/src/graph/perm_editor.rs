@@ -0,0 +1,388 @@
+use std::ffi::OsString;
+
+use ncurses::*;
+
+use super::display::print_fitted;
+use super::popup::pick;
+use crate::filesystem::*;
+use crate::permissions::*;
+use crate::screen::Screen;
+use crate::tree_node::TreeNodeRef;
+
+const CLASSES: [&str; 3] = ["owner", "group", "others"];
+const SPECIAL: [&str; 3] = ["setuid", "setgid", "sticky"];
+
+// columns of the read, write, exec and special bit boxes
+const BOX_X: [i32; 4] = [13, 19, 25, 32];
+const WIDTH: i32 = 52;
+const HEIGHT: i32 = 16;
+
+/// The permissions being edited. Bits the user hasn't touched keep the
+/// value each file has; bits which differ between the files are "mixed".
+struct Editor {
+    // bits set in all / in any of the files
+    all: u32,
+    any: u32,
+    mode: u32,
+    mode_mask: u32,
+    // the octal mode being typed
+    octal: Option<u32>,
+    // the owner and group if all files share them
+    uid: Option<u32>,
+    gid: Option<u32>,
+    new_uid: Option<u32>,
+    new_gid: Option<u32>,
+    recursive: bool,
+    row: usize,
+    col: usize,
+}
+
+impl Editor {
+    fn new(nodes: &[TreeNodeRef]) -> Editor {
+        let mut all = MODE_BITS;
+        let mut any = 0;
+        let first = nodes[0].borrow();
+        let mut uid = Some(first.sys_node.uid);
+        let mut gid = Some(first.sys_node.gid);
+        for node in nodes {
+            let sn = &node.borrow().sys_node;
+            all &= sn.mode;
+            any |= sn.mode & MODE_BITS;
+            if uid != Some(sn.uid) {
+                uid = None;
+            }
+            if gid != Some(sn.gid) {
+                gid = None;
+            }
+        }
+        Editor {
+            all,
+            any,
+            mode: 0,
+            mode_mask: 0,
+            octal: None,
+            uid,
+            gid,
+            new_uid: None,
+            new_gid: None,
+            recursive: false,
+            row: 0,
+            col: 0,
+        }
+    }
+
+    fn bit(row: usize, col: usize) -> u32 {
+        match col {
+            3 => 0o4000 >> row,
+            _ => 1 << ((2 - row) * 3 + (2 - col)),
+        }
+    }
+
+    /// Whether the bit is set, `None` if it differs between the files.
+    fn state(&self, bit: u32) -> Option<bool> {
+        if self.mode_mask & bit != 0 {
+            Some(self.mode & bit != 0)
+        } else if (self.any & !self.all) & bit != 0 {
+            None
+        } else {
+            Some(self.all & bit != 0)
+        }
+    }
+
+    // the mode shown, with mixed bits cleared
+    fn shown_mode(&self) -> u32 {
+        (self.all & !self.mode_mask) | (self.mode & self.mode_mask)
+    }
+
+    fn toggle(&mut self) {
+        let bit = Editor::bit(self.row, self.col);
+        let set = !self.state(bit).unwrap_or(false);
+        self.mode_mask |= bit;
+        self.mode = if set {
+            self.mode | bit
+        } else {
+            self.mode & !bit
+        };
+        self.octal = None;
+    }
+
+    /// Typed octal digits replace the whole mode, they are shifted in from
+    /// the right: `755` gives `0755`.
+    fn octal_digit(&mut self, digit: u32) {
+        let octal = ((self.octal.unwrap_or(0) << 3) | digit) & MODE_BITS;
+        self.set_octal(octal);
+    }
+
+    fn octal_backspace(&mut self) {
+        let octal = self.octal.unwrap_or(self.shown_mode()) >> 3;
+        self.set_octal(octal);
+    }
+
+    fn set_octal(&mut self, octal: u32) {
+        self.octal = Some(octal);
+        self.mode = octal;
+        self.mode_mask = MODE_BITS;
+    }
+
+    fn change(&self) -> AttrChange {
+        AttrChange {
+            mode: self.mode,
+            mode_mask: self.mode_mask,
+            uid: self.new_uid.filter(|uid| self.uid != Some(*uid)),
+            gid: self.new_gid.filter(|gid| self.gid != Some(*gid)),
+            recursive: self.recursive,
+        }
+    }
+
+    fn draw(&self, win: WINDOW, title: &str) {
+        werase(win);
+        box_(win, 0, 0);
+        let _ = mvwaddstr(win, 0, 2, &format!(" {} ", title));
+        let _ = mvwaddstr(win, 2, BOX_X[0], "read  write exec");
+
+        let check = |state: Option<bool>| match state {
+            Some(true) => "[x]",
+            Some(false) => "[ ]",
+            None => "[?]",
+        };
+        for (row, class) in CLASSES.iter().enumerate() {
+            let y = 3 + row as i32;
+            let _ = mvwaddstr(win, y, 2, class);
+            for (col, x) in BOX_X.iter().enumerate() {
+                let cursor = row == self.row && col == self.col;
+                if cursor {
+                    wattr_on(win, A_REVERSE);
+                }
+                let _ = mvwaddstr(win, y, *x, check(self.state(Editor::bit(row, col))));
+                if cursor {
+                    wattr_off(win, A_REVERSE);
+                }
+            }
+            let _ = mvwaddstr(win, y, BOX_X[3] + 4, SPECIAL[row]);
+        }
+
+        let mut perms: Vec<char> = permissions_to_str(self.shown_mode()).chars().collect();
+        for (i, ch) in perms.iter_mut().enumerate() {
+            if self.state(1 << (8 - i)).is_none() {
+                *ch = '?';
+            }
+        }
+        let perms: String = perms.into_iter().collect();
+        let _ = mvwaddstr(
+            win,
+            7,
+            2,
+            &format!("mode      {:04o}  {}", self.shown_mode(), perms),
+        );
+
+        let user = match self.new_uid.or(self.uid) {
+            Some(uid) => user_name(uid),
+            None => "(differs)".to_owned(),
+        };
+        let group = match self.new_gid.or(self.gid) {
+            Some(gid) => group_name(gid),
+            None => "(differs)".to_owned(),
+        };
+        let recursive = if self.recursive { "[x]" } else { "[ ]" };
+        let fields = [
+            ("owner", user),
+            ("group", group),
+            ("recursive", recursive.to_owned()),
+        ];
+        for (i, (label, value)) in fields.iter().enumerate() {
+            wmove(win, 8 + i as i32, 2);
+            print_fitted(
+                win,
+                &format!("{:<10}{}", label, value),
+                (WIDTH - 4) as usize,
+            );
+        }
+
+        let _ = mvwaddstr(win, 12, 2, "Space toggles, 0-7 octal mode");
+        let _ = mvwaddstr(win, 13, 2, "u owner, g group, r recursive");
+        let _ = mvwaddstr(win, 14, 2, "Enter applies, Esc cancels");
+        wrefresh(win);
+    }
+}
+
+fn user_name(uid: u32) -> String {
+    match users::get_user_by_uid(uid) {
+        Some(user) => escape_name(user.name()),
+        None => uid.to_string(),
+    }
+}
+
+fn group_name(gid: u32) -> String {
+    match users::get_group_by_gid(gid) {
+        Some(group) => escape_name(group.name()),
+        None => gid.to_string(),
+    }
+}
+
+/// Lets the user choose from the users or groups, starting at `current`.
+fn pick_id(title: &str, ids: Vec<(u32, OsString)>, current: Option<u32>) -> Option<u32> {
+    let items: Vec<String> = ids
+        .iter()
+        .map(|(id, name)| format!("{} ({})", escape_name(name), id))
+        .collect();
+    let selected = ids
+        .iter()
+        .position(|(id, _)| Some(*id) == current)
+        .unwrap_or(0);
+    let idx = pick(title, &items, selected)?;
+    Some(ids[idx].0)
+}
+
+/// Edits the permissions, owner and group of `nodes` in a window over the
+/// panes; `screen` is repainted after the owner or group list covered it.
+/// Returns what to change, or `None` if cancelled.
+pub fn edit_permissions(screen: &Screen, nodes: &[TreeNodeRef]) -> Option<AttrChange> {
+    if nodes.is_empty() {
+        return None;
+    }
+    let title = match nodes {
+        [node] => format!("Permissions: {}", escape_name(&node.borrow().sys_node.name)),
+        _ => format!("Permissions: {} entries", nodes.len()),
+    };
+
+    let mut height = 0;
+    let mut width = 0;
+    getmaxyx(stdscr(), &mut height, &mut width);
+    let win = newwin(
+        HEIGHT.min(height - 1),
+        WIDTH.min(width),
+        ((height - 1 - HEIGHT) / 2).max(0),
+        ((width - WIDTH) / 2).max(0),
+    );
+    keypad(win, true);
+
+    let mut editor = Editor::new(nodes);
+    let result = loop {
+        editor.draw(win, &title);
+        match wgetch(win) {
+            KEY_UP => editor.row = editor.row.saturating_sub(1),
+            KEY_DOWN => editor.row = (editor.row + 1).min(2),
+            KEY_LEFT => editor.col = editor.col.saturating_sub(1),
+            KEY_RIGHT => editor.col = (editor.col + 1).min(3),
+            k if k == 'k' as i32 => editor.row = editor.row.saturating_sub(1),
+            k if k == 'j' as i32 => editor.row = (editor.row + 1).min(2),
+            k if k == 'h' as i32 => editor.col = editor.col.saturating_sub(1),
+            k if k == 'l' as i32 => editor.col = (editor.col + 1).min(3),
+            k if k == ' ' as i32 || k == 'x' as i32 => editor.toggle(),
+            k if ('0' as i32..='7' as i32).contains(&k) => {
+                editor.octal_digit((k - '0' as i32) as u32)
+            }
+            KEY_BACKSPACE | 127 => editor.octal_backspace(),
+            k if k == 'u' as i32 => {
+                let current = editor.new_uid.or(editor.uid);
+                if let Some(uid) = pick_id("Owner", all_users(), current) {
+                    editor.new_uid = Some(uid);
+                }
+                screen.redraw();
+            }
+            k if k == 'g' as i32 => {
+                let current = editor.new_gid.or(editor.gid);
+                if let Some(gid) = pick_id("Group", all_groups(), current) {
+                    editor.new_gid = Some(gid);
+                }
+                screen.redraw();
+            }
+            k if k == 'r' as i32 => editor.recursive = !editor.recursive,
+            10 => break Some(editor.change()),
+            k if k == 27 || k == 'q' as i32 => break None,
+            _ => {}
+        }
+    };
+    delwin(win);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tree_node::TreeNode;
+
+    fn node(mode: u32, uid: u32, gid: u32) -> TreeNodeRef {
+        let mut sn = SysNode::new(std::ffi::OsStr::new("f"), NodeType::File);
+        sn.mode = 0o100000 | mode;
+        sn.uid = uid;
+        sn.gid = gid;
+        TreeNode::from(sn)
+    }
+
+    #[test]
+    fn mixed_bits() {
+        let mut editor = Editor::new(&[node(0o4644, 1, 1), node(0o755, 1, 2)]);
+        assert_eq!((editor.uid, editor.gid), (Some(1), None));
+        assert_eq!(editor.state(0o400), Some(true));
+        assert_eq!(editor.state(0o100), None);
+        assert_eq!(editor.state(0o4000), None);
+        assert_eq!(editor.state(0o002), Some(false));
+        assert_eq!(editor.shown_mode(), 0o644);
+
+        // owner exec: a mixed bit is set first, then cleared
+        (editor.row, editor.col) = (0, 2);
+        assert_eq!(Editor::bit(0, 2), 0o100);
+        editor.toggle();
+        assert_eq!(editor.state(0o100), Some(true));
+        assert_eq!(editor.shown_mode(), 0o744);
+        editor.toggle();
+        assert_eq!(editor.state(0o100), Some(false));
+
+        // setuid, then group read which all the files have
+        (editor.row, editor.col) = (0, 3);
+        editor.toggle();
+        (editor.row, editor.col) = (1, 0);
+        editor.toggle();
+        let change = editor.change();
+        assert_eq!(change.mode_mask, 0o4140);
+        assert_eq!(change.mode, 0o4000);
+        // the other mixed bits are left alone
+        assert_eq!(editor.state(0o010), None);
+    }
+
+    #[test]
+    fn octal_mode() {
+        let mut editor = Editor::new(&[node(0o644, 0, 0), node(0o600, 0, 0)]);
+        for digit in [7, 5, 1] {
+            editor.octal_digit(digit);
+        }
+        assert_eq!(editor.octal, Some(0o751));
+        assert_eq!(editor.state(0o040), Some(true));
+        assert_eq!(editor.state(0o004), Some(false));
+        let change = editor.change();
+        assert_eq!((change.mode, change.mode_mask), (0o751, MODE_BITS));
+
+        // only the last four digits count
+        editor.octal_digit(2);
+        editor.octal_digit(3);
+        assert_eq!(editor.octal, Some(0o5123));
+        editor.octal_backspace();
+        assert_eq!(editor.octal, Some(0o512));
+        assert_eq!(editor.change().mode, 0o512);
+
+        // a toggle ends the typing, backspace starts from what is shown
+        (editor.row, editor.col) = (2, 2);
+        editor.toggle();
+        assert_eq!(editor.octal, None);
+        assert_eq!(editor.shown_mode(), 0o513);
+        editor.octal_backspace();
+        assert_eq!(editor.octal, Some(0o51));
+        assert_eq!(editor.state(0o400), Some(false));
+
+        // without typing, backspace on mixed files shifts the common bits
+        let mut editor = Editor::new(&[node(0o644, 0, 0), node(0o600, 0, 0)]);
+        editor.octal_backspace();
+        assert_eq!(editor.octal, Some(0o60));
+    }
+
+    #[test]
+    fn unchanged_owner_is_left_out() {
+        let mut editor = Editor::new(&[node(0o644, 1000, 100), node(0o644, 1000, 100)]);
+        assert!(editor.change().is_empty());
+        editor.new_uid = Some(1000);
+        editor.new_gid = Some(5);
+        let change = editor.change();
+        assert_eq!((change.uid, change.gid), (None, Some(5)));
+    }
+}
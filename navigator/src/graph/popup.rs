@@ -0,0 +1,149 @@
+use ncurses::*;
+
+use super::display::print_fitted;
+
+/// A line of a popup window.
+pub enum PopupLine {
+    Title(String),
+    Text(String),
+    Blank,
+}
+
+/// Shows `lines` in a boxed window over both panes, until Esc, `q`, Enter,
+/// F1 or `?` is pressed. Arrows, PgUp/PgDn, Home/End and `j`/`k` scroll.
+pub fn show_popup(title: &str, lines: &[PopupLine]) {
+    run_popup(title, lines, None, |k| {
+        k == 27 || k == 10 || k == KEY_F(1) || k == 'q' as i32 || k == '?' as i32
+    });
+}
+
+/// Shows `lines` like `show_popup` with `question` at the bottom, e.g. to
+/// confirm what they list. Only 'y' confirms, any other key declines.
+pub fn ask_popup(title: &str, lines: &[PopupLine], question: &str) -> bool {
+    let answer = run_popup(title, lines, Some(question), |_| true);
+    answer == 'y' as i32 || answer == 'Y' as i32
+}
+
+// scrolls until a key for which `done` holds, which is returned
+fn run_popup(
+    title: &str,
+    lines: &[PopupLine],
+    footer: Option<&str>,
+    done: impl Fn(i32) -> bool,
+) -> i32 {
+    let mut height = 0;
+    let mut width = 0;
+    getmaxyx(stdscr(), &mut height, &mut width);
+    // the status line stays visible
+    let win = newwin(height - 1, width, 0, 0);
+    keypad(win, true);
+
+    let view_h = (height - 3).max(1) as usize;
+    let view_w = (width - 4).max(0) as usize;
+    let max_offset = lines.len().saturating_sub(view_h);
+    let mut offset = 0;
+    let key = loop {
+        werase(win);
+        box_(win, 0, 0);
+        let _ = mvwaddstr(win, 0, 2, &format!(" {} ", title));
+        for (y, line) in lines.iter().skip(offset).take(view_h).enumerate() {
+            wmove(win, y as i32 + 1, 2);
+            match line {
+                PopupLine::Title(title) => {
+                    wattr_on(win, A_BOLD);
+                    print_fitted(win, title, view_w);
+                    wattr_off(win, A_BOLD);
+                }
+                PopupLine::Text(text) => print_fitted(win, text, view_w),
+                PopupLine::Blank => {}
+            }
+        }
+        if let Some(footer) = footer {
+            wmove(win, height - 2, 2);
+            wattr_on(win, A_REVERSE);
+            print_fitted(win, &format!(" {} ", footer), view_w);
+            wattr_off(win, A_REVERSE);
+        }
+        if max_offset > 0 {
+            let pos = format!(" {}/{} ", offset + view_h.min(lines.len()), lines.len());
+            let _ = mvwaddstr(win, height - 2, (width - 2 - pos.len() as i32).max(1), &pos);
+        }
+        wrefresh(win);
+
+        match wgetch(win) {
+            KEY_UP => offset = offset.saturating_sub(1),
+            KEY_DOWN => offset = (offset + 1).min(max_offset),
+            KEY_PPAGE => offset = offset.saturating_sub(view_h),
+            KEY_NPAGE => offset = (offset + view_h).min(max_offset),
+            KEY_HOME => offset = 0,
+            KEY_END => offset = max_offset,
+            k if k == 'k' as i32 => offset = offset.saturating_sub(1),
+            k if k == 'j' as i32 => offset = (offset + 1).min(max_offset),
+            k if done(k) => break k,
+            _ => {}
+        }
+    };
+    delwin(win);
+    key
+}
+
+/// Lets the user choose one of `items` in a boxed window over both panes,
+/// starting at `selected`. Enter chooses, Esc or `q` cancels. Typing a
+/// character jumps to the next item starting with it.
+pub fn pick(title: &str, items: &[String], selected: usize) -> Option<usize> {
+    let mut height = 0;
+    let mut width = 0;
+    getmaxyx(stdscr(), &mut height, &mut width);
+    let win = newwin(height - 1, width, 0, 0);
+    keypad(win, true);
+
+    let view_h = (height - 3).max(1) as usize;
+    let view_w = (width - 4).max(0) as usize;
+    let last = items.len().saturating_sub(1);
+    let mut curs = selected.min(last);
+    let mut offset = 0;
+    let result = loop {
+        if curs < offset {
+            offset = curs;
+        } else if curs >= offset + view_h {
+            offset = curs + 1 - view_h;
+        }
+        werase(win);
+        box_(win, 0, 0);
+        let _ = mvwaddstr(win, 0, 2, &format!(" {} ", title));
+        for (y, item) in items.iter().skip(offset).take(view_h).enumerate() {
+            wmove(win, y as i32 + 1, 2);
+            if offset + y == curs {
+                wattr_on(win, A_REVERSE);
+                print_fitted(win, item, view_w);
+                wattr_off(win, A_REVERSE);
+            } else {
+                print_fitted(win, item, view_w);
+            }
+        }
+        wrefresh(win);
+
+        match wgetch(win) {
+            KEY_UP => curs = curs.saturating_sub(1),
+            KEY_DOWN => curs = (curs + 1).min(last),
+            KEY_PPAGE => curs = curs.saturating_sub(view_h),
+            KEY_NPAGE => curs = (curs + view_h).min(last),
+            KEY_HOME => curs = 0,
+            KEY_END => curs = last,
+            10 if !items.is_empty() => break Some(curs),
+            27 => break None,
+            k if (0x20..0x7f).contains(&k) => {
+                let c = (k as u8 as char).to_ascii_lowercase();
+                let next = (1..=items.len())
+                    .map(|i| (curs + i) % items.len())
+                    .find(|i| items[*i].to_lowercase().starts_with(c));
+                if let Some(next) = next {
+                    curs = next;
+                }
+            }
+            _ => {}
+        }
+    };
+    delwin(win);
+    result
+}
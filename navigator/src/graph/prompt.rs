@@ -0,0 +1,60 @@
+use ncurses::*;
+
+use super::display::{print_fitted, str_width};
+
+/// Asks a yes/no question on the bottom line of the screen. Only 'y'
+/// confirms, any other key declines.
+pub fn confirm(question: &str) -> bool {
+    let mut height = 0;
+    let mut width = 0;
+    getmaxyx(stdscr(), &mut height, &mut width);
+
+    let win = newwin(1, width, height - 1, 0);
+    keypad(win, true);
+    wattr_on(win, A_REVERSE);
+    print_fitted(win, &format!("{} (y/n)", question), width as usize);
+    wattr_off(win, A_REVERSE);
+    wrefresh(win);
+
+    let answer = wgetch(win);
+    delwin(win);
+    answer == 'y' as i32 || answer == 'Y' as i32
+}
+
+/// Reads a line of text on the bottom line of the screen, after `prompt`,
+/// starting with `initial`. Enter accepts the text, Esc cancels.
+pub fn read_line(prompt: &str, initial: &str) -> Option<String> {
+    let mut height = 0;
+    let mut width = 0;
+    getmaxyx(stdscr(), &mut height, &mut width);
+
+    let win = newwin(1, width, height - 1, 0);
+    keypad(win, true);
+    curs_set(CURSOR_VISIBILITY::CURSOR_VISIBLE);
+
+    // raw bytes, multibyte characters arrive one byte per key
+    let mut bytes: Vec<u8> = initial.as_bytes().to_vec();
+    let result = loop {
+        let line = format!("{}{}", prompt, String::from_utf8_lossy(&bytes));
+        wmove(win, 0, 0);
+        print_fitted(win, &line, width as usize);
+        wmove(win, 0, (str_width(&line) as i32).min(width - 1));
+        wrefresh(win);
+
+        match wgetch(win) {
+            10 => break Some(String::from_utf8_lossy(&bytes).into_owned()),
+            27 => break None,
+            KEY_BACKSPACE | 127 | 8 => {
+                let mut text = String::from_utf8_lossy(&bytes).into_owned();
+                text.pop();
+                bytes = text.into_bytes();
+            }
+            k if (32..256).contains(&k) => bytes.push(k as u8),
+            _ => {}
+        }
+    };
+
+    curs_set(CURSOR_VISIBILITY::CURSOR_INVISIBLE);
+    delwin(win);
+    result
+}
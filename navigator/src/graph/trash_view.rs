@@ -0,0 +1,203 @@
+use std::{cell::RefCell, rc::Rc};
+
+use super::display::*;
+use super::prompt::confirm;
+use crate::colors::ColorScheme;
+use crate::common::*;
+use crate::filesystem::*;
+use crate::keymap::*;
+use crate::trash::{self, TrashEntry};
+use crate::tree::*;
+use crate::tree_node::*;
+
+/// The content of all trash directories, shown in place of the list: when
+/// each entry was deleted and where from.
+pub struct TrashView {
+    tree: Rc<RefCell<Tree>>,
+    entries: Vec<TrashEntry>,
+    lines: Vec<ViewLine>,
+    cursor: usize,
+    colors: Rc<ColorScheme>,
+    modified: bool,
+}
+
+impl TrashView {
+    pub fn new(tree: Rc<RefCell<Tree>>, colors: Rc<ColorScheme>) -> TrashView {
+        let mut view = TrashView {
+            tree,
+            entries: Vec::new(),
+            lines: Vec::new(),
+            cursor: 0,
+            colors,
+            modified: true,
+        };
+        view.read_trash();
+        view
+    }
+
+    fn read_trash(&mut self) {
+        self.entries = trash::list();
+        self.lines.clear();
+        for entry in &self.entries {
+            // the node is only for the name's colors and searching
+            let mut sys_node = SysNode::from_path(&entry.file_path());
+            if let Some(name) = entry.original.file_name() {
+                sys_node.name = name.to_os_string();
+            }
+            let node = TreeNode::from(sys_node);
+
+            let date = entry.deleted.replace('T', " ");
+            let dir = match entry.original.parent() {
+                Some(dir) if dir.parent().is_some() => format!("{}/", escape_name(dir.as_os_str())),
+                _ => "/".to_owned(),
+            };
+            let name = escape_name(&node.borrow().sys_node.name);
+            let x1 = str_width(&date) + 2 + str_width(&dir);
+            let x2 = x1 + str_width(&name);
+            let attrs = self.colors.attrs(&node.borrow().sys_node);
+            self.lines.push(ViewLine::new(
+                &format!("{}  {}{}", date, dir, name),
+                x1 as i32,
+                x2 as i32,
+                attrs,
+                &node,
+            ));
+        }
+        self.cursor = self.cursor.min(self.lines.len().saturating_sub(1));
+        self.modified = true;
+    }
+
+    fn move_by(&mut self, delta: i64) {
+        let last = self.lines.len() as i64 - 1;
+        self.cursor = (self.cursor as i64 + delta).clamp(0, last.max(0)) as usize;
+        self.modified = true;
+    }
+
+    fn search(&mut self, forward: bool) {
+        let Some(pattern) = self.tree.borrow().search.clone() else {
+            return;
+        };
+        if self.lines.is_empty() {
+            return;
+        }
+        match find_line(&self.lines, self.cursor, &pattern, forward) {
+            Some(y) => self.move_by(y as i64 - self.cursor as i64),
+            None => {
+                self.tree.borrow_mut().status_msg = Some(format!("Pattern not found: {}", pattern))
+            }
+        }
+    }
+
+    /// Puts the entry under the cursor back and shows it in the tree, if
+    /// its directory is loaded there.
+    fn restore(&mut self) {
+        let Some(entry) = self.entries.get(self.cursor) else {
+            return;
+        };
+        let original = escape_name(entry.original.as_os_str());
+        let message = match trash::restore(entry) {
+            Ok(()) => {
+                if let Some(dir) = entry.original.parent() {
+                    self.tree.borrow_mut().reload_dir(dir);
+                }
+                format!("Restored {}", original)
+            }
+            Err(err) => format!("Cannot restore: {}", err),
+        };
+        self.tree.borrow_mut().status_msg = Some(message);
+        self.read_trash();
+    }
+
+    fn purge(&mut self) {
+        let Some(entry) = self.entries.get(self.cursor) else {
+            return;
+        };
+        let original = escape_name(entry.original.as_os_str());
+        if !confirm(&format!("Delete {} for good?", original)) {
+            self.modified = true;
+            return;
+        }
+        let message = match trash::purge(entry) {
+            Ok(()) => format!("Deleted {}", original),
+            Err(err) => format!("Cannot delete: {}", err),
+        };
+        self.tree.borrow_mut().status_msg = Some(message);
+        self.read_trash();
+    }
+}
+
+impl DisplContent for TrashView {
+    fn modified(&self) -> bool {
+        self.modified
+    }
+
+    fn reset_modified(&mut self) {
+        self.modified = false;
+    }
+
+    fn prepare(&mut self, info: &mut DisplInfo) -> Result<(), AppError> {
+        info.lines_count = self.lines.len() as i32;
+        match self.lines.get(self.cursor) {
+            Some(ln) => {
+                info.curs_line = Some(self.cursor as i32);
+                info.curs_x1 = ln.x1;
+                info.curs_x2 = ln.x2;
+            }
+            None => {
+                info.curs_line = None;
+                info.curs_x1 = 0;
+                info.curs_x2 = 0;
+            }
+        }
+        Ok(())
+    }
+
+    fn get_line(&self, y: usize) -> Result<&ViewLine, AppError> {
+        match self.lines.get(y) {
+            Some(line) => Ok(line),
+            None => Err(AppError::StrError(
+                "TrashView index out of range".to_owned(),
+            )),
+        }
+    }
+
+    fn click(&mut self, y: usize, _double: bool) -> Result<(), AppError> {
+        if y < self.lines.len() {
+            self.cursor = y;
+            self.modified = true;
+        }
+        Ok(())
+    }
+
+    fn context(&self) -> Context {
+        Context::Trash
+    }
+
+    fn process_action(
+        &mut self,
+        action: Action,
+        count: usize,
+        page: usize,
+    ) -> Result<(), AppError> {
+        let count = count as i64;
+        match action {
+            Action::Up => self.move_by(-count),
+            Action::Down => self.move_by(count),
+            Action::PageUp => self.move_by(-count * page as i64),
+            Action::PageDown => self.move_by(count * page as i64),
+            Action::HalfPageUp => self.move_by(-count * page.div_ceil(2) as i64),
+            Action::HalfPageDown => self.move_by(count * page.div_ceil(2) as i64),
+            Action::Top => self.move_by(-(self.cursor as i64)),
+            Action::Bottom => self.move_by(self.lines.len() as i64),
+            Action::SearchNext | Action::SearchPrev => {
+                for _ in 0..count {
+                    self.search(action == Action::SearchNext);
+                }
+            }
+            Action::Restore => self.restore(),
+            Action::Purge => self.purge(),
+            _ => {}
+        };
+        Ok(())
+    }
+}
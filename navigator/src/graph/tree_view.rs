@@ -1,26 +1,120 @@
 use std::{cell::RefCell, rc::Rc};
 
-use ncurses::KEY_BACKSPACE;
-use ncurses::KEY_DOWN;
-use ncurses::KEY_LEFT;
-use ncurses::KEY_RIGHT;
-use ncurses::KEY_UP;
-
 use super::display::*;
-use crate::{common::*, filesystem::NodeType, tree::*, tree_node::*};
+use super::prompt::confirm;
+use crate::{
+    colors::ColorScheme, common::*, dir_size::DirSize, filesystem::*, keymap::*, tree::*,
+    tree_node::*,
+};
+
+/// Strings used to draw tree branches. Every string of a style has the same
+/// width.
+pub struct LineStyle {
+    pub vert: &'static str,
+    pub blank: &'static str,
+    pub tee: &'static str,
+    pub corner: &'static str,
+}
+
+/// One column per level, used in the tree pane.
+pub const COMPACT_LINES: LineStyle = LineStyle {
+    vert: "│",
+    blank: " ",
+    tee: "├",
+    corner: "└",
+};
+
+pub const UNICODE_LINES: LineStyle = LineStyle {
+    vert: "│   ",
+    blank: "    ",
+    tee: "├── ",
+    corner: "└── ",
+};
+
+pub const ASCII_LINES: LineStyle = LineStyle {
+    vert: "|   ",
+    blank: "    ",
+    tee: "|-- ",
+    corner: "`-- ",
+};
+
+/// Builds the branch prefix of a node. `prevs_stack` tells for every
+/// ancestor level whether more siblings follow, `tbc` ("to be continued")
+/// whether the node itself has following siblings (`None` for the root).
+pub fn branch_prefix(style: &LineStyle, prevs_stack: &[bool], tbc: Option<bool>) -> String {
+    let lead: String = prevs_stack
+        .iter()
+        .map(|b| if *b { style.vert } else { style.blank })
+        .collect();
+
+    let link = match tbc {
+        Some(tbc) => match tbc {
+            true => style.tee,
+            false => style.corner,
+        },
+        None => "",
+    };
+    lead + link
+}
+
+const DU_BAR_WIDTH: usize = 10;
 
 pub struct TreeView {
     tree: Rc<RefCell<Tree>>,
     lines: Vec<ViewLine>,
+    colors: Rc<ColorScheme>,
     pub modif_flags: ModifFlags,
+    /// Disk usage mode: directories are sorted by size and shown with their
+    /// share of the parent.
+    pub du_mode: bool,
 }
 
 impl TreeView {
-    pub fn new(tree: Rc<RefCell<Tree>>) -> TreeView {
+    pub fn new(tree: Rc<RefCell<Tree>>, colors: Rc<ColorScheme>) -> TreeView {
         TreeView {
             tree,
             lines: Vec::new(),
+            colors,
             modif_flags: ModifFlags::new(),
+            du_mode: false,
+        }
+    }
+
+    // e.g. "   1.2 GiB  42.0% [####      ] "
+    fn du_info(&self, n: &TreeNode) -> String {
+        let size = match n.dir_size {
+            DirSize::Done(size) => human_size(size),
+            DirSize::Computing => self.tree.borrow().size_jobs.spinner().to_string(),
+            DirSize::Failed => "?".to_owned(),
+            DirSize::Unknown => "".to_owned(),
+        };
+        let share = match (n.dir_size, n.parent.upgrade()) {
+            (DirSize::Done(size), Some(parent)) => match parent.borrow().dir_size {
+                DirSize::Done(psize) if psize > 0 => Some(size as f64 / psize as f64),
+                _ => None,
+            },
+            (DirSize::Done(_), None) => Some(1.0),
+            _ => None,
+        };
+        match share {
+            Some(share) => {
+                let share = share.clamp(0.0, 1.0);
+                let filled = (share * DU_BAR_WIDTH as f64).round() as usize;
+                format!(
+                    "{:>10} {:>5.1}% [{:<width$}] ",
+                    size,
+                    share * 100.0,
+                    "#".repeat(filled),
+                    width = DU_BAR_WIDTH
+                )
+            }
+            None => format!(
+                "{:>10} {:>6} {:width$}   ",
+                size,
+                "",
+                "",
+                width = DU_BAR_WIDTH
+            ),
         }
     }
 
@@ -29,36 +123,38 @@ impl TreeView {
         if n.sys_node.typ != NodeType::Dir {
             return;
         }
-        let lead: String = prevs_stack
-            .iter()
-            .map(|b| if *b { "│" } else { " " })
-            .collect();
-
-        let link = match tbc {
-            Some(tbc) => match tbc {
-                true => "├",
-                false => "└",
-            },
-            None => "",
-        };
-        let link_len = link.chars().count();
+        let mut prefix = String::new();
+        if self.du_mode {
+            prefix += &self.du_info(&n);
+        }
+        prefix += &branch_prefix(&COMPACT_LINES, prevs_stack, tbc);
+        let prefix_len = str_width(&prefix);
         let exp_stat = if node.borrow().expanded { "-" } else { "+" };
-        let name_as_str = n.sys_node.name.to_string_lossy().to_string();
-        let s = &format!("{}{}{}{}", lead, link, exp_stat, &name_as_str);
+        let name_as_str = escape_name(&n.sys_node.name);
+        let git_marker = match self.tree.borrow().git.repo() {
+            Some(git) => git
+                .dir_marker(&n.get_path())
+                .map_or(String::new(), |m| format!(" {}", m)),
+            None => String::new(),
+        };
+        let s = &format!("{}{}{}{}", prefix, exp_stat, &name_as_str, git_marker);
         let vline = ViewLine::new(
             s,
-            (prevs_stack.len() + link_len + 1) as i32,
-            (prevs_stack.len() + link_len + 1 + name_as_str.chars().count()) as i32,
-            None,
+            (prefix_len + 1) as i32,
+            (prefix_len + 1 + str_width(&name_as_str)) as i32,
+            self.colors.attrs(&n.sys_node),
             &node,
         );
         self.lines.push(vline);
         if n.expanded {
-            let subnodes: Vec<_> = n
+            let mut subnodes: Vec<_> = n
                 .subnodes
                 .iter()
                 .filter(|sn| sn.borrow().sys_node.typ == NodeType::Dir)
                 .collect();
+            if self.du_mode {
+                subnodes.sort_by_key(|sn| std::cmp::Reverse(sn.borrow().effective_size()));
+            }
             if let Some(tbc) = tbc {
                 prevs_stack.push(tbc);
             }
@@ -77,6 +173,111 @@ impl TreeView {
         let root = &self.tree.borrow().root.clone(); // TODO: clone? - przyjrzeć się temu
         let mut prevs_stack: Vec<bool> = Vec::new();
         self.list_node(root, &mut prevs_stack, None);
+        if self.du_mode {
+            self.request_sizes();
+        }
+    }
+
+    // Sizes are computed for the current directory and its subdirectories,
+    // the rest of the tree shows what is already known.
+    fn request_sizes(&mut self) {
+        let mut tree = self.tree.borrow_mut();
+        let cd = tree.curr_dir();
+        tree.size_jobs.request(&cd);
+        for sn in &cd.borrow().subnodes {
+            tree.size_jobs.request(sn);
+        }
+    }
+
+    fn refresh_lines(&mut self) {
+        if self.modif_flags.render {
+            self.list_tree();
+            self.modif_flags.render = false;
+        }
+    }
+
+    fn goto_line(&mut self, y: usize) -> Result<(), AppError> {
+        let Some(line) = self.lines.get(y) else {
+            return Ok(());
+        };
+        let tree = self.tree.clone();
+        let dest = line.src_node.clone();
+        tree.borrow_mut().tv_goto(&dest, self)?;
+        Ok(())
+    }
+
+    /// Moves the cursor by `delta` lines, stopping at the first and last one.
+    fn move_by(&mut self, delta: i64) -> Result<(), AppError> {
+        self.refresh_lines();
+        if let Some(curs_y) = self.find_cursor() {
+            let last = self.lines.len() as i64 - 1;
+            self.goto_line((curs_y as i64 + delta).clamp(0, last) as usize)?;
+        }
+        Ok(())
+    }
+
+    /// Moves to the first subdirectory, expanding the current one if needed.
+    fn goto_first_child(&mut self) -> Result<(), AppError> {
+        let tree = self.tree.clone();
+        tree.borrow_mut().tv_expand(true, self)?;
+        self.refresh_lines();
+        let cd = tree.borrow().curr_dir();
+        if let Some(curs_y) = self.find_cursor() {
+            let y = curs_y as usize + 1;
+            let is_child = self.lines.get(y).is_some_and(|line| {
+                let parent = line.src_node.borrow().parent.upgrade();
+                parent.is_some_and(|p| Rc::ptr_eq(&p, &cd))
+            });
+            if is_child {
+                self.goto_line(y)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves `delta` siblings forward or back, in the order they are shown.
+    fn goto_sibling(&mut self, delta: i64) -> Result<(), AppError> {
+        self.refresh_lines();
+        let cd = self.tree.borrow().curr_dir();
+        let Some(parent) = cd.borrow().parent.upgrade() else {
+            return Ok(());
+        };
+        let siblings: Vec<usize> = self
+            .lines
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| {
+                let p = line.src_node.borrow().parent.upgrade();
+                p.is_some_and(|p| Rc::ptr_eq(&p, &parent))
+            })
+            .map(|(y, _)| y)
+            .collect();
+        let Some(curs_y) = self.find_cursor() else {
+            return Ok(());
+        };
+        if let Some(idx) = siblings.iter().position(|y| *y == curs_y as usize) {
+            let last = siblings.len() as i64 - 1;
+            let idx = (idx as i64 + delta).clamp(0, last) as usize;
+            self.goto_line(siblings[idx])?;
+        }
+        Ok(())
+    }
+
+    fn search(&mut self, forward: bool) -> Result<(), AppError> {
+        self.refresh_lines();
+        let Some(pattern) = self.tree.borrow().search.clone() else {
+            return Ok(());
+        };
+        let Some(curs_y) = self.find_cursor() else {
+            return Ok(());
+        };
+        match find_line(&self.lines, curs_y as usize, &pattern, forward) {
+            Some(y) => self.goto_line(y)?,
+            None => {
+                self.tree.borrow_mut().status_msg = Some(format!("Pattern not found: {}", pattern))
+            }
+        }
+        Ok(())
     }
 
     // TODO: to ma zwracać Option(i32 lub usize) i tegoż typu ma być DisplInfo::curs_line
@@ -103,10 +304,7 @@ impl DisplContent for TreeView {
     }
 
     fn prepare(&mut self, info: &mut DisplInfo) -> Result<(), AppError> {
-        if self.modif_flags.render {
-            self.list_tree();
-            self.modif_flags.render = false;
-        }
+        self.refresh_lines();
         info.lines_count = self.lines.len() as i32;
         info.curs_line = self.find_cursor();
         match info.curs_line {
@@ -135,37 +333,80 @@ impl DisplContent for TreeView {
         }
     }
 
-    fn process_key(&mut self, key: i32) -> Result<(), AppError> {
-        match key {
-            KEY_UP => {
-                if let Some(curs_y) = self.find_cursor() {
-                    if let Some(line) = self.lines.get((curs_y - 1) as usize) {
-                        let tree = self.tree.clone();
-                        let dest = line.src_node.clone();
-                        tree.borrow_mut().tv_goto(&dest, self)?;
-                    }
+    fn click(&mut self, y: usize, double: bool) -> Result<(), AppError> {
+        let Some(line) = self.lines.get(y) else {
+            return Ok(());
+        };
+        let tree = self.tree.clone();
+        let dest = line.src_node.clone();
+        tree.borrow_mut().tv_goto(&dest, self)?;
+        if double {
+            let expanded = dest.borrow().expanded;
+            tree.borrow_mut().tv_expand(!expanded, self)?;
+        }
+        Ok(())
+    }
+
+    fn context(&self) -> Context {
+        Context::Tree
+    }
+
+    fn process_action(
+        &mut self,
+        action: Action,
+        count: usize,
+        page: usize,
+    ) -> Result<(), AppError> {
+        let tree = self.tree.clone();
+        let count = count as i64;
+        match action {
+            Action::Up => self.move_by(-count)?,
+            Action::Down => self.move_by(count)?,
+            Action::PageUp => self.move_by(-count * page as i64)?,
+            Action::PageDown => self.move_by(count * page as i64)?,
+            Action::HalfPageUp => self.move_by(-count * page.div_ceil(2) as i64)?,
+            Action::HalfPageDown => self.move_by(count * page.div_ceil(2) as i64)?,
+            Action::Top => {
+                self.refresh_lines();
+                self.goto_line(0)?;
+            }
+            Action::Bottom => {
+                self.refresh_lines();
+                self.goto_line(self.lines.len().saturating_sub(1))?;
+            }
+            Action::Parent => {
+                for _ in 0..count {
+                    tree.borrow_mut().tv_move_up(self)?;
                 }
             }
-            KEY_DOWN => {
-                if let Some(curs_y) = self.find_cursor() {
-                    if let Some(line) = self.lines.get((curs_y + 1) as usize) {
-                        let tree = self.tree.clone();
-                        let dest = line.src_node.clone();
-                        tree.borrow_mut().tv_goto(&dest, self)?;
-                    }
+            Action::FirstChild => {
+                for _ in 0..count {
+                    self.goto_first_child()?;
                 }
             }
-            KEY_RIGHT => {
-                let tree = self.tree.clone();
-                tree.borrow_mut().tv_expand(true, self)?;
+            Action::NextSibling => self.goto_sibling(count)?,
+            Action::PrevSibling => self.goto_sibling(-count)?,
+            Action::SearchNext | Action::SearchPrev => {
+                for _ in 0..count {
+                    self.search(action == Action::SearchNext)?;
+                }
             }
-            KEY_LEFT => {
-                let tree = self.tree.clone();
-                tree.borrow_mut().tv_expand(false, self)?;
+            Action::Expand => tree.borrow_mut().tv_expand(true, self)?,
+            Action::Collapse => tree.borrow_mut().tv_expand(false, self)?,
+            Action::ToggleDiskUsage => {
+                self.du_mode = !self.du_mode;
+                self.modif_flags.render = true;
+                self.modif_flags.print = true;
             }
-            KEY_BACKSPACE => {
-                let tree = self.tree.clone();
-                tree.borrow_mut().tv_move_up(self)?;
+            Action::Delete if self.du_mode => {
+                let path = tree.borrow().curr_path();
+                if confirm(&format!(
+                    "Delete {} with all its contents?",
+                    escape_name(path.as_os_str())
+                )) {
+                    tree.borrow_mut().tv_delete(self)?;
+                }
+                self.modif_flags.print = true;
             }
             _ => {}
         };
@@ -0,0 +1,754 @@
+use std::collections::HashSet;
+use std::ffi::{CString, OsStr, OsString};
+use std::fs::{self, File, Metadata};
+use std::io::{self, Read};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::path::Path;
+
+use users::{get_group_by_gid, get_user_by_uid};
+
+use crate::common::AppError;
+use crate::filesystem::*;
+
+/// A titled group of fields, as (label, value).
+pub struct InfoSection {
+    pub title: &'static str,
+    pub fields: Vec<(String, String)>,
+}
+
+impl InfoSection {
+    fn new(title: &'static str) -> InfoSection {
+        InfoSection {
+            title,
+            fields: Vec::new(),
+        }
+    }
+
+    fn add(&mut self, label: &str, value: String) {
+        self.fields.push((label.to_owned(), value));
+    }
+}
+
+/// Collects everything known about the file at `path`: `stat` data, the
+/// symlink chain, the MIME type, ACLs, capabilities and extended attributes.
+/// Symlinks are not followed, except to resolve the chain.
+pub fn inspect(path: &Path) -> Result<Vec<InfoSection>, AppError> {
+    let md = fs::symlink_metadata(path)
+        .map_err(|err| AppError::PathError(err.to_string(), path.to_string_lossy().into()))?;
+    let mut sections = vec![stat_section(path, &md), times_section(&md)];
+    if md.file_type().is_symlink() {
+        sections.push(link_section(path));
+    }
+
+    let mut content = InfoSection::new("Content");
+    content.add("MIME type", mime_type(path, &md));
+    sections.push(content);
+
+    let xattrs = Xattrs::read(path);
+    sections.push(acl_section(&xattrs));
+    sections.push(caps_section(&xattrs));
+    sections.push(xattrs_section(&xattrs));
+    Ok(sections)
+}
+
+/* #region stat */
+
+fn stat_section(path: &Path, md: &Metadata) -> InfoSection {
+    let typ = NodeType::from(md.file_type());
+    let mut section = InfoSection::new("File");
+    section.add("Path", escape_name(path.as_os_str()));
+    section.add("Type", type_name(md).to_owned());
+    section.add("Size", format!("{} ({})", md.size(), human_size(md.size())));
+    section.add(
+        "Blocks",
+        format!("{} of 512 B, I/O block {} B", md.blocks(), md.blksize()),
+    );
+    let (major, minor) = major_minor(md.dev());
+    section.add("Device", format!("{}:{} ({})", major, minor, md.dev()));
+    section.add("Inode", md.ino().to_string());
+    section.add("Links", md.nlink().to_string());
+    if typ.is_device() {
+        let (major, minor) = major_minor(md.rdev());
+        section.add("Device type", format!("{}, {}", major, minor));
+    }
+    section.add(
+        "Mode",
+        format!(
+            "{:04o} ({}{})",
+            md.mode() & 0o7777,
+            file_type_to_str(&typ),
+            permissions_to_str(md.mode())
+        ),
+    );
+    let user = get_user_by_uid(md.uid()).map(|u| u.name().to_os_string());
+    section.add("Owner", id_with_name(md.uid(), user));
+    let group = get_group_by_gid(md.gid()).map(|g| g.name().to_os_string());
+    section.add("Group", id_with_name(md.gid(), group));
+    section
+}
+
+fn type_name(md: &Metadata) -> &'static str {
+    let ft = md.file_type();
+    if ft.is_dir() {
+        "directory"
+    } else if ft.is_symlink() {
+        "symbolic link"
+    } else if ft.is_block_device() {
+        "block device"
+    } else if ft.is_char_device() {
+        "character device"
+    } else if ft.is_fifo() {
+        "FIFO"
+    } else if ft.is_socket() {
+        "socket"
+    } else {
+        "regular file"
+    }
+}
+
+fn id_with_name(id: u32, name: Option<OsString>) -> String {
+    match name {
+        Some(name) => format!("{} ({})", escape_name(&name), id),
+        None => id.to_string(),
+    }
+}
+
+fn times_section(md: &Metadata) -> InfoSection {
+    let full = |secs, nsecs| {
+        unix_time_to_local(secs, nsecs)
+            .format("%Y-%m-%d %H:%M:%S%.9f %z")
+            .to_string()
+    };
+    let mut section = InfoSection::new("Times");
+    section.add("Accessed", full(md.atime(), md.atime_nsec()));
+    section.add("Modified", full(md.mtime(), md.mtime_nsec()));
+    section.add("Changed", full(md.ctime(), md.ctime_nsec()));
+    // not every file system records it
+    let born = match md.created() {
+        Ok(t) => {
+            let t: chrono::DateTime<chrono::Local> = t.into();
+            t.format("%Y-%m-%d %H:%M:%S%.9f %z").to_string()
+        }
+        Err(_) => "unknown".to_owned(),
+    };
+    section.add("Born", born);
+    section
+}
+
+/* #endregion */
+
+/* #region Symlinks */
+
+// the kernel gives up after as many, with ELOOP
+const MAX_LINKS: usize = 40;
+
+/// Follows the link at `path` hop by hop: every link with its target as
+/// written, then the final file, or why there is none.
+fn link_section(path: &Path) -> InfoSection {
+    let mut section = InfoSection::new("Symlink chain");
+    let mut visited = HashSet::new();
+    let mut current = path.to_path_buf();
+    loop {
+        if !visited.insert(current.clone()) || visited.len() > MAX_LINKS {
+            section.add("Result", "loop, the link never resolves".to_owned());
+            break;
+        }
+        let target = match fs::read_link(&current) {
+            Ok(target) => target,
+            Err(err) => {
+                section.add("Result", format!("unreadable: {}", err));
+                break;
+            }
+        };
+        section.add(
+            &format!("{}.", visited.len()),
+            format!(
+                "{} -> {}",
+                escape_name(current.as_os_str()),
+                escape_name(target.as_os_str())
+            ),
+        );
+        current = match current.parent() {
+            Some(parent) => parent.join(&target),
+            None => target,
+        };
+        match fs::symlink_metadata(&current) {
+            Ok(md) if md.file_type().is_symlink() => continue,
+            Ok(md) => {
+                let resolved = fs::canonicalize(&current).unwrap_or(current);
+                section.add(
+                    "Result",
+                    format!("{} ({})", escape_name(resolved.as_os_str()), type_name(&md)),
+                );
+            }
+            Err(err) => section.add(
+                "Result",
+                format!("dangling, {}: {}", escape_name(current.as_os_str()), err),
+            ),
+        }
+        break;
+    }
+    section
+}
+
+/* #endregion */
+
+/* #region MIME */
+
+/// Signatures at the start of a file (or at an offset), with their types.
+const MAGIC: &[(usize, &[u8], &str)] = &[
+    (0, b"%PDF-", "application/pdf"),
+    (0, b"\x89PNG\r\n\x1a\n", "image/png"),
+    (0, b"\xff\xd8\xff", "image/jpeg"),
+    (0, b"GIF87a", "image/gif"),
+    (0, b"GIF89a", "image/gif"),
+    (8, b"WEBP", "image/webp"),
+    (8, b"WAVE", "audio/x-wav"),
+    (0, b"BM", "image/bmp"),
+    (0, b"\x00\x00\x01\x00", "image/vnd.microsoft.icon"),
+    (0, b"PK\x03\x04", "application/zip"),
+    (0, b"\x1f\x8b", "application/gzip"),
+    (0, b"BZh", "application/x-bzip2"),
+    (0, b"\xfd7zXZ\x00", "application/x-xz"),
+    (0, b"\x28\xb5\x2f\xfd", "application/zstd"),
+    (0, b"7z\xbc\xaf\x27\x1c", "application/x-7z-compressed"),
+    (0, b"Rar!\x1a\x07", "application/vnd.rar"),
+    (257, b"ustar", "application/x-tar"),
+    (0, b"ID3", "audio/mpeg"),
+    (0, b"OggS", "audio/ogg"),
+    (0, b"fLaC", "audio/flac"),
+    (4, b"ftyp", "video/mp4"),
+    (0, b"\x1a\x45\xdf\xa3", "video/x-matroska"),
+    (0, b"SQLite format 3\x00", "application/vnd.sqlite3"),
+    (0, b"\x00asm", "application/wasm"),
+    (0, b"%!PS", "application/postscript"),
+    (0, b"<?xml", "text/xml"),
+    (0, b"#!", "text/x-script"),
+];
+
+// enough for the signatures above and to tell text from binary
+const SNIFF_LEN: u64 = 4096;
+
+/// Guesses the MIME type from the first bytes. Only regular files are read,
+/// a FIFO could block forever.
+fn mime_type(path: &Path, md: &Metadata) -> String {
+    let ft = md.file_type();
+    let inode_type = if ft.is_dir() {
+        "inode/directory"
+    } else if ft.is_symlink() {
+        "inode/symlink"
+    } else if ft.is_block_device() {
+        "inode/blockdevice"
+    } else if ft.is_char_device() {
+        "inode/chardevice"
+    } else if ft.is_fifo() {
+        "inode/fifo"
+    } else if ft.is_socket() {
+        "inode/socket"
+    } else if md.len() == 0 {
+        "inode/x-empty"
+    } else {
+        ""
+    };
+    if !inode_type.is_empty() {
+        return inode_type.to_owned();
+    }
+
+    let mut head = Vec::new();
+    let read = File::open(path).and_then(|f| f.take(SNIFF_LEN).read_to_end(&mut head));
+    if let Err(err) = read {
+        return format!("unknown ({})", err);
+    }
+    if head.starts_with(b"\x7fELF") {
+        // e_type, little endian on the machines we run on
+        return match head.get(16) {
+            Some(1) => "application/x-object",
+            Some(2) => "application/x-executable",
+            Some(3) => "application/x-sharedlib",
+            Some(4) => "application/x-coredump",
+            _ => "application/x-elf",
+        }
+        .to_owned();
+    }
+    let magic = MAGIC.iter().find(|(offset, sig, _)| {
+        head.get(*offset..*offset + sig.len())
+            .is_some_and(|bytes| bytes == *sig)
+    });
+    if let Some((_, _, mime)) = magic {
+        return (*mime).to_owned();
+    }
+    if !head.contains(&0) && is_text(&head, md.len() > SNIFF_LEN) {
+        "text/plain".to_owned()
+    } else {
+        "application/octet-stream".to_owned()
+    }
+}
+
+/// Whether the bytes are UTF-8; when `cut`, a character may be split at the
+/// end.
+fn is_text(bytes: &[u8], cut: bool) -> bool {
+    match std::str::from_utf8(bytes) {
+        Ok(_) => true,
+        Err(err) => cut && err.error_len().is_none(),
+    }
+}
+
+/* #endregion */
+
+/* #region Extended attributes */
+
+const ACL_ACCESS: &str = "system.posix_acl_access";
+const ACL_DEFAULT: &str = "system.posix_acl_default";
+const CAPABILITY: &str = "security.capability";
+
+/// Extended attributes of a file (not of a symlink's target), with values.
+struct Xattrs {
+    attrs: io::Result<Vec<(OsString, io::Result<Vec<u8>>)>>,
+}
+
+impl Xattrs {
+    fn read(path: &Path) -> Xattrs {
+        let attrs = CString::new(path.as_os_str().as_bytes())
+            .map_err(io::Error::from)
+            .and_then(|cpath| {
+                let names = list_xattrs(&cpath)?;
+                Ok(names
+                    .into_iter()
+                    .map(|name| {
+                        let value = get_xattr(&cpath, &name);
+                        (name, value)
+                    })
+                    .collect())
+            });
+        Xattrs { attrs }
+    }
+
+    fn get(&self, name: &str) -> Option<&io::Result<Vec<u8>>> {
+        let attrs = self.attrs.as_ref().ok()?;
+        attrs
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value)
+    }
+}
+
+fn list_xattrs(cpath: &CString) -> io::Result<Vec<OsString>> {
+    loop {
+        let size = unsafe { libc::llistxattr(cpath.as_ptr(), std::ptr::null_mut(), 0) };
+        if size < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut buf = vec![0u8; size as usize];
+        let size = unsafe {
+            libc::llistxattr(
+                cpath.as_ptr(),
+                buf.as_mut_ptr() as *mut libc::c_char,
+                buf.len(),
+            )
+        };
+        if size < 0 {
+            let err = io::Error::last_os_error();
+            // an attribute was added in the meantime
+            if err.raw_os_error() == Some(libc::ERANGE) {
+                continue;
+            }
+            return Err(err);
+        }
+        buf.truncate(size as usize);
+        let names = buf.split(|b| *b == 0).filter(|name| !name.is_empty());
+        return Ok(names
+            .map(|name| OsStr::from_bytes(name).to_os_string())
+            .collect());
+    }
+}
+
+fn get_xattr(cpath: &CString, name: &OsStr) -> io::Result<Vec<u8>> {
+    let cname = CString::new(name.as_bytes())?;
+    loop {
+        let size =
+            unsafe { libc::lgetxattr(cpath.as_ptr(), cname.as_ptr(), std::ptr::null_mut(), 0) };
+        if size < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut buf = vec![0u8; size as usize];
+        let size = unsafe {
+            libc::lgetxattr(
+                cpath.as_ptr(),
+                cname.as_ptr(),
+                buf.as_mut_ptr() as *mut libc::c_void,
+                buf.len(),
+            )
+        };
+        if size < 0 {
+            let err = io::Error::last_os_error();
+            if err.raw_os_error() == Some(libc::ERANGE) {
+                continue;
+            }
+            return Err(err);
+        }
+        buf.truncate(size as usize);
+        return Ok(buf);
+    }
+}
+
+fn xattrs_section(xattrs: &Xattrs) -> InfoSection {
+    let mut section = InfoSection::new("Extended attributes");
+    match &xattrs.attrs {
+        Ok(attrs) if attrs.is_empty() => section.add("", "none".to_owned()),
+        Ok(attrs) => {
+            for (name, value) in attrs {
+                let value = match value {
+                    Ok(value) => xattr_value_to_str(value),
+                    Err(err) => format!("unreadable: {}", err),
+                };
+                section.add(&escape_name(name), value);
+            }
+        }
+        Err(err) => section.add("", xattr_error(err)),
+    }
+    section
+}
+
+// longer binary values are cut
+const MAX_HEX_BYTES: usize = 32;
+
+/// Text values are shown quoted, others in hex.
+fn xattr_value_to_str(value: &[u8]) -> String {
+    let text = value.strip_suffix(b"\0").unwrap_or(value);
+    if let Ok(text) = std::str::from_utf8(text) {
+        if !text.contains(|c: char| c.is_control()) {
+            return format!("\"{}\"", text);
+        }
+    }
+    let hex: String = value
+        .iter()
+        .take(MAX_HEX_BYTES)
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    match value.len() > MAX_HEX_BYTES {
+        true => format!("0x{}… ({} bytes)", hex, value.len()),
+        false => format!("0x{}", hex),
+    }
+}
+
+fn xattr_error(err: &io::Error) -> String {
+    match err.raw_os_error() {
+        Some(libc::ENOTSUP) => "not supported by the file system".to_owned(),
+        _ => format!("unreadable: {}", err),
+    }
+}
+
+/* #endregion */
+
+/* #region ACL */
+
+// entry tags of the POSIX ACL attributes
+const ACL_USER_OBJ: u16 = 0x01;
+const ACL_USER: u16 = 0x02;
+const ACL_GROUP_OBJ: u16 = 0x04;
+const ACL_GROUP: u16 = 0x08;
+const ACL_MASK: u16 = 0x10;
+const ACL_OTHER: u16 = 0x20;
+
+fn acl_section(xattrs: &Xattrs) -> InfoSection {
+    let mut section = InfoSection::new("ACL");
+    if let Err(err) = &xattrs.attrs {
+        section.add("", xattr_error(err));
+        return section;
+    }
+    for (name, prefix) in [(ACL_ACCESS, ""), (ACL_DEFAULT, "default:")] {
+        match xattrs.get(name) {
+            Some(Ok(value)) => match decode_acl(value) {
+                Some(entries) => {
+                    for entry in entries {
+                        section.add("", format!("{}{}", prefix, entry));
+                    }
+                }
+                None => section.add("", format!("{}malformed", prefix)),
+            },
+            Some(Err(err)) => section.add("", format!("{}unreadable: {}", prefix, err)),
+            None => {}
+        }
+    }
+    if section.fields.is_empty() {
+        section.add("", "none, only the mode bits apply".to_owned());
+    }
+    section
+}
+
+/// Decodes an ACL attribute into entries formatted like `getfacl` does, e.g.
+/// `user:alice:r-x`. The value is a version, then (tag, permissions, id)
+/// entries, all little endian.
+fn decode_acl(value: &[u8]) -> Option<Vec<String>> {
+    let (header, entries) = value.split_first_chunk::<4>()?;
+    if u32::from_le_bytes(*header) != 2 || entries.len() % 8 != 0 {
+        return None;
+    }
+    let entries = entries.chunks(8).map(|e| {
+        let tag = u16::from_le_bytes([e[0], e[1]]);
+        let perm = u16::from_le_bytes([e[2], e[3]]);
+        let id = u32::from_le_bytes([e[4], e[5], e[6], e[7]]);
+        let perms = format!(
+            "{}{}{}",
+            if perm & 4 != 0 { 'r' } else { '-' },
+            if perm & 2 != 0 { 'w' } else { '-' },
+            if perm & 1 != 0 { 'x' } else { '-' },
+        );
+        match tag {
+            ACL_USER_OBJ => format!("user::{}", perms),
+            ACL_USER => {
+                let name = get_user_by_uid(id).map(|u| u.name().to_os_string());
+                format!(
+                    "user:{}:{}",
+                    name.map_or(id.to_string(), |n| escape_name(&n)),
+                    perms
+                )
+            }
+            ACL_GROUP_OBJ => format!("group::{}", perms),
+            ACL_GROUP => {
+                let name = get_group_by_gid(id).map(|g| g.name().to_os_string());
+                format!(
+                    "group:{}:{}",
+                    name.map_or(id.to_string(), |n| escape_name(&n)),
+                    perms
+                )
+            }
+            ACL_MASK => format!("mask::{}", perms),
+            ACL_OTHER => format!("other::{}", perms),
+            _ => format!("unknown tag {:#x}:{}", tag, perms),
+        }
+    });
+    Some(entries.collect())
+}
+
+/* #endregion */
+
+/* #region Capabilities */
+
+/// Capability names by bit number, as in `linux/capability.h`.
+const CAP_NAMES: &[&str] = &[
+    "cap_chown",
+    "cap_dac_override",
+    "cap_dac_read_search",
+    "cap_fowner",
+    "cap_fsetid",
+    "cap_kill",
+    "cap_setgid",
+    "cap_setuid",
+    "cap_setpcap",
+    "cap_linux_immutable",
+    "cap_net_bind_service",
+    "cap_net_broadcast",
+    "cap_net_admin",
+    "cap_net_raw",
+    "cap_ipc_lock",
+    "cap_ipc_owner",
+    "cap_sys_module",
+    "cap_sys_rawio",
+    "cap_sys_chroot",
+    "cap_sys_ptrace",
+    "cap_sys_pacct",
+    "cap_sys_admin",
+    "cap_sys_boot",
+    "cap_sys_nice",
+    "cap_sys_resource",
+    "cap_sys_time",
+    "cap_sys_tty_config",
+    "cap_mknod",
+    "cap_lease",
+    "cap_audit_write",
+    "cap_audit_control",
+    "cap_setfcap",
+    "cap_mac_override",
+    "cap_mac_admin",
+    "cap_syslog",
+    "cap_wake_alarm",
+    "cap_block_suspend",
+    "cap_audit_read",
+    "cap_perfmon",
+    "cap_bpf",
+    "cap_checkpoint_restore",
+];
+
+const VFS_CAP_REVISION_MASK: u32 = 0xff00_0000;
+const VFS_CAP_FLAGS_EFFECTIVE: u32 = 0x01;
+
+fn caps_section(xattrs: &Xattrs) -> InfoSection {
+    let mut section = InfoSection::new("Capabilities");
+    match xattrs.get(CAPABILITY) {
+        Some(Ok(value)) => match decode_caps(value) {
+            Some(caps) => {
+                for (label, value) in caps {
+                    section.add(label, value);
+                }
+            }
+            None => section.add("", "malformed".to_owned()),
+        },
+        Some(Err(err)) => section.add("", format!("unreadable: {}", err)),
+        None => section.add("", "none".to_owned()),
+    }
+    section
+}
+
+/// Decodes file capabilities: a revision with flags, then permitted and
+/// inheritable masks, two 32-bit words each since revision 2. Revision 3
+/// adds the root user id of the user namespace. Each revision has its own
+/// size, like the kernel the value must have exactly that.
+fn decode_caps(value: &[u8]) -> Option<Vec<(&'static str, String)>> {
+    let words: Vec<u32> = value
+        .chunks_exact(4)
+        .map(|w| u32::from_le_bytes([w[0], w[1], w[2], w[3]]))
+        .collect();
+    let magic = *words.first()?;
+    let size = match magic & VFS_CAP_REVISION_MASK {
+        0x0100_0000 => 3,
+        0x0200_0000 => 5,
+        0x0300_0000 => 6,
+        _ => return None,
+    };
+    if value.len() != size * 4 {
+        return None;
+    }
+    let (permitted, inheritable) = match size {
+        3 => (words[1] as u64, words[2] as u64),
+        _ => (
+            words[1] as u64 | (words[3] as u64) << 32,
+            words[2] as u64 | (words[4] as u64) << 32,
+        ),
+    };
+    let effective = magic & VFS_CAP_FLAGS_EFFECTIVE != 0;
+    let mut result = vec![
+        ("Permitted", cap_names(permitted)),
+        ("Inheritable", cap_names(inheritable)),
+        (
+            "Effective",
+            if effective {
+                "the permitted set"
+            } else {
+                "none"
+            }
+            .to_owned(),
+        ),
+    ];
+    if size == 6 {
+        result.push(("Root id", words[5].to_string()));
+    }
+    Some(result)
+}
+
+fn cap_names(mask: u64) -> String {
+    let names: Vec<String> = (0..64)
+        .filter(|bit| mask & (1 << bit) != 0)
+        .map(|bit| match CAP_NAMES.get(bit) {
+            Some(name) => (*name).to_owned(),
+            None => format!("cap_{}", bit),
+        })
+        .collect();
+    match names.is_empty() {
+        true => "none".to_owned(),
+        false => names.join(","),
+    }
+}
+
+/* #endregion */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bytes(words: &[u32]) -> Vec<u8> {
+        words.iter().flat_map(|w| w.to_le_bytes()).collect()
+    }
+
+    fn acl_entry(tag: u16, perm: u16, id: u32) -> Vec<u8> {
+        [
+            &tag.to_le_bytes()[..],
+            &perm.to_le_bytes(),
+            &id.to_le_bytes(),
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn decodes_acls() {
+        let value = [
+            bytes(&[2]),
+            acl_entry(ACL_USER_OBJ, 7, u32::MAX),
+            acl_entry(ACL_USER, 5, 0),
+            acl_entry(ACL_USER, 4, 3_999_999),
+            acl_entry(ACL_GROUP_OBJ, 4, u32::MAX),
+            acl_entry(ACL_GROUP, 6, 3_999_999),
+            acl_entry(ACL_MASK, 5, u32::MAX),
+            acl_entry(ACL_OTHER, 0, u32::MAX),
+            acl_entry(0x40, 1, 0),
+        ]
+        .concat();
+        assert_eq!(
+            decode_acl(&value).unwrap(),
+            [
+                "user::rwx",
+                "user:root:r-x",
+                "user:3999999:r--",
+                "group::r--",
+                "group:3999999:rw-",
+                "mask::r-x",
+                "other::---",
+                "unknown tag 0x40:--x",
+            ]
+        );
+        assert_eq!(decode_acl(&bytes(&[2])).unwrap(), Vec::<String>::new());
+
+        // another version, a cut off entry, no version at all
+        let mut other = value.clone();
+        other[0] = 1;
+        assert!(decode_acl(&other).is_none());
+        assert!(decode_acl(&value[..value.len() - 3]).is_none());
+        assert!(decode_acl(&value[..3]).is_none());
+        assert!(decode_acl(&[]).is_none());
+    }
+
+    fn field(caps: &[(&'static str, String)], label: &str) -> String {
+        caps.iter().find(|c| c.0 == label).unwrap().1.clone()
+    }
+
+    #[test]
+    fn decodes_capabilities() {
+        // revision 1: one word per set
+        let decoded = decode_caps(&bytes(&[0x0100_0000, 1 << 10 | 1, 0])).unwrap();
+        assert_eq!(
+            field(&decoded, "Permitted"),
+            "cap_chown,cap_net_bind_service"
+        );
+        assert_eq!(field(&decoded, "Inheritable"), "none");
+        assert_eq!(field(&decoded, "Effective"), "none");
+        assert_eq!(decoded.len(), 3);
+
+        // revision 2: the upper words hold capabilities 32 and up
+        let decoded = decode_caps(&bytes(&[
+            0x0200_0001,
+            1 << 13,
+            1 << 21,
+            1 << 7 | 1 << 31,
+            0,
+        ]))
+        .unwrap();
+        assert_eq!(field(&decoded, "Permitted"), "cap_net_raw,cap_bpf,cap_63");
+        assert_eq!(field(&decoded, "Inheritable"), "cap_sys_admin");
+        assert_eq!(field(&decoded, "Effective"), "the permitted set");
+        assert_eq!(decoded.len(), 3);
+
+        // revision 3 adds the root id
+        let value = bytes(&[0x0300_0001, 1 << 12, 0, 0, 0, 100_000]);
+        let decoded = decode_caps(&value).unwrap();
+        assert_eq!(field(&decoded, "Permitted"), "cap_net_admin");
+        assert_eq!(field(&decoded, "Root id"), "100000");
+
+        assert!(decode_caps(&value[..20]).is_none());
+        assert!(decode_caps(&value[..22]).is_none());
+        assert!(decode_caps(&[value.as_slice(), &[0]].concat()).is_none());
+        assert!(decode_caps(&bytes(&[0x0200_0000, 1, 0])).is_none());
+        assert!(decode_caps(&bytes(&[0x0400_0000, 1, 0, 0, 0])).is_none());
+        assert!(decode_caps(&[]).is_none());
+    }
+}
@@ -0,0 +1,618 @@
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::bulk_rename::{self, NamePair};
+use crate::common::AppError;
+use crate::file_ops::*;
+use crate::filesystem::{escape_name, percent_decode, percent_encode};
+use crate::trash::{self, TrashEntry};
+
+// older changes are forgotten
+const MAX_CHANGES: usize = 100;
+
+const HEADER: &str = "navigator journal 1";
+
+/// A file operation done from the navigator, with what is needed to reverse
+/// it.
+pub enum Operation {
+    /// A rename, or a move to another directory.
+    Move {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    Copy {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    /// `name` is the entry in `files/` of the trash directory.
+    Trash {
+        original: PathBuf,
+        trash_dir: PathBuf,
+        name: OsString,
+    },
+    /// Several entries of `dir` renamed at once, in any order, e.g. swapping
+    /// names.
+    Renames {
+        dir: PathBuf,
+        names: Vec<NamePair>,
+    },
+}
+
+impl Operation {
+    pub fn trashed(entry: TrashEntry) -> Operation {
+        Operation::Trash {
+            original: entry.original,
+            trash_dir: entry.trash_dir,
+            name: entry.name,
+        }
+    }
+
+    /// The directories whose content the operation changes.
+    pub fn dirs(&self) -> Vec<PathBuf> {
+        let paths = match self {
+            Operation::Move { from, to } | Operation::Copy { from, to } => vec![from, to],
+            Operation::Trash { original, .. } => vec![original],
+            Operation::Renames { dir, .. } => return vec![dir.clone()],
+        };
+        paths
+            .iter()
+            .filter_map(|p| p.parent())
+            .map(Path::to_path_buf)
+            .collect()
+    }
+
+    /// Why the operation can't be reversed now, e.g. because something took
+    /// the place of the original.
+    fn check_undo(&self) -> Result<(), AppError> {
+        match self {
+            Operation::Move { from, to } => check_paths(to, from),
+            // the copy goes to the trash, changed or not
+            Operation::Copy { to, .. } => check_paths(to, Path::new("")),
+            Operation::Trash {
+                original,
+                trash_dir,
+                name,
+            } => check_paths(&trash_dir.join("files").join(name), original),
+            Operation::Renames { dir, names } => check_renames(dir, &bulk_rename::reversed(names)),
+        }
+    }
+
+    fn check_redo(&self) -> Result<(), AppError> {
+        match self {
+            Operation::Move { from, to } | Operation::Copy { from, to } => check_paths(from, to),
+            Operation::Trash { original, .. } => check_paths(original, Path::new("")),
+            Operation::Renames { dir, names } => check_renames(dir, names),
+        }
+    }
+
+    fn undo(&mut self) -> Result<(), AppError> {
+        match self {
+            Operation::Move { from, to } => move_entry(to, from).map_err(path_error(to)),
+            Operation::Copy { to, .. } => trash::trash(to).map(|_| ()),
+            Operation::Trash {
+                original,
+                trash_dir,
+                name,
+            } => trash::restore(&TrashEntry {
+                trash_dir: trash_dir.clone(),
+                name: name.clone(),
+                original: original.clone(),
+                deleted: String::new(),
+            }),
+            Operation::Renames { dir, names } => {
+                bulk_rename::apply(dir, &bulk_rename::reversed(names))
+            }
+        }
+    }
+
+    fn redo(&mut self) -> Result<(), AppError> {
+        match self {
+            Operation::Move { from, to } => move_entry(from, to).map_err(path_error(from)),
+            Operation::Copy { from, to } => copy_all(from, to).map_err(path_error(from)),
+            // in the trash again, likely under another name
+            Operation::Trash { original, .. } => {
+                *self = Operation::trashed(trash::trash(original)?);
+                Ok(())
+            }
+            Operation::Renames { dir, names } => bulk_rename::apply(dir, names),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        let show = |p: &Path| escape_name(p.as_os_str());
+        match self {
+            Operation::Move { from, to } if from.parent() == to.parent() => {
+                let name = to.file_name().unwrap_or_default();
+                format!("rename {} to {}", show(from), escape_name(name))
+            }
+            Operation::Move { from, to } => format!("move {} to {}", show(from), show(to)),
+            Operation::Copy { from, to } => format!("copy {} to {}", show(from), show(to)),
+            Operation::Trash { original, .. } => format!("trash {}", show(original)),
+            Operation::Renames { dir, names } => {
+                format!("rename {} entries in {}", names.len(), show(dir))
+            }
+        }
+    }
+
+    fn to_line(&self) -> String {
+        match self {
+            Operation::Move { from, to } => {
+                format!("move\t{}\t{}", percent_encode(from), percent_encode(to))
+            }
+            Operation::Copy { from, to } => {
+                format!("copy\t{}\t{}", percent_encode(from), percent_encode(to))
+            }
+            Operation::Trash {
+                original,
+                trash_dir,
+                name,
+            } => format!(
+                "trash\t{}\t{}\t{}",
+                percent_encode(original),
+                percent_encode(trash_dir),
+                percent_encode(Path::new(name))
+            ),
+            Operation::Renames { dir, names } => {
+                let mut line = format!("renames\t{}", percent_encode(dir));
+                for (from, to) in names {
+                    line += &format!(
+                        "\t{}\t{}",
+                        percent_encode(Path::new(from)),
+                        percent_encode(Path::new(to))
+                    );
+                }
+                line
+            }
+        }
+    }
+
+    fn parse(line: &str) -> Option<Operation> {
+        let fields: Vec<PathBuf> = line.split('\t').skip(1).map(percent_decode).collect();
+        match (line.split('\t').next()?, fields.as_slice()) {
+            ("move", [from, to]) => Some(Operation::Move {
+                from: from.clone(),
+                to: to.clone(),
+            }),
+            ("copy", [from, to]) => Some(Operation::Copy {
+                from: from.clone(),
+                to: to.clone(),
+            }),
+            ("trash", [original, trash_dir, name]) => Some(Operation::Trash {
+                original: original.clone(),
+                trash_dir: trash_dir.clone(),
+                name: name.clone().into_os_string(),
+            }),
+            ("renames", [dir, names @ ..]) if !names.is_empty() && names.len() % 2 == 0 => {
+                Some(Operation::Renames {
+                    dir: dir.clone(),
+                    names: names
+                        .chunks(2)
+                        .map(|p| (p[0].clone().into_os_string(), p[1].clone().into_os_string()))
+                        .collect(),
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+// `from` must exist and `to` must not, an empty path is not checked
+fn check_paths(from: &Path, to: &Path) -> Result<(), AppError> {
+    if !exists(from) {
+        return Err(AppError::PathError(
+            "no longer exists".to_owned(),
+            escape_name(from.as_os_str()),
+        ));
+    }
+    if !to.as_os_str().is_empty() && exists(to) {
+        return Err(AppError::PathError(
+            "is in the way".to_owned(),
+            escape_name(to.as_os_str()),
+        ));
+    }
+    Ok(())
+}
+
+// the first thing in the way of renaming entries of `dir`
+fn check_renames(dir: &Path, names: &[NamePair]) -> Result<(), AppError> {
+    match bulk_rename::conflicts(dir, names).into_iter().next() {
+        Some(problem) => Err(AppError::StrError(problem)),
+        None => Ok(()),
+    }
+}
+
+fn path_error(path: &Path) -> impl FnOnce(io::Error) -> AppError + '_ {
+    move |err| AppError::PathError(err.to_string(), escape_name(path.as_os_str()))
+}
+
+/// What one command did, undone and redone as a whole.
+pub struct Change {
+    ops: Vec<Operation>,
+}
+
+impl Change {
+    pub fn describe(&self) -> String {
+        match self.ops.as_slice() {
+            [op] => op.describe(),
+            ops => format!("{} and {} more", ops[0].describe(), ops.len() - 1),
+        }
+    }
+
+    pub fn dirs(&self) -> Vec<PathBuf> {
+        let mut dirs: Vec<PathBuf> = self.ops.iter().flat_map(|op| op.dirs()).collect();
+        dirs.sort();
+        dirs.dedup();
+        dirs
+    }
+}
+
+/// The file operations done from the navigator, for undo and redo. It's
+/// saved after every change, so it outlives the session and a crash.
+pub struct Journal {
+    changes: Vec<Change>,
+    // how many of the changes are done, the rest was undone
+    done: usize,
+    path: Option<PathBuf>,
+    // why it couldn't be saved the last time
+    save_error: Option<AppError>,
+}
+
+impl Journal {
+    /// A journal kept only in memory.
+    pub fn new() -> Journal {
+        Journal {
+            changes: Vec::new(),
+            done: 0,
+            path: None,
+            save_error: None,
+        }
+    }
+
+    /// `$XDG_STATE_HOME/navigator/journal`, by default
+    /// `~/.local/state/navigator/journal`.
+    pub fn default_path() -> Option<PathBuf> {
+        let dir = match env::var_os("XDG_STATE_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(env::var_os("HOME")?).join(".local/state"),
+        };
+        Some(dir.join("navigator").join("journal"))
+    }
+
+    /// Loads the journal saved at `path`. A missing or unreadable file gives
+    /// an empty journal, which is saved there.
+    pub fn load(path: PathBuf) -> Journal {
+        let mut journal = Journal::new();
+        if let Ok(text) = fs::read_to_string(&path) {
+            let mut lines = text.lines();
+            if lines.next() == Some(HEADER) {
+                for line in lines {
+                    if let Some(done) = line.strip_prefix("done ") {
+                        journal.done = done.parse().unwrap_or(0);
+                    } else if line == "change" {
+                        journal.changes.push(Change { ops: Vec::new() });
+                    } else if let (Some(change), Some(op)) =
+                        (journal.changes.last_mut(), Operation::parse(line))
+                    {
+                        change.ops.push(op);
+                    }
+                }
+            }
+        }
+        journal.changes.retain(|c| !c.ops.is_empty());
+        journal.done = journal.done.min(journal.changes.len());
+        journal.path = Some(path);
+        journal
+    }
+
+    /// Adds what a command did. Changes undone before can't be redone anymore.
+    pub fn record(&mut self, ops: Vec<Operation>) {
+        if ops.is_empty() {
+            return;
+        }
+        self.changes.truncate(self.done);
+        self.changes.push(Change { ops });
+        if self.changes.len() > MAX_CHANGES {
+            self.changes.remove(0);
+        }
+        self.done = self.changes.len();
+        self.save();
+    }
+
+    /// Reverses the last change which is done, `None` if there is none.
+    /// Nothing is touched if any of its operations can't be reversed.
+    pub fn undo(&mut self) -> Option<Result<&Change, AppError>> {
+        if self.done == 0 {
+            return None;
+        }
+        let change = &mut self.changes[self.done - 1];
+        if let Err(err) = change.ops.iter().rev().try_for_each(Operation::check_undo) {
+            return Some(Err(err));
+        }
+        for i in (0..change.ops.len()).rev() {
+            if let Err(err) = change.ops[i].undo() {
+                // put back what was already undone
+                for op in &mut change.ops[i + 1..] {
+                    let _ = op.redo();
+                }
+                return Some(Err(err));
+            }
+        }
+        self.done -= 1;
+        self.save();
+        Some(Ok(&self.changes[self.done]))
+    }
+
+    /// Does again the first change which was undone, `None` if there is none.
+    pub fn redo(&mut self) -> Option<Result<&Change, AppError>> {
+        if self.done == self.changes.len() {
+            return None;
+        }
+        let change = &mut self.changes[self.done];
+        if let Err(err) = change.ops.iter().try_for_each(Operation::check_redo) {
+            return Some(Err(err));
+        }
+        for i in 0..change.ops.len() {
+            if let Err(err) = change.ops[i].redo() {
+                for op in change.ops[..i].iter_mut().rev() {
+                    let _ = op.undo();
+                }
+                return Some(Err(err));
+            }
+        }
+        self.done += 1;
+        self.save();
+        Some(Ok(&self.changes[self.done - 1]))
+    }
+
+    /// Why saving the journal failed, once after each failure.
+    pub fn take_save_error(&mut self) -> Option<AppError> {
+        self.save_error.take()
+    }
+
+    /// Writes the journal to a temporary file which then replaces the old
+    /// one, so a crash leaves either of them whole.
+    fn save(&mut self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let mut text = format!("{}\ndone {}\n", HEADER, self.done);
+        for change in &self.changes {
+            text += "change\n";
+            for op in &change.ops {
+                text += &op.to_line();
+                text.push('\n');
+            }
+        }
+        let tmp = path.with_extension("tmp");
+        let result = (|| {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            let mut file = fs::File::create(&tmp)?;
+            file.write_all(text.as_bytes())?;
+            file.sync_data()?;
+            fs::rename(&tmp, path)
+        })();
+        if let Err(err) = result {
+            self.save_error = Some(AppError::PathError(
+                format!("cannot save the journal: {}", err),
+                escape_name(path.as_os_str()),
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+    use std::process;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("navigator-journal-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn copy(from: &str, to: &str) -> Operation {
+        Operation::Copy {
+            from: PathBuf::from(from),
+            to: PathBuf::from(to),
+        }
+    }
+
+    fn moved(from: &Path, to: &Path) -> Operation {
+        Operation::Move {
+            from: from.to_path_buf(),
+            to: to.to_path_buf(),
+        }
+    }
+
+    fn lines(journal: &Journal) -> Vec<Vec<String>> {
+        journal
+            .changes
+            .iter()
+            .map(|c| c.ops.iter().map(Operation::to_line).collect())
+            .collect()
+    }
+
+    #[test]
+    fn operations_round_trip() {
+        let odd = OsStr::from_bytes(b"a\tb\nc%41 \xff");
+        let ops = vec![
+            moved(&Path::new("/d").join(odd), Path::new("/e/f")),
+            copy("/d/x", "/d/y"),
+            Operation::Trash {
+                original: Path::new("/d").join(odd),
+                trash_dir: PathBuf::from("/home/u/.local/share/Trash"),
+                name: odd.to_owned(),
+            },
+            Operation::Renames {
+                dir: PathBuf::from("/d"),
+                names: vec![
+                    (odd.to_owned(), OsString::from("b")),
+                    (OsString::from("b"), odd.to_owned()),
+                ],
+            },
+        ];
+        for op in &ops {
+            let line = op.to_line();
+            assert!(!line.contains('\n'), "{}", line);
+            let parsed = Operation::parse(&line).unwrap();
+            assert_eq!(parsed.to_line(), line);
+        }
+        assert_eq!(ops[0].to_line(), "move\t/d/a%09b%0Ac%2541%20%FF\t/e/f");
+        let Some(Operation::Trash { name, .. }) = Operation::parse(&ops[2].to_line()) else {
+            panic!("not a trash operation");
+        };
+        assert_eq!(name, odd);
+    }
+
+    #[test]
+    fn bad_lines_are_not_operations() {
+        for line in [
+            "",
+            "move",
+            "move\t/a",
+            "move\t/a\t/b\t/c",
+            "jump\t/a\t/b",
+            "renames\t/d",
+            "renames\t/d\ta",
+        ] {
+            assert!(Operation::parse(line).is_none(), "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn loads_what_it_saves() {
+        let dir = temp_dir("load");
+        let path = dir.join("state").join("journal");
+        let mut journal = Journal::load(path.clone());
+        journal.record(vec![copy("/a", "/b"), copy("/c", "/d")]);
+        journal.record(vec![copy("/e", "/f")]);
+        journal.done = 1;
+        journal.save();
+        assert!(journal.take_save_error().is_none());
+
+        let loaded = Journal::load(path.clone());
+        assert_eq!(lines(&loaded), lines(&journal));
+        assert_eq!(loaded.done, 1);
+        assert!(!path.with_extension("tmp").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn loads_bad_and_truncated_files() {
+        let dir = temp_dir("bad");
+        let path = dir.join("journal");
+        let load = |text: &str| {
+            fs::write(&path, text).unwrap();
+            Journal::load(path.clone())
+        };
+
+        let journal = load("navigator journal 2\ndone 1\nchange\ncopy\t/a\t/b\n");
+        assert!(journal.changes.is_empty());
+        assert_eq!(journal.done, 0);
+
+        // cut off in the middle of a line, and more done than there are
+        let journal = load(&format!(
+            "{}\ndone 7\ncopy\t/x\t/y\nchange\ncopy\t/a\t/b\nchange\ncopy\t/c",
+            HEADER
+        ));
+        assert_eq!(lines(&journal), [["copy\t/a\t/b"]]);
+        assert_eq!(journal.done, 1);
+
+        let journal = load(&format!("{}\ndone many\nchange\ncopy\t/a\t/b\n", HEADER));
+        assert_eq!(journal.changes.len(), 1);
+        assert_eq!(journal.done, 0);
+
+        let journal = load("");
+        assert!(journal.changes.is_empty());
+        assert_eq!(journal.path.as_deref(), Some(path.as_path()));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn keeps_the_last_changes() {
+        let mut journal = Journal::new();
+        for i in 0..MAX_CHANGES + 5 {
+            journal.record(vec![copy(&format!("/{}", i), "/x")]);
+        }
+        assert_eq!(journal.changes.len(), MAX_CHANGES);
+        assert_eq!(journal.done, MAX_CHANGES);
+        assert_eq!(journal.changes[0].describe(), "copy /5 to /x");
+
+        // a new change drops the undone ones
+        journal.done = 10;
+        journal.record(vec![copy("/new", "/x")]);
+        assert_eq!(journal.changes.len(), 11);
+        assert_eq!(journal.done, 11);
+        journal.record(Vec::new());
+        assert_eq!(journal.changes.len(), 11);
+    }
+
+    #[test]
+    fn failed_undo_puts_back_what_was_undone() {
+        let dir = temp_dir("undo");
+        fs::write(dir.join("b"), "b").unwrap();
+        fs::write(dir.join("d"), "d").unwrap();
+        let mut journal = Journal::new();
+        // the first move came from a directory which is gone since
+        journal.record(vec![
+            moved(&dir.join("gone").join("a"), &dir.join("b")),
+            moved(&dir.join("c"), &dir.join("d")),
+        ]);
+
+        assert!(journal.undo().unwrap().is_err());
+        assert_eq!(journal.done, 1);
+        assert!(dir.join("b").exists());
+        assert!(dir.join("d").exists());
+        assert!(!dir.join("c").exists());
+
+        // something in the way is found before anything is moved
+        fs::write(dir.join("c"), "c").unwrap();
+        fs::create_dir(dir.join("gone")).unwrap();
+        assert!(journal.undo().unwrap().is_err());
+        assert!(dir.join("b").exists());
+        fs::remove_file(dir.join("c")).unwrap();
+
+        assert!(journal.undo().unwrap().is_ok());
+        assert_eq!(journal.done, 0);
+        assert_eq!(fs::read(dir.join("gone").join("a")).unwrap(), b"b");
+        assert_eq!(fs::read(dir.join("c")).unwrap(), b"d");
+        assert!(journal.undo().is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn failed_redo_puts_back_what_was_redone() {
+        let dir = temp_dir("redo");
+        fs::write(dir.join("a"), "a").unwrap();
+        fs::write(dir.join("c"), "c").unwrap();
+        let mut journal = Journal::new();
+        journal.record(vec![
+            moved(&dir.join("a"), &dir.join("b")),
+            moved(&dir.join("c"), &dir.join("gone").join("d")),
+        ]);
+        journal.done = 0;
+
+        assert!(journal.redo().unwrap().is_err());
+        assert_eq!(journal.done, 0);
+        assert!(dir.join("a").exists());
+        assert!(!dir.join("b").exists());
+        assert!(dir.join("c").exists());
+
+        fs::create_dir(dir.join("gone")).unwrap();
+        assert!(journal.redo().unwrap().is_ok());
+        assert_eq!(journal.done, 1);
+        assert!(dir.join("b").exists());
+        assert!(dir.join("gone").join("d").exists());
+        assert!(journal.redo().is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,797 @@
+use ncurses::*;
+
+/// Something the user can do. Keys are translated to actions by the
+/// `Dispatcher`, according to the active `Keymap`.
+#[derive(Copy, Clone, PartialEq)]
+pub enum Action {
+    Help,
+    Quit,
+    Abandon,
+    SwitchFocus,
+    ExportTree,
+    GoHome,
+    Search,
+    SearchNext,
+    SearchPrev,
+    Command,
+    Inspect,
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    HalfPageUp,
+    HalfPageDown,
+    Top,
+    Bottom,
+    Parent,
+    Expand,
+    Collapse,
+    FirstChild,
+    NextSibling,
+    PrevSibling,
+    ToggleDiskUsage,
+    Delete,
+    Enter,
+    ComputeSize,
+    ComputeAllSizes,
+    ToggleMark,
+    EditPermissions,
+    MoveToTrash,
+    ShowTrash,
+    Restore,
+    Purge,
+    Copy,
+    Move,
+    Rename,
+    BulkRename,
+    Undo,
+    Redo,
+    Refresh,
+}
+
+/// Names used in `map` config lines, and descriptions for the help.
+const ACTIONS: &[(Action, &str, &str)] = &[
+    (Action::Help, "help", "show this help"),
+    (
+        Action::Quit,
+        "quit",
+        "quit and write the selected directory",
+    ),
+    (Action::Abandon, "abandon", "quit without writing anything"),
+    (
+        Action::SwitchFocus,
+        "switch_focus",
+        "switch to the next pane",
+    ),
+    (
+        Action::ExportTree,
+        "export_tree",
+        "export the expanded tree as JSON",
+    ),
+    (Action::GoHome, "go_home", "go to the home directory"),
+    (Action::Search, "search", "search names in the pane"),
+    (Action::SearchNext, "search_next", "next search match"),
+    (Action::SearchPrev, "search_prev", "previous search match"),
+    (
+        Action::Command,
+        "command",
+        "command line (:cd DIR, :undo, :redo, :q, :q!)",
+    ),
+    (
+        Action::Inspect,
+        "inspect",
+        "show the file's metadata, ACLs and attributes",
+    ),
+    (Action::Up, "up", "move up"),
+    (Action::Down, "down", "move down"),
+    (Action::PageUp, "page_up", "move a screen up"),
+    (Action::PageDown, "page_down", "move a screen down"),
+    (Action::HalfPageUp, "half_page_up", "move half a screen up"),
+    (
+        Action::HalfPageDown,
+        "half_page_down",
+        "move half a screen down",
+    ),
+    (Action::Top, "top", "go to the first line"),
+    (Action::Bottom, "bottom", "go to the last line"),
+    (Action::Parent, "parent", "go to the parent directory"),
+    (Action::Expand, "expand", "expand the directory"),
+    (
+        Action::Collapse,
+        "collapse",
+        "collapse the directory, or go to the parent",
+    ),
+    (
+        Action::FirstChild,
+        "first_child",
+        "go to the first subdirectory",
+    ),
+    (
+        Action::NextSibling,
+        "next_sibling",
+        "go to the next sibling",
+    ),
+    (
+        Action::PrevSibling,
+        "prev_sibling",
+        "go to the previous sibling",
+    ),
+    (
+        Action::ToggleDiskUsage,
+        "toggle_disk_usage",
+        "toggle the disk usage mode",
+    ),
+    (
+        Action::Delete,
+        "delete",
+        "delete the directory (disk usage mode)",
+    ),
+    (Action::Enter, "enter", "enter the directory or archive"),
+    (
+        Action::ComputeSize,
+        "compute_size",
+        "compute the size of the directory",
+    ),
+    (
+        Action::ComputeAllSizes,
+        "compute_all_sizes",
+        "compute all sizes and sort by size",
+    ),
+    (
+        Action::ToggleMark,
+        "toggle_mark",
+        "mark or unmark the entry and move down",
+    ),
+    (
+        Action::EditPermissions,
+        "edit_permissions",
+        "change permissions and owner (marked entries)",
+    ),
+    (
+        Action::MoveToTrash,
+        "trash",
+        "move to the trash (marked entries)",
+    ),
+    (Action::ShowTrash, "show_trash", "open or close the trash"),
+    (
+        Action::Restore,
+        "restore",
+        "restore the entry to where it was deleted from",
+    ),
+    (
+        Action::Purge,
+        "purge",
+        "delete the entry from the trash for good",
+    ),
+    (
+        Action::Copy,
+        "copy",
+        "copy or extract to another directory (marked entries)",
+    ),
+    (
+        Action::Move,
+        "move",
+        "move to another directory (marked entries)",
+    ),
+    (Action::Rename, "rename", "rename the entry"),
+    (
+        Action::BulkRename,
+        "bulk_rename",
+        "rename the entries in $EDITOR (marked or all)",
+    ),
+    (Action::Undo, "undo", "undo the last file operation"),
+    (Action::Redo, "redo", "redo the last undone file operation"),
+    (
+        Action::Refresh,
+        "refresh",
+        "read the directory and the git status again",
+    ),
+];
+
+impl Action {
+    pub fn parse(s: &str) -> Option<Action> {
+        ACTIONS.iter().find(|a| a.1 == s).map(|a| a.0)
+    }
+
+    pub fn name(&self) -> &'static str {
+        ACTIONS.iter().find(|a| a.0 == *self).map_or("", |a| a.1)
+    }
+
+    pub fn description(&self) -> &'static str {
+        ACTIONS.iter().find(|a| a.0 == *self).map_or("", |a| a.2)
+    }
+
+    /// Whether the action may change entries on the file system, which other
+    /// panes then have to read again.
+    pub fn changes_files(&self) -> bool {
+        matches!(
+            self,
+            Action::Delete
+                | Action::EditPermissions
+                | Action::MoveToTrash
+                | Action::Restore
+                | Action::Copy
+                | Action::Move
+                | Action::Rename
+                | Action::BulkRename
+                | Action::Undo
+                | Action::Redo
+                | Action::Command
+        )
+    }
+
+    /// Whether the action only works with entries on the local file system:
+    /// it changes them, or looks at what the tree doesn't know of them.
+    pub fn local_only(&self) -> bool {
+        matches!(
+            self,
+            Action::Inspect
+                | Action::ComputeSize
+                | Action::ComputeAllSizes
+                | Action::ToggleDiskUsage
+                | Action::Delete
+                | Action::EditPermissions
+                | Action::MoveToTrash
+                | Action::Move
+                | Action::Rename
+                | Action::BulkRename
+        )
+    }
+}
+
+/// Where a binding applies: everywhere, or only in one of the panes.
+#[derive(Copy, Clone, PartialEq)]
+pub enum Context {
+    Global,
+    Tree,
+    List,
+    /// The trash browser, shown instead of the list.
+    Trash,
+}
+
+impl Context {
+    pub fn parse(s: &str) -> Option<Context> {
+        match s {
+            "global" => Some(Context::Global),
+            "tree" => Some(Context::Tree),
+            "list" => Some(Context::List),
+            "trash" => Some(Context::Trash),
+            _ => None,
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            Context::Global => "Global",
+            Context::Tree => "Tree",
+            Context::List => "List",
+            Context::Trash => "Trash",
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Profile {
+    Default,
+    Vim,
+}
+
+impl Profile {
+    pub fn parse(s: &str) -> Option<Profile> {
+        match s {
+            "default" => Some(Profile::Default),
+            "vim" => Some(Profile::Vim),
+            _ => None,
+        }
+    }
+}
+
+const ESC: i32 = 27;
+const ENTER: i32 = 10;
+const TAB: i32 = 9;
+
+const fn ctrl(c: char) -> i32 {
+    c as i32 & 0x1f
+}
+
+const fn ch(c: char) -> i32 {
+    c as i32
+}
+
+/// Keys with a name, written in angle brackets, e.g. `<PgDn>`.
+const NAMED_KEYS: &[(&str, i32)] = &[
+    ("Esc", ESC),
+    ("Enter", ENTER),
+    ("Tab", TAB),
+    ("Space", 32),
+    ("lt", 60),
+    ("Up", KEY_UP),
+    ("Down", KEY_DOWN),
+    ("Left", KEY_LEFT),
+    ("Right", KEY_RIGHT),
+    ("PgUp", KEY_PPAGE),
+    ("PgDn", KEY_NPAGE),
+    ("Home", KEY_HOME),
+    ("End", KEY_END),
+    ("Backspace", KEY_BACKSPACE),
+    ("Delete", KEY_DC),
+];
+
+fn parse_key_name(name: &str) -> Option<i32> {
+    if let Some(&(_, key)) = NAMED_KEYS.iter().find(|k| k.0 == name) {
+        return Some(key);
+    }
+    if let Some(c) = name.strip_prefix("C-") {
+        let mut chars = c.chars();
+        return match (chars.next(), chars.next()) {
+            (Some(c), None) if c.is_ascii_lowercase() => Some(ctrl(c)),
+            _ => None,
+        };
+    }
+    match name.strip_prefix('F').and_then(|n| n.parse::<i32>().ok()) {
+        Some(n) if (1..=63).contains(&n) => Some(KEY_F(n as u8)),
+        _ => None,
+    }
+}
+
+/// Parses a key sequence written like in vim: characters stand for
+/// themselves, other keys are named in angle brackets, e.g. `gg`, `<C-d>`,
+/// `<F10>`.
+pub fn parse_keys(s: &str) -> Result<Vec<i32>, String> {
+    let mut keys = Vec::new();
+    let mut rest = s;
+    while let Some(c) = rest.chars().next() {
+        if c == '<' {
+            if let Some(end) = rest.find('>') {
+                let name = &rest[1..end];
+                keys.push(parse_key_name(name).ok_or(format!("unknown key '<{}>'", name))?);
+                rest = &rest[end + 1..];
+                continue;
+            }
+        }
+        if !c.is_ascii_graphic() {
+            return Err(format!("unsupported key '{}'", c));
+        }
+        keys.push(c as i32);
+        rest = &rest[1..];
+    }
+    if keys.is_empty() {
+        return Err("no keys given".to_owned());
+    }
+    Ok(keys)
+}
+
+/// A readable name of a key code, in the notation of `parse_keys`.
+pub fn key_name(key: i32) -> String {
+    if let Some((name, _)) = NAMED_KEYS.iter().find(|k| k.1 == key) {
+        return format!("<{}>", name);
+    }
+    match key {
+        1..=26 => format!("<C-{}>", (b'a' + key as u8 - 1) as char),
+        33..=126 => (key as u8 as char).to_string(),
+        k if (KEY_F0 + 1..=KEY_F0 + 63).contains(&k) => format!("<F{}>", k - KEY_F0),
+        k => format!("<{}>", k),
+    }
+}
+
+/// Keys bound to one or more actions; several actions make a macro, run one
+/// after another.
+#[derive(Clone)]
+pub struct Binding {
+    pub context: Context,
+    pub keys: Vec<i32>,
+    pub actions: Vec<Action>,
+}
+
+impl Binding {
+    /// Parses `CONTEXT KEYS ACTION[,ACTION...]`, e.g. `list x down,enter`.
+    /// The action `none` removes the binding.
+    pub fn parse(s: &str) -> Result<Binding, String> {
+        let mut words = s.split_whitespace();
+        let (Some(context), Some(keys), Some(actions), None) =
+            (words.next(), words.next(), words.next(), words.next())
+        else {
+            return Err("expected 'CONTEXT KEYS ACTION'".to_owned());
+        };
+        let context = Context::parse(context).ok_or(format!("unknown context '{}'", context))?;
+        let keys = parse_keys(keys)?;
+        let actions = match actions {
+            "none" => Vec::new(),
+            _ => actions
+                .split(',')
+                .map(|a| Action::parse(a).ok_or(format!("unknown action '{}'", a)))
+                .collect::<Result<_, _>>()?,
+        };
+        Ok(Binding {
+            context,
+            keys,
+            actions,
+        })
+    }
+}
+
+/// A line of the key help: the keys doing the same, the names of the actions
+/// and what they do.
+pub struct HelpEntry {
+    pub keys: String,
+    pub actions: String,
+    pub description: String,
+}
+
+/// The bindings of one context.
+pub struct HelpSection {
+    pub title: &'static str,
+    pub entries: Vec<HelpEntry>,
+}
+
+pub struct Keymap {
+    bindings: Vec<Binding>,
+}
+
+impl Keymap {
+    pub fn new(profile: Profile) -> Keymap {
+        let mut keymap = Keymap {
+            bindings: Vec::new(),
+        };
+        keymap.add_defaults(profile);
+        if profile == Profile::Vim {
+            keymap.add_vim();
+        }
+        keymap
+    }
+
+    /// Adds a binding, replacing the one with the same keys and context.
+    pub fn bind(&mut self, binding: Binding) {
+        self.bindings
+            .retain(|b| b.context != binding.context || b.keys != binding.keys);
+        if !binding.actions.is_empty() {
+            self.bindings.push(binding);
+        }
+    }
+
+    fn add(&mut self, context: Context, keys: &[i32], action: Action) {
+        self.bind(Binding {
+            context,
+            keys: keys.to_vec(),
+            actions: vec![action],
+        });
+    }
+
+    fn add_defaults(&mut self, profile: Profile) {
+        use Action::*;
+        use Context::*;
+        self.add(Global, &[KEY_F(1)], Help);
+        self.add(Global, &[ch('?')], Help);
+        self.add(Global, &[KEY_F(10)], Quit);
+        // in vim Esc only cancels, quitting without a result is `:q!`
+        if profile != Profile::Vim {
+            self.add(Global, &[ESC], Abandon);
+        }
+        self.add(Global, &[TAB], SwitchFocus);
+        self.add(Global, &[KEY_F(2)], ExportTree);
+        self.add(Global, &[ch('i')], Inspect);
+        self.add(Global, &[ch('P')], EditPermissions);
+        self.add(Global, &[ch('T')], ShowTrash);
+        self.add(Global, &[KEY_F(5)], Copy);
+        self.add(Global, &[KEY_F(6)], Move);
+        self.add(Global, &[ch('U')], Undo);
+        self.add(Global, &[ctrl('r')], Redo);
+        self.add(Global, &[ctrl('l')], Refresh);
+        self.add(Global, &[KEY_UP], Up);
+        self.add(Global, &[KEY_DOWN], Down);
+        self.add(Global, &[KEY_PPAGE], PageUp);
+        self.add(Global, &[KEY_NPAGE], PageDown);
+        self.add(Global, &[KEY_HOME], Top);
+        self.add(Global, &[KEY_END], Bottom);
+        self.add(Global, &[KEY_BACKSPACE], Parent);
+
+        self.add(Tree, &[KEY_RIGHT], Expand);
+        self.add(Tree, &[KEY_LEFT], Collapse);
+        self.add(Tree, &[ch('p')], Parent);
+        self.add(Tree, &[ch('c')], FirstChild);
+        self.add(Tree, &[ch(']')], NextSibling);
+        self.add(Tree, &[ch('[')], PrevSibling);
+        self.add(Tree, &[ch('u')], ToggleDiskUsage);
+        self.add(Tree, &[KEY_DC], Delete);
+
+        self.add(List, &[ENTER], Enter);
+        self.add(List, &[ch('s')], ComputeSize);
+        self.add(List, &[ch('S')], ComputeAllSizes);
+        self.add(List, &[ch(' ')], ToggleMark);
+        self.add(List, &[KEY_DC], MoveToTrash);
+        self.add(List, &[ch('R')], Rename);
+        self.add(List, &[ch('B')], BulkRename);
+
+        self.add(Trash, &[ESC], ShowTrash);
+        self.add(Trash, &[ch('q')], ShowTrash);
+        self.add(Trash, &[ch('r')], Restore);
+        self.add(Trash, &[KEY_DC], Purge);
+    }
+
+    fn add_vim(&mut self) {
+        use Action::*;
+        use Context::*;
+        self.add(Global, &[ch('q')], Quit);
+        self.add(Global, &[ch(':')], Command);
+        self.add(Global, &[ch('/')], Search);
+        self.add(Global, &[ch('n')], SearchNext);
+        self.add(Global, &[ch('N')], SearchPrev);
+        self.add(Global, &[ch('~')], GoHome);
+        self.add(Global, &[ch('k')], Up);
+        self.add(Global, &[ch('j')], Down);
+        self.add(Global, &[ch('g'), ch('g')], Top);
+        self.add(Global, &[ch('G')], Bottom);
+        self.add(Global, &[ctrl('u')], HalfPageUp);
+        self.add(Global, &[ctrl('d')], HalfPageDown);
+        self.add(Global, &[ctrl('b')], PageUp);
+        self.add(Global, &[ctrl('f')], PageDown);
+
+        self.add(Tree, &[ch('h')], Collapse);
+        self.add(Tree, &[ch('l')], Expand);
+
+        self.add(List, &[ch('h')], Parent);
+        self.add(List, &[ch('l')], Enter);
+        self.add(List, &[ch('d'), ch('d')], MoveToTrash);
+        self.add(List, &[ch('u')], Undo);
+    }
+
+    /// Describes the bindings by context. Keys doing the same are listed
+    /// together, e.g. `<Up>, k`.
+    pub fn help(&self) -> Vec<HelpSection> {
+        [
+            Context::Global,
+            Context::Tree,
+            Context::List,
+            Context::Trash,
+        ]
+        .iter()
+        .map(|context| {
+            let mut groups: Vec<(&[Action], Vec<String>)> = Vec::new();
+            for b in self.bindings.iter().filter(|b| b.context == *context) {
+                let keys: String = b.keys.iter().map(|k| key_name(*k)).collect();
+                match groups.iter_mut().find(|g| g.0 == b.actions.as_slice()) {
+                    Some(group) => group.1.push(keys),
+                    None => groups.push((&b.actions, vec![keys])),
+                }
+            }
+            let entries = groups
+                .into_iter()
+                .map(|(actions, keys)| {
+                    let names: Vec<_> = actions.iter().map(|a| a.name()).collect();
+                    let description = match actions {
+                        [action] => action.description().to_owned(),
+                        _ => format!("macro: {}", names.join(", ")),
+                    };
+                    HelpEntry {
+                        keys: keys.join(", "),
+                        actions: names.join(","),
+                        description,
+                    }
+                })
+                .collect();
+            HelpSection {
+                title: context.title(),
+                entries,
+            }
+        })
+        .collect()
+    }
+}
+
+pub enum Dispatch {
+    /// The key starts a count or a longer sequence.
+    Pending,
+    /// A complete sequence: its actions and the repeat count.
+    Actions(Vec<Action>, usize),
+    /// Nothing is bound to the keys.
+    Unbound,
+}
+
+const MAX_COUNT: usize = 9999;
+
+/// Collects keys until they form a bound sequence. Like in vim, a number
+/// typed first is a repeat count (e.g. `5j`).
+pub struct Dispatcher {
+    keymap: Keymap,
+    keys: Vec<i32>,
+    count: Option<usize>,
+}
+
+impl Dispatcher {
+    pub fn new(keymap: Keymap) -> Dispatcher {
+        Dispatcher {
+            keymap,
+            keys: Vec::new(),
+            count: None,
+        }
+    }
+
+    /// Bindings of the focused pane win over global ones. A sequence which is
+    /// bound and also starts a longer one is taken as soon as it's complete.
+    pub fn feed(&mut self, key: i32, context: Context) -> Dispatch {
+        if self.keys.is_empty() {
+            if let Some(digit) = char::from_u32(key as u32).and_then(|c| c.to_digit(10)) {
+                if digit > 0 || self.count.is_some() {
+                    let count = self.count.unwrap_or(0) * 10 + digit as usize;
+                    self.count = Some(count.min(MAX_COUNT));
+                    return Dispatch::Pending;
+                }
+            }
+        }
+        self.keys.push(key);
+
+        let candidates: Vec<&Binding> = self
+            .keymap
+            .bindings
+            .iter()
+            .filter(|b| b.context == context || b.context == Context::Global)
+            .filter(|b| b.keys.starts_with(&self.keys))
+            .collect();
+        let exact = candidates
+            .iter()
+            .filter(|b| b.keys == self.keys)
+            .min_by_key(|b| b.context == Context::Global);
+        if let Some(binding) = exact {
+            let actions = binding.actions.clone();
+            let count = self.count.unwrap_or(1);
+            self.reset();
+            return Dispatch::Actions(actions, count);
+        }
+        if candidates.is_empty() {
+            self.reset();
+            return Dispatch::Unbound;
+        }
+        Dispatch::Pending
+    }
+
+    pub fn keymap(&self) -> &Keymap {
+        &self.keymap
+    }
+
+    pub fn reset(&mut self) {
+        self.keys.clear();
+        self.count = None;
+    }
+
+    /// The count and keys typed so far, e.g. `5g`.
+    pub fn pending(&self) -> Option<String> {
+        if self.keys.is_empty() && self.count.is_none() {
+            return None;
+        }
+        let mut text = self.count.map(|c| c.to_string()).unwrap_or_default();
+        for key in &self.keys {
+            text += &key_name(*key);
+        }
+        Some(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // feeds the keys, all but the last must leave the sequence pending
+    fn feed(
+        dispatcher: &mut Dispatcher,
+        keys: &str,
+        context: Context,
+    ) -> Option<(Vec<Action>, usize)> {
+        let keys = parse_keys(keys).unwrap();
+        let (last, first) = keys.split_last().unwrap();
+        for key in first {
+            assert!(matches!(dispatcher.feed(*key, context), Dispatch::Pending));
+        }
+        match dispatcher.feed(*last, context) {
+            Dispatch::Actions(actions, count) => Some((actions, count)),
+            Dispatch::Pending => panic!("still pending"),
+            Dispatch::Unbound => None,
+        }
+    }
+
+    fn vim() -> Dispatcher {
+        Dispatcher::new(Keymap::new(Profile::Vim))
+    }
+
+    #[test]
+    fn counts() {
+        let mut d = vim();
+        assert!(feed(&mut d, "j", Context::List) == Some((vec![Action::Down], 1)));
+        assert!(feed(&mut d, "5j", Context::List) == Some((vec![Action::Down], 5)));
+        assert!(feed(&mut d, "10<Down>", Context::Tree) == Some((vec![Action::Down], 10)));
+        assert!(feed(&mut d, "123456k", Context::List) == Some((vec![Action::Up], MAX_COUNT)));
+        // a zero first is a key, not a count
+        assert!(feed(&mut d, "0", Context::List).is_none());
+        assert!(d.pending().is_none());
+    }
+
+    #[test]
+    fn sequences() {
+        let mut d = vim();
+        assert!(feed(&mut d, "3gg", Context::Tree) == Some((vec![Action::Top], 3)));
+        assert!(matches!(d.feed(ch('2'), Context::List), Dispatch::Pending));
+        assert!(matches!(d.feed(ch('d'), Context::List), Dispatch::Pending));
+        assert_eq!(d.pending().as_deref(), Some("2d"));
+        assert!(feed(&mut d, "d", Context::List) == Some((vec![Action::MoveToTrash], 2)));
+
+        // a wrong key drops the sequence and the count
+        assert!(feed(&mut d, "5gx", Context::List).is_none());
+        assert!(d.pending().is_none());
+        assert!(feed(&mut d, "j", Context::List) == Some((vec![Action::Down], 1)));
+
+        // dd is only bound in the list
+        assert!(matches!(d.feed(ch('d'), Context::Tree), Dispatch::Unbound));
+        assert!(matches!(d.feed(ch('d'), Context::List), Dispatch::Pending));
+        d.reset();
+        assert!(d.pending().is_none());
+    }
+
+    #[test]
+    fn pane_bindings_win_over_global_ones() {
+        let mut d = Dispatcher::new(Keymap::new(Profile::Default));
+        let del = |d: &mut Dispatcher, context| feed(d, "<Delete>", context).unwrap().0;
+        assert!(del(&mut d, Context::Tree) == [Action::Delete]);
+        assert!(del(&mut d, Context::List) == [Action::MoveToTrash]);
+        assert!(del(&mut d, Context::Trash) == [Action::Purge]);
+        // global bindings apply in every pane
+        for context in [Context::Tree, Context::List, Context::Trash] {
+            assert!(feed(&mut d, "i", context) == Some((vec![Action::Inspect], 1)));
+        }
+        assert!(feed(&mut d, "s", Context::Tree).is_none());
+        assert!(feed(&mut d, "q", Context::List).is_none());
+        assert!(feed(&mut d, "q", Context::Trash) == Some((vec![Action::ShowTrash], 1)));
+    }
+
+    #[test]
+    fn remapped_keys_and_macros() {
+        let mut keymap = Keymap::new(Profile::Default);
+        for line in [
+            "list x down,enter",
+            "global x up",
+            "global i none",
+            "tree <C-d> half_page_down",
+        ] {
+            keymap.bind(Binding::parse(line).unwrap());
+        }
+        let mut d = Dispatcher::new(keymap);
+        assert!(feed(&mut d, "2x", Context::List) == Some((vec![Action::Down, Action::Enter], 2)));
+        assert!(feed(&mut d, "x", Context::Tree) == Some((vec![Action::Up], 1)));
+        assert!(feed(&mut d, "i", Context::List).is_none());
+        assert!(feed(&mut d, "<C-d>", Context::Tree) == Some((vec![Action::HalfPageDown], 1)));
+
+        let help = d.keymap().help();
+        let list = help.iter().find(|s| s.title == "List").unwrap();
+        let entry = list.entries.iter().find(|e| e.keys == "x").unwrap();
+        assert_eq!(entry.actions, "down,enter");
+        assert_eq!(entry.description, "macro: down, enter");
+    }
+
+    #[test]
+    fn bad_bindings() {
+        for (line, err) in [
+            ("list x", "expected 'CONTEXT KEYS ACTION'"),
+            ("list x up down", "expected 'CONTEXT KEYS ACTION'"),
+            ("pane x up", "unknown context 'pane'"),
+            ("list <Nope> up", "unknown key '<Nope>'"),
+            ("list <C-1> up", "unknown key '<C-1>'"),
+            ("list x up,fly", "unknown action 'fly'"),
+        ] {
+            assert_eq!(Binding::parse(line).err().as_deref(), Some(err));
+        }
+    }
+
+    #[test]
+    fn key_names() {
+        let keys = parse_keys("g<lt><C-d><F10><PgDn><Space>").unwrap();
+        assert_eq!(
+            keys,
+            [ch('g'), ch('<'), ctrl('d'), KEY_F(10), KEY_NPAGE, ch(' ')]
+        );
+        let names: String = keys.iter().map(|k| key_name(*k)).collect();
+        assert_eq!(names, "g<lt><C-d><F10><PgDn><Space>");
+        // a bracket which doesn't close is the key itself
+        assert_eq!(parse_keys("<x").unwrap(), [ch('<'), ch('x')]);
+        assert!(parse_keys("").is_err());
+        assert!(parse_keys("é").is_err());
+        assert!(parse_keys("<F64>").is_err());
+    }
+}
@@ -1,100 +1,697 @@
+mod archive;
+mod bulk_rename;
+mod cli;
+mod colors;
 mod common;
+mod config;
+mod dir_size;
+mod file_ops;
 mod filesystem;
+mod git;
+mod inspect;
+mod journal;
+mod keymap;
+mod permissions;
+mod provider;
 mod screen;
+mod sftp;
+mod trash;
 mod tree;
 mod tree_node;
+mod export {
+    pub mod json;
+    pub mod text_tree;
+    pub mod walk;
+}
 mod graph {
+    pub mod columns;
     pub mod display;
+    pub mod help;
+    pub mod inspector;
     pub mod list_view;
+    pub mod perm_editor;
+    pub mod popup;
+    pub mod prompt;
+    pub mod trash_view;
     pub mod tree_view;
 }
 
+use std::env;
+use std::ffi::{OsStr, OsString};
 use std::fs::File;
 use std::io::Write;
+use std::os::unix::ffi::OsStrExt;
 use std::path::{Path, PathBuf};
 use std::process::ExitCode;
 use std::{cell::RefCell, rc::Rc};
-use std::{env, iter};
 
+use cli::*;
+use colors::*;
 use common::*;
-use graph::{display::*, list_view::*, tree_view::*};
+use config::*;
+use export::{json::*, text_tree::*, walk::WalkOptions};
+use filesystem::{escape_name, NodeType};
+use graph::{
+    display::*, help::show_help, inspector::show_inspector, list_view::*,
+    perm_editor::edit_permissions, popup::*, prompt::read_line, trash_view::TrashView,
+    tree_view::*,
+};
+use journal::Journal;
+use keymap::*;
 use ncurses::*;
+use provider::FsProvider;
 use screen::*;
+use sftp::{Sftp, SshUrl};
 use tree::*;
 
-fn run(screen: &Screen) -> Result<PathBuf, AppError> {
-    let tree = Rc::new(RefCell::new(Tree::new()));
+// entries in the trash are only restored or purged
+const NOT_IN_TRASH: &str = "Not in the trash, restore the entry first";
 
-    let args: Vec<String> = env::args().collect();
-    if let Some(path) = args.get(1) {
-        let _ = tree.borrow_mut().go_to_path(&PathBuf::from(path));
-    } else {
-        if let Ok(path) = env::current_dir() {
-            let _ = tree.borrow_mut().go_to_path(&path);
-        }
+fn run(
+    screen: &Screen,
+    config: &Config,
+    colors: ColorScheme,
+    start_path: &Path,
+    remote: Option<Rc<dyn FsProvider>>,
+) -> Result<PathBuf, AppError> {
+    let mut tree = open_tree(config, start_path, remote.clone());
+    // nothing local to undo on a remote host
+    if let (None, Some(path)) = (&remote, Journal::default_path()) {
+        tree.journal = Rc::new(RefCell::new(Journal::load(path)));
     }
+    let tree = Rc::new(RefCell::new(tree));
 
-    let tree_view = Rc::new(RefCell::new(TreeView::new(tree.clone())));
-    let list_view = Rc::new(RefCell::new(ListView::new(tree.clone())));
+    let colors = Rc::new(colors);
+    let tree_view = Rc::new(RefCell::new(TreeView::new(tree.clone(), colors.clone())));
+    let list_view = Rc::new(RefCell::new(ListView::new(
+        tree.clone(),
+        config.column_layout(),
+        colors.clone(),
+    )));
 
     tree.borrow_mut().tree_view = Rc::downgrade(&tree_view);
     tree.borrow_mut().list_view = Rc::downgrade(&list_view);
 
-    let left_displ = Rc::new(RefCell::new(Display::new(
-        tree_view.clone(),
-        &screen.tree_win,
-        &screen.tw_size,
-    )));
-    let right_displ = Rc::new(RefCell::new(Display::new(
-        list_view.clone(),
-        &screen.list_win,
-        &screen.lw_size,
-    )));
+    let mut displays: Vec<Rc<RefCell<Display>>> = Vec::new();
+    // the tree each display shows
+    let mut pane_trees: Vec<Rc<RefCell<Tree>>> = Vec::new();
+    let mut trees = vec![tree.clone()];
+    let mut list_views = vec![list_view.clone()];
+    if let Some(tree_win) = &screen.tree_win {
+        displays.push(Rc::new(RefCell::new(Display::new(
+            tree_view.clone(),
+            tree_win,
+            &screen.tw_size,
+        ))));
+        pane_trees.push(tree.clone());
+    }
+    if let Some(list_win) = &screen.list_win {
+        displays.push(Rc::new(RefCell::new(Display::new(
+            list_view.clone(),
+            list_win,
+            &screen.lw_size,
+        ))));
+        pane_trees.push(tree.clone());
+    }
+    // the second list of the dual layout has a tree of its own
+    if let Some(list_win) = &screen.second_list_win {
+        let mut second = open_tree(config, start_path, remote.clone());
+        second.journal = tree.borrow().journal.clone();
+        let second = Rc::new(RefCell::new(second));
+        let view = Rc::new(RefCell::new(ListView::new(
+            second.clone(),
+            config.column_layout(),
+            colors.clone(),
+        )));
+        second.borrow_mut().list_view = Rc::downgrade(&view);
+        displays.push(Rc::new(RefCell::new(Display::new(
+            view.clone(),
+            list_win,
+            &screen.slw_size,
+        ))));
+        pane_trees.push(second.clone());
+        trees.push(second);
+        list_views.push(view);
+    }
 
-    let mut focused_displ = left_displ.clone();
-    focused_displ.borrow_mut().active = true;
-    left_displ.borrow_mut().display(true)?;
-    loop {
-        left_displ.borrow_mut().display(false)?;
-        right_displ.borrow_mut().display(false)?;
-        display_status(&screen, &tree.borrow());
+    // what the trash browser replaced while it's open
+    let mut hidden_content: Option<Rc<RefCell<dyn DisplContent>>> = None;
+    let mut focused = 0;
+    let mut dispatcher = Dispatcher::new(config.key_bindings());
+    displays[focused].borrow_mut().active = true;
+    displays[focused].borrow_mut().display(true)?;
+    'main: loop {
+        // actions work on the tree of the focused pane
+        let tree = pane_trees[focused].clone();
+        for t in &trees {
+            t.borrow_mut().update_git();
+        }
+        for displ in &displays {
+            displ.borrow_mut().display(false)?;
+        }
+        let message = match dispatcher.pending() {
+            Some(keys) => Some(keys),
+            None => tree.borrow_mut().status_msg.take(),
+        };
+        display_status(screen, &tree.borrow(), message);
 
+        let busy = trees
+            .iter()
+            .any(|t| t.borrow().size_jobs.busy() || t.borrow().git.busy());
+        timeout(if busy { 100 } else { -1 });
         let ch: i32 = getch();
-
-        if ch == KEY_F(10) {
-            break;
+        for t in &trees {
+            t.borrow_mut().poll_sizes();
         }
-        if ch == 27 {
-            return Err(AppError::StrError("Abandoned.".to_owned()));
+        if let Some(error) = remote.as_ref().and_then(|fs| fs.take_error()) {
+            let lines: Vec<PopupLine> = error
+                .lines()
+                .map(|l| PopupLine::Text(l.to_owned()))
+                .collect();
+            show_popup("Connection lost", &lines);
+            screen.redraw();
         }
-        if ch == '\t' as i32 {
-            focused_displ.borrow_mut().active = false;
-            focused_displ = if Rc::ptr_eq(&focused_displ, &left_displ) {
-                right_displ.clone()
-            } else {
-                left_displ.clone()
-            };
-            focused_displ.borrow_mut().active = true;
+        if ch == ERR {
+            continue;
+        }
+        if ch == KEY_MOUSE {
+            dispatcher.reset();
+            process_mouse(&displays, &mut focused)?;
             tree_view.borrow_mut().modif_flags.print = true;
-            list_view.borrow_mut().modif_flags.print = true;
+            for lv in &list_views {
+                lv.borrow_mut().modif_flags.print = true;
+            }
             continue;
         }
 
-        focused_displ.borrow().process_key(ch)?;
+        let context = displays[focused].borrow().context();
+        let (actions, count) = match dispatcher.feed(ch, context) {
+            Dispatch::Actions(actions, count) => (actions, count),
+            Dispatch::Pending | Dispatch::Unbound => continue,
+        };
+        let changes_files = actions.iter().any(Action::changes_files);
+        for action in actions {
+            if action.local_only() && !tree.borrow().curr_dir().borrow().is_local() {
+                tree.borrow_mut().status_msg =
+                    Some("Not on the local file system, F5 copies entries out".to_owned());
+                break;
+            }
+            match action {
+                Action::Quit => break 'main,
+                Action::Abandon => return Err(AppError::StrError("Abandoned.".to_owned())),
+                Action::Help => {
+                    show_help(dispatcher.keymap());
+                    screen.redraw();
+                }
+                Action::Inspect => {
+                    // the directory under the cursor in the tree pane
+                    let node = match displays[focused].borrow().context() {
+                        Context::List => tree.borrow().curr_file(),
+                        Context::Trash => {
+                            tree.borrow_mut().status_msg = Some(NOT_IN_TRASH.to_owned());
+                            break;
+                        }
+                        _ => Some(tree.borrow().curr_dir()),
+                    };
+                    let Some(node) = node else { break };
+                    let path = node.borrow().get_path();
+                    if let Err(err) = show_inspector(&path) {
+                        tree.borrow_mut().status_msg = Some(err.to_string());
+                    }
+                    screen.redraw();
+                }
+                Action::EditPermissions => {
+                    let nodes = match displays[focused].borrow().context() {
+                        Context::List => tree.borrow().marked_files(),
+                        Context::Trash => {
+                            tree.borrow_mut().status_msg = Some(NOT_IN_TRASH.to_owned());
+                            break;
+                        }
+                        _ => vec![tree.borrow().curr_dir()],
+                    };
+                    let change = edit_permissions(screen, &nodes);
+                    screen.redraw();
+                    let Some(change) = change else { break };
+                    if change.is_empty() {
+                        tree.borrow_mut().status_msg = Some("Nothing to change".to_owned());
+                        break;
+                    }
+                    let report = tree.borrow_mut().change_attrs(&nodes, &change);
+                    let message = match report.failures.len() {
+                        0 => format!("Changed {} entries", report.changed),
+                        failed => {
+                            let lines: Vec<PopupLine> = report
+                                .failures
+                                .iter()
+                                .map(|(path, err)| {
+                                    let path = escape_name(path.as_os_str());
+                                    PopupLine::Text(format!("{}: {}", path, err))
+                                })
+                                .collect();
+                            show_popup("Failed changes", &lines);
+                            screen.redraw();
+                            format!("Changed {} entries, failed for {}", report.changed, failed)
+                        }
+                    };
+                    tree.borrow_mut().status_msg = Some(message);
+                }
+                Action::ShowTrash => {
+                    // the trash is shown in the list pane, or the only one
+                    let idx = displays.len() - 1;
+                    match hidden_content.take() {
+                        Some(content) => {
+                            displays[idx].borrow_mut().set_content(content);
+                        }
+                        None => {
+                            let view = TrashView::new(pane_trees[idx].clone(), colors.clone());
+                            let content = displays[idx]
+                                .borrow_mut()
+                                .set_content(Rc::new(RefCell::new(view)));
+                            hidden_content = Some(content);
+                            set_focus(&displays, &mut focused, idx);
+                        }
+                    }
+                    tree_view.borrow_mut().modif_flags.print = true;
+                    for lv in &list_views {
+                        lv.borrow_mut().modif_flags.render = true;
+                        lv.borrow_mut().modif_flags.print = true;
+                    }
+                }
+                Action::Copy | Action::Move => {
+                    let nodes = match displays[focused].borrow().context() {
+                        Context::List => tree.borrow().marked_files(),
+                        Context::Trash => {
+                            tree.borrow_mut().status_msg = Some(NOT_IN_TRASH.to_owned());
+                            break;
+                        }
+                        _ => vec![tree.borrow().curr_dir()],
+                    };
+                    let verb = if action == Action::Copy {
+                        "Copy"
+                    } else {
+                        "Move"
+                    };
+                    let prompt = match nodes.as_slice() {
+                        [] => break,
+                        [node] => {
+                            let name = escape_name(&node.borrow().sys_node.name);
+                            format!("{} {} to: ", verb, name)
+                        }
+                        _ => format!("{} {} entries to: ", verb, nodes.len()),
+                    };
+                    // in the dual layout, the directory of the other list; a
+                    // lossy copy of a name that is not UTF-8 would go elsewhere
+                    let other = trees.iter().find(|t| !Rc::ptr_eq(t, &tree));
+                    let initial = match other {
+                        Some(other) if other.borrow().curr_dir().borrow().is_local() => {
+                            let path = other.borrow().curr_path();
+                            path.to_str().unwrap_or_default().to_owned()
+                        }
+                        _ => String::new(),
+                    };
+                    let Some(dest) = read_line(&prompt, &initial) else {
+                        break;
+                    };
+                    if dest.trim().is_empty() {
+                        break;
+                    }
+                    // copies from elsewhere go to the local file system
+                    let dest = match local_path(&tree.borrow(), dest.trim()) {
+                        Ok(dest) => dest,
+                        Err(err) => {
+                            tree.borrow_mut().status_msg =
+                                Some(format!("{} failed: {}", verb, err));
+                            break;
+                        }
+                    };
+                    tree.borrow_mut()
+                        .transfer(&nodes, &dest, action == Action::Copy);
+                }
+                Action::Rename => {
+                    let Some(node) = tree.borrow().curr_file() else {
+                        break;
+                    };
+                    let name = node.borrow().sys_node.name.clone();
+                    // the prompt can only edit text; a lossy copy would rename it
+                    let Some(text) = name.to_str() else {
+                        tree.borrow_mut().status_msg = Some(format!(
+                            "Cannot rename {}: the name is not valid UTF-8",
+                            escape_name(&name)
+                        ));
+                        break;
+                    };
+                    let Some(new_name) = read_line("Rename to: ", text) else {
+                        break;
+                    };
+                    if new_name != text {
+                        tree.borrow_mut().rename(&node, OsStr::new(&new_name));
+                    }
+                }
+                Action::BulkRename => {
+                    let nodes = tree.borrow().marked_or_all();
+                    if nodes.is_empty() {
+                        break;
+                    }
+                    let dir = tree.borrow().curr_path();
+                    let names: Vec<OsString> = nodes
+                        .iter()
+                        .map(|n| n.borrow().sys_node.name.clone())
+                        .collect();
+                    screen.suspend();
+                    let edited = bulk_rename::edit_names(&names);
+                    screen.resume();
+                    let text = match edited {
+                        Ok(text) => text,
+                        Err(err) => {
+                            tree.borrow_mut().status_msg = Some(err.to_string());
+                            break;
+                        }
+                    };
+                    let names = match bulk_rename::plan(&dir, &names, &text) {
+                        Ok(names) => names,
+                        Err(problems) => {
+                            let lines: Vec<PopupLine> =
+                                problems.into_iter().map(PopupLine::Text).collect();
+                            show_popup("Cannot rename", &lines);
+                            screen.redraw();
+                            tree.borrow_mut().status_msg = Some("Nothing renamed".to_owned());
+                            break;
+                        }
+                    };
+                    if names.is_empty() {
+                        tree.borrow_mut().status_msg = Some("No names changed".to_owned());
+                        break;
+                    }
+                    let lines: Vec<PopupLine> = names
+                        .iter()
+                        .map(|(from, to)| {
+                            PopupLine::Text(format!("{} -> {}", escape_name(from), escape_name(to)))
+                        })
+                        .collect();
+                    let question = format!("Rename {} entries? (y/n)", names.len());
+                    let confirmed = ask_popup("Bulk rename", &lines, &question);
+                    screen.redraw();
+                    if confirmed {
+                        tree.borrow_mut().bulk_rename(&dir, names);
+                    }
+                }
+                Action::Undo => tree.borrow_mut().undo(),
+                Action::Redo => tree.borrow_mut().redo(),
+                Action::Refresh => {
+                    tree.borrow_mut().refresh();
+                    screen.redraw();
+                }
+                Action::SwitchFocus => {
+                    let next = (focused + 1) % displays.len();
+                    set_focus(&displays, &mut focused, next);
+                    tree_view.borrow_mut().modif_flags.print = true;
+                    for lv in &list_views {
+                        lv.borrow_mut().modif_flags.print = true;
+                    }
+                }
+                Action::ExportTree => {
+                    let Some(path) = read_line("Export to: ", &config.export_path) else {
+                        break;
+                    };
+                    if path.trim().is_empty() {
+                        break;
+                    }
+                    let path = match local_path(&tree.borrow(), path.trim()) {
+                        Ok(path) => path,
+                        Err(err) => {
+                            tree.borrow_mut().status_msg = Some(format!("Export failed: {}", err));
+                            break;
+                        }
+                    };
+                    let message = match export_expanded(&tree.borrow(), &path) {
+                        Ok(()) => format!(
+                            "Expanded tree exported to {}",
+                            escape_name(path.as_os_str())
+                        ),
+                        Err(err) => format!("Export failed: {}", err),
+                    };
+                    tree.borrow_mut().status_msg = Some(message);
+                }
+                Action::GoHome => {
+                    let result = change_dir(&mut tree.borrow_mut(), "~");
+                    if let Err(err) = result {
+                        tree.borrow_mut().status_msg = Some(err.to_string());
+                    }
+                }
+                Action::Search => {
+                    let Some(pattern) = read_line("/", "") else {
+                        break;
+                    };
+                    // an empty pattern searches for the previous one again
+                    if !pattern.is_empty() {
+                        tree.borrow_mut().search = Some(pattern);
+                    }
+                    displays[focused]
+                        .borrow()
+                        .process_action(Action::SearchNext, count)?;
+                }
+                Action::Command => {
+                    let Some(line) = read_line(":", "") else {
+                        break;
+                    };
+                    let result = run_command(&mut tree.borrow_mut(), line.trim());
+                    match result {
+                        Ok(Some(Action::Quit)) => break 'main,
+                        Ok(Some(Action::Abandon)) => {
+                            return Err(AppError::StrError("Abandoned.".to_owned()))
+                        }
+                        Ok(_) => {}
+                        Err(err) => tree.borrow_mut().status_msg = Some(err.to_string()),
+                    }
+                }
+                action => displays[focused].borrow().process_action(action, count)?,
+            }
+        }
+        if changes_files {
+            for t in &trees {
+                t.borrow_mut().git.invalidate();
+            }
+            // the other list may show what changed
+            let path = tree.borrow().curr_path();
+            for other in trees.iter().filter(|t| !Rc::ptr_eq(t, &tree)) {
+                let own = other.borrow().curr_path();
+                other.borrow_mut().reload_dir(&path);
+                if own != path {
+                    other.borrow_mut().reload_dir(&own);
+                }
+            }
+        }
     }
-    let x = Ok(tree.borrow().curr_path());
+    let x = Ok(pane_trees[focused].borrow().curr_path());
     x
 }
 
+/// A tree showing `start_path`, on `remote` if given.
+fn open_tree(config: &Config, start_path: &Path, remote: Option<Rc<dyn FsProvider>>) -> Tree {
+    let mut tree = Tree::new(
+        config.load_options(),
+        config.one_file_system,
+        config.git && remote.is_none(),
+    );
+    if let Some(fs) = remote {
+        tree.mount_root(fs);
+    }
+    let _ = tree.go_to_path(start_path);
+    tree
+}
+
+fn set_focus(displays: &[Rc<RefCell<Display>>], focused: &mut usize, new: usize) {
+    displays[*focused].borrow_mut().active = false;
+    *focused = new;
+    displays[*focused].borrow_mut().active = true;
+}
+
+const WHEEL_STEP: usize = 3;
+
+fn process_mouse(displays: &[Rc<RefCell<Display>>], focused: &mut usize) -> Result<(), AppError> {
+    let mut event = MEVENT {
+        id: 0,
+        x: 0,
+        y: 0,
+        z: 0,
+        bstate: 0,
+    };
+    if getmouse(&mut event) != OK {
+        return Ok(());
+    }
+    let Some(idx) = displays
+        .iter()
+        .position(|d| d.borrow().encloses(event.y, event.x))
+    else {
+        return Ok(());
+    };
+    set_focus(displays, focused, idx);
+
+    let displ = displays[idx].borrow();
+    let bstate = event.bstate as mmask_t;
+    if bstate & BUTTON4_PRESSED as mmask_t != 0 {
+        displ.process_action(Action::Up, WHEEL_STEP)?;
+    } else if bstate & BUTTON5_PRESSED as mmask_t != 0 {
+        displ.process_action(Action::Down, WHEEL_STEP)?;
+    } else if bstate & BUTTON1_DOUBLE_CLICKED as mmask_t != 0 {
+        displ.click(event.y, true)?;
+    } else if bstate & BUTTON1_CLICKED as mmask_t != 0 {
+        displ.click(event.y, false)?;
+    }
+    Ok(())
+}
+
+/// Runs a `:` command line. Commands which end the program are returned as
+/// the corresponding action.
+fn run_command(tree: &mut Tree, line: &str) -> Result<Option<Action>, AppError> {
+    let (cmd, arg) = match line.split_once(char::is_whitespace) {
+        Some((cmd, arg)) => (cmd, arg.trim()),
+        None => (line, ""),
+    };
+    match cmd {
+        "" => {}
+        "q" | "quit" => return Ok(Some(Action::Quit)),
+        "q!" | "quit!" => return Ok(Some(Action::Abandon)),
+        "cd" => change_dir(tree, arg)?,
+        "undo" => tree.undo(),
+        "redo" => tree.redo(),
+        _ => return Err(AppError::StrError(format!("Not a command: {}", cmd))),
+    }
+    Ok(None)
+}
+
+/// Goes to a directory given like in the shell: relative to the current one,
+/// `~` for home, nothing for home as well.
+fn change_dir(tree: &mut Tree, arg: &str) -> Result<(), AppError> {
+    let path = expand_path(tree, arg);
+    // a remote host's paths can't be resolved here
+    let local = tree.root.borrow().is_local();
+    match local {
+        true => tree.jump_to_path(&resolve_dir(Some(&path))?),
+        false => tree.jump_to_path(&path),
+    }
+}
+
+/// A path on the local file system given like in the shell. Relative to the
+/// current directory if that is a local one, else to the working directory.
+fn local_path(tree: &Tree, arg: &str) -> Result<PathBuf, AppError> {
+    let home = env::var_os("HOME").map(PathBuf::from);
+    Ok(match (arg, home) {
+        ("~", Some(home)) => home,
+        (arg, Some(home)) if arg.starts_with("~/") => home.join(&arg[2..]),
+        (arg, _) if tree.curr_dir().borrow().is_local() => tree.curr_path().join(arg),
+        (arg, _) => env::current_dir()?.join(arg),
+    })
+}
+
+/// A path given like in the shell, see `change_dir`. `~` is the home
+/// directory on the file system the tree shows, e.g. on a remote host.
+fn expand_path(tree: &Tree, arg: &str) -> PathBuf {
+    let home = tree.root.borrow().dir_location().0.home().ok();
+    match (arg, home) {
+        ("" | "~", Some(home)) => home,
+        (arg, Some(home)) if arg.starts_with("~/") => home.join(&arg[2..]),
+        (arg, _) => tree.curr_path().join(arg),
+    }
+}
+
 fn main() -> ExitCode {
-    let screen = Screen::create();
-    init_app_colors();
-    let result = run(&screen);
+    let opts = match parse_args(env::args_os().skip(1).collect()) {
+        Ok(Command::Run(opts)) => opts,
+        Ok(Command::Help) => {
+            print!("{}", usage());
+            return ExitCode::SUCCESS;
+        }
+        Ok(Command::Version) => {
+            println!("{}", version());
+            return ExitCode::SUCCESS;
+        }
+        Err(err) => {
+            eprintln!("{}: {}", env!("CARGO_PKG_NAME"), err);
+            eprintln!(
+                "Try '{} --help' for more information.",
+                env!("CARGO_PKG_NAME")
+            );
+            return ExitCode::from(2);
+        }
+    };
+
+    let mut config = match Config::load(opts.config_path.as_deref()) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}: {}", env!("CARGO_PKG_NAME"), err);
+            return ExitCode::from(2);
+        }
+    };
+    opts.apply_to(&mut config);
+
+    let url = match opts
+        .start_path
+        .as_deref()
+        .and_then(|p| SshUrl::parse(p.as_os_str()))
+    {
+        Some(Ok(url)) => Some(url),
+        Some(Err(err)) => {
+            eprintln!("{}: {}", env!("CARGO_PKG_NAME"), err);
+            return ExitCode::from(2);
+        }
+        None => None,
+    };
+    // a remote one is looked up once connected
+    let start_path = match url {
+        Some(_) => PathBuf::from("/"),
+        None => match resolve_dir(opts.start_path.as_deref()) {
+            Ok(path) => path,
+            Err(err) => {
+                eprintln!("{}: {}", env!("CARGO_PKG_NAME"), err);
+                return ExitCode::from(2);
+            }
+        },
+    };
+
+    if opts.mode == Mode::ListKeys {
+        print!("{}", keys_help(&config.key_bindings()));
+        return ExitCode::SUCCESS;
+    }
+
+    let (start_path, remote) = match &url {
+        Some(url) => match connect(url, &config) {
+            Ok((path, fs)) => (path, Some(fs)),
+            Err(err) => {
+                eprintln!("{}: {}", env!("CARGO_PKG_NAME"), err);
+                return ExitCode::from(2);
+            }
+        },
+        None => (start_path, None),
+    };
+
+    if opts.mode != Mode::Interactive {
+        return print_mode(&opts, &config, &start_path, remote);
+    }
+
+    let palette = match Palette::for_theme(config.theme, config.dircolors.as_deref()) {
+        Ok(palette) => palette,
+        Err(err) => {
+            eprintln!("{}: {}", env!("CARGO_PKG_NAME"), err);
+            return ExitCode::from(2);
+        }
+    };
+
+    let screen = Screen::create(config.layout);
+    if config.mouse {
+        screen.enable_mouse();
+    }
+    let colors = ColorScheme::init(palette);
+    let result = run(&screen, &config, colors, &start_path, remote);
     screen.close();
 
     match result {
         Ok(path) => {
-            if let Err(err) = write_output(&path) {
+            let path = match &url {
+                Some(url) => url.with_path(&path),
+                None => path.into_os_string(),
+            };
+            if let Err(err) = write_output(&path, &opts.output) {
                 eprintln!("{}", err);
                 return ExitCode::FAILURE;
             }
@@ -108,26 +705,184 @@ fn main() -> ExitCode {
     ExitCode::SUCCESS
 }
 
-fn write_output(path: &Path) -> Result<(), AppError> {
-    let mut file = File::create("/tmp/navigator.dir")?;
-    file.write_all(path.to_string_lossy().as_bytes())?;
+/// Writes the expanded tree as JSON to a new file, an existing one is left
+/// alone.
+fn export_expanded(tree: &Tree, path: &Path) -> Result<(), AppError> {
+    let file = File::create_new(path)
+        .map_err(|err| AppError::PathError(err.to_string(), escape_name(path.as_os_str())))?;
+    let mut file = std::io::BufWriter::new(file);
+    let opts = WalkOptions::default();
+    JsonExporter::new(&mut file, JsonFormat::Tree, &opts, ChildSource::Expanded)
+        .export(&tree.root)?;
+    file.flush()?;
+    Ok(())
+}
+
+/// Connects to the host of `url`, returns the start directory there and the
+/// host's file system.
+fn connect(url: &SshUrl, config: &Config) -> Result<(PathBuf, Rc<dyn FsProvider>), AppError> {
+    let sftp = Sftp::connect(url, &config.ssh_command)?;
+    let path_error = |err: std::io::Error| {
+        AppError::PathError(
+            err.to_string(),
+            url.with_path(&url.path).to_string_lossy().into(),
+        )
+    };
+    let path = sftp.realpath(&url.path).map_err(path_error)?;
+    if sftp.stat(&path).map_err(path_error)?.typ != NodeType::Dir {
+        return Err(path_error(std::io::Error::other("not a directory")));
+    }
+    Ok((path, Rc::new(sftp)))
+}
+
+fn print_mode(
+    opts: &Options,
+    config: &Config,
+    start_path: &Path,
+    remote: Option<Rc<dyn FsProvider>>,
+) -> ExitCode {
+    let load_opts = config.load_options();
+    let mut tree = Tree::new(load_opts.clone(), config.one_file_system, false);
+    if let Some(fs) = remote {
+        tree.mount_root(fs);
+    }
+    if let Err(err) = tree.go_to_path(start_path) {
+        eprintln!("{}: {}", env!("CARGO_PKG_NAME"), err);
+        return ExitCode::FAILURE;
+    }
+    let mut out = std::io::BufWriter::new(std::io::stdout().lock());
+    let result = match opts.mode {
+        Mode::Json(format) => {
+            let source = ChildSource::Load(&load_opts);
+            JsonExporter::new(&mut out, format, &opts.walk, source).export(&tree.curr_dir())
+        }
+        _ => {
+            let label = match &opts.start_path {
+                Some(path) => escape_name(path.as_os_str()),
+                None => ".".to_owned(),
+            };
+            let style = match opts.charset {
+                Charset::Unicode => &UNICODE_LINES,
+                Charset::Ascii => &ASCII_LINES,
+            };
+            TreePrinter::new(&mut out, style, &opts.walk, &load_opts)
+                .print(&tree.curr_dir(), &label)
+        }
+    };
+    match result.and_then(|_| out.flush()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) if err.kind() == std::io::ErrorKind::BrokenPipe => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{}: {}", env!("CARGO_PKG_NAME"), err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// The active key bindings as text, section by section.
+fn keys_help(keymap: &Keymap) -> String {
+    let mut text = String::new();
+    for section in keymap.help() {
+        text += &format!("{}:\n", section.title);
+        let keys_width = section
+            .entries
+            .iter()
+            .map(|e| e.keys.len())
+            .max()
+            .unwrap_or(0);
+        let names_width = section
+            .entries
+            .iter()
+            .map(|e| e.actions.len())
+            .max()
+            .unwrap_or(0);
+        for e in &section.entries {
+            text += &format!(
+                "  {:kw$}  {:nw$}  {}\n",
+                e.keys,
+                e.actions,
+                e.description,
+                kw = keys_width,
+                nw = names_width
+            );
+        }
+        text += "\n";
+    }
+    text
+}
+
+fn resolve_dir(path: Option<&Path>) -> Result<PathBuf, AppError> {
+    let path = match path {
+        Some(path) => path.to_path_buf(),
+        None => env::current_dir()?,
+    };
+    match path.canonicalize() {
+        Ok(p) if p.is_dir() => Ok(p),
+        Ok(_) => Err(AppError::PathError(
+            "not a directory".to_owned(),
+            path.to_string_lossy().to_string(),
+        )),
+        Err(err) => Err(AppError::PathError(
+            err.to_string(),
+            path.to_string_lossy().to_string(),
+        )),
+    }
+}
+
+fn write_output(path: &OsStr, output: &OutputMode) -> Result<(), AppError> {
+    match output {
+        OutputMode::File(file_path) => {
+            let mut file = File::create(file_path)?;
+            file.write_all(path.as_bytes())?;
+        }
+        OutputMode::None => {}
+    }
     Ok(())
 }
 
-fn display_status(screen: &Screen, tree: &Tree) {
+fn display_status(screen: &Screen, tree: &Tree, message: Option<String>) {
     let win = screen.status_win;
     wmove(win, 0, 0);
     wattr_on(win, A_REVERSE);
-    for ch in tree
-        .curr_path()
-        .to_string_lossy()
-        .to_string()
-        .chars()
-        .chain(iter::repeat(' '))
-        .take(screen.sw_size.width as usize)
-    {
-        waddch(win, ch as u32);
+    let width = screen.sw_size.width as usize;
+    let text = match message {
+        Some(message) => message,
+        None => escape_name(tree.curr_path().as_os_str()),
+    };
+    // the branch is shown at the right end
+    let branch = match tree.git.repo() {
+        Some(git) => format!(" {} ", escape_name(git.branch.as_ref())),
+        None => String::new(),
+    };
+    let branch_width = str_width(&branch);
+    if branch_width > 0 && branch_width + 10 < width {
+        print_fitted(win, &text, width - branch_width);
+        let _ = waddstr(win, &branch);
+    } else {
+        print_fitted(win, &text, width);
     }
     wattr_off(win, A_REVERSE);
     wrefresh(screen.status_win);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use provider::mem::MemFs;
+
+    #[test]
+    fn home_is_on_the_trees_file_system() {
+        let fs = MemFs::new()
+            .dir("/home/me/docs")
+            .dir("/srv")
+            .home("/home/me");
+        let mut tree = Tree::new(Config::new().load_options(), false, false);
+        tree.mount_root(Rc::new(fs));
+        change_dir(&mut tree, "/srv").unwrap();
+        change_dir(&mut tree, "~/docs").unwrap();
+        assert_eq!(tree.curr_path(), Path::new("/home/me/docs"));
+        change_dir(&mut tree, "/srv").unwrap();
+        change_dir(&mut tree, "").unwrap();
+        assert_eq!(tree.curr_path(), Path::new("/home/me"));
+    }
+}
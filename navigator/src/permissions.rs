@@ -0,0 +1,244 @@
+use std::ffi::{CStr, OsStr, OsString};
+use std::fs::{self, Permissions};
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{lchown, MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+/// Permission bits `chmod` can change, including setuid, setgid and sticky.
+pub const MODE_BITS: u32 = 0o7777;
+
+/// Changes to make to the permissions and owner of files.
+pub struct AttrChange {
+    /// New values of the bits in `mode_mask`, the other bits are kept.
+    pub mode: u32,
+    pub mode_mask: u32,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    /// Also change everything inside directories.
+    pub recursive: bool,
+}
+
+/// What applying an `AttrChange` did.
+pub struct ChangeReport {
+    pub changed: usize,
+    pub failures: Vec<(PathBuf, io::Error)>,
+}
+
+impl AttrChange {
+    pub fn is_empty(&self) -> bool {
+        self.mode_mask == 0 && self.uid.is_none() && self.gid.is_none()
+    }
+
+    /// Applies the change to `path`, and if recursive to its content. Symlinks
+    /// given directly are followed by `chmod`, like the `chmod` command does;
+    /// found inside directories they are skipped. Ownership of a symlink is
+    /// changed on the link itself.
+    pub fn apply(&self, path: &Path, report: &mut ChangeReport) {
+        match self.apply_one(path, true) {
+            Ok(()) => report.changed += 1,
+            Err(err) => report.failures.push((path.to_path_buf(), err)),
+        }
+        if self.recursive && fs::symlink_metadata(path).is_ok_and(|md| md.is_dir()) {
+            self.apply_inside(path, report);
+        }
+    }
+
+    fn apply_inside(&self, dir: &Path, report: &mut ChangeReport) {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(err) => {
+                report.failures.push((dir.to_path_buf(), err));
+                return;
+            }
+        };
+        for entry in entries {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(err) => {
+                    report.failures.push((dir.to_path_buf(), err));
+                    continue;
+                }
+            };
+            let path = entry.path();
+            match self.apply_one(&path, false) {
+                Ok(()) => report.changed += 1,
+                Err(err) => report.failures.push((path.clone(), err)),
+            }
+            if entry.file_type().is_ok_and(|ft| ft.is_dir()) {
+                self.apply_inside(&path, report);
+            }
+        }
+    }
+
+    fn apply_one(&self, path: &Path, follow: bool) -> io::Result<()> {
+        let md = fs::symlink_metadata(path)?;
+        if self.uid.is_some() || self.gid.is_some() {
+            lchown(path, self.uid, self.gid)?;
+        }
+        if self.mode_mask != 0 {
+            let is_link = md.file_type().is_symlink();
+            if is_link && !follow {
+                return Ok(());
+            }
+            // a link's own mode means nothing, the target's counts
+            let old = if is_link {
+                fs::metadata(path)?.mode()
+            } else {
+                md.mode()
+            };
+            let mode = (old & MODE_BITS & !self.mode_mask) | (self.mode & self.mode_mask);
+            // changing the owner may have cleared setuid and setgid
+            if mode != old & MODE_BITS || self.uid.is_some() || self.gid.is_some() {
+                fs::set_permissions(path, Permissions::from_mode(mode))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/* #region Users and groups */
+
+/// All users known to the system, as (uid, name), by uid.
+pub fn all_users() -> Vec<(u32, OsString)> {
+    // reads the password database with getpwent, which isn't thread safe;
+    // nothing else reads it meanwhile
+    let mut users: Vec<(u32, OsString)> = unsafe { users::all_users() }
+        .map(|u| (u.uid(), u.name().to_os_string()))
+        .collect();
+    users.sort();
+    users.dedup_by_key(|u| u.0);
+    users
+}
+
+/// All groups known to the system, as (gid, name), by gid.
+pub fn all_groups() -> Vec<(u32, OsString)> {
+    let mut groups = Vec::new();
+    // getgrent, like `users::all_users` does for the users
+    unsafe {
+        libc::setgrent();
+        loop {
+            let grp = libc::getgrent();
+            if grp.is_null() {
+                break;
+            }
+            let name = CStr::from_ptr((*grp).gr_name);
+            groups.push((
+                (*grp).gr_gid,
+                OsStr::from_bytes(name.to_bytes()).to_os_string(),
+            ));
+        }
+        libc::endgrent();
+    }
+    groups.sort();
+    groups.dedup_by_key(|g| g.0);
+    groups
+}
+
+/* #endregion */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+    use std::{env, process};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("navigator-perms-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn make(path: &Path, mode: u32) {
+        match path.extension() {
+            Some(ext) if ext == "d" => fs::create_dir(path).unwrap(),
+            _ => fs::write(path, "").unwrap(),
+        }
+        fs::set_permissions(path, Permissions::from_mode(mode)).unwrap();
+    }
+
+    fn mode(path: &Path) -> u32 {
+        fs::metadata(path).unwrap().mode() & MODE_BITS
+    }
+
+    fn change(mode: u32, mode_mask: u32, recursive: bool) -> AttrChange {
+        AttrChange {
+            mode,
+            mode_mask,
+            uid: None,
+            gid: None,
+            recursive,
+        }
+    }
+
+    fn apply(change: &AttrChange, path: &Path) -> ChangeReport {
+        let mut report = ChangeReport {
+            changed: 0,
+            failures: Vec::new(),
+        };
+        change.apply(path, &mut report);
+        report
+    }
+
+    #[test]
+    fn only_masked_bits_change() {
+        let dir = temp_dir("mask");
+        let (a, b, d) = (dir.join("a"), dir.join("b"), dir.join("c.d"));
+        make(&a, 0o600);
+        make(&b, 0o644);
+        make(&d, 0o755);
+
+        // owner exec added, the rest of each mode kept
+        let add_exec = change(0o100, 0o100, false);
+        assert_eq!(apply(&add_exec, &a).changed, 1);
+        apply(&add_exec, &b);
+        assert_eq!((mode(&a), mode(&b)), (0o700, 0o744));
+
+        apply(&change(0o4005, 0o4007, false), &a);
+        assert_eq!(mode(&a), 0o4705);
+        apply(&change(0, 0o4000, false), &a);
+        assert_eq!(mode(&a), 0o705);
+
+        apply(&change(0o1000, 0o1000, false), &d);
+        assert_eq!(mode(&d), 0o1755);
+        apply(&change(0o2000, 0o3000, false), &d);
+        assert_eq!(mode(&d), 0o2755);
+
+        assert!(change(0, 0, false).is_empty());
+        assert!(!change(0, 0o1, false).is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn recursion_skips_symlinks() {
+        let dir = temp_dir("recursive");
+        let outside = dir.join("outside");
+        make(&outside, 0o600);
+        let top = dir.join("top.d");
+        make(&top, 0o700);
+        make(&top.join("file"), 0o600);
+        make(&top.join("sub.d"), 0o700);
+        make(&top.join("sub.d").join("file"), 0o600);
+        symlink(&outside, top.join("sub.d").join("link")).unwrap();
+        symlink("nowhere", top.join("dangling")).unwrap();
+
+        let report = apply(&change(0o044, 0o044, true), &top);
+        assert!(report.failures.is_empty());
+        assert_eq!(report.changed, 6);
+        assert_eq!(mode(&top), 0o744);
+        assert_eq!(mode(&top.join("file")), 0o644);
+        assert_eq!(mode(&top.join("sub.d")), 0o744);
+        assert_eq!(mode(&top.join("sub.d").join("file")), 0o644);
+        assert_eq!(mode(&outside), 0o600);
+
+        // a symlink given directly is followed
+        let report = apply(&change(0o040, 0o040, true), &top.join("sub.d").join("link"));
+        assert_eq!(report.changed, 1);
+        assert_eq!(mode(&outside), 0o640);
+
+        let report = apply(&change(0, 0o7, false), &dir.join("missing"));
+        assert_eq!(report.failures.len(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
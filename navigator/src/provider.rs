@@ -0,0 +1,310 @@
+use std::env;
+use std::fs::{self, File, Permissions};
+use std::io::{self, Read};
+use std::os::unix::fs::{symlink, PermissionsExt};
+use std::path::{Component, Path, PathBuf};
+
+use crate::file_ops::{exists, remove_all};
+use crate::filesystem::{NodeType, SysNode};
+
+/// A file system the tree can be browsed in: the local one, an archive, ...
+/// Paths given to a provider are absolute within it, `/` being its top.
+/// Providers which can't change anything leave out the write operations.
+pub trait FsProvider {
+    /// The entries of the directory `dir`, unsorted.
+    fn list(&self, dir: &Path) -> io::Result<Vec<SysNode>>;
+
+    /// The attributes of an entry. Symlinks are not followed.
+    fn stat(&self, path: &Path) -> io::Result<SysNode>;
+
+    /// The attributes of an entry, or of the one it leads to if it's a
+    /// symlink; the name stays the entry's.
+    fn metadata(&self, path: &Path) -> io::Result<SysNode> {
+        let mut target = path.to_path_buf();
+        // as many as Linux follows
+        for _ in 0..40 {
+            let mut node = self.stat(&target)?;
+            if node.typ != NodeType::SymLink {
+                node.name = path.file_name().unwrap_or(path.as_os_str()).to_os_string();
+                return Ok(node);
+            }
+            let link = self.read_link(&target)?;
+            target = normalize(&target.parent().unwrap_or(Path::new("/")).join(link));
+        }
+        Err(io::Error::from_raw_os_error(libc::ELOOP))
+    }
+
+    /// The content of a file.
+    fn read(&self, path: &Path) -> io::Result<Box<dyn Read + '_>>;
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf>;
+
+    /// Whether paths are on the local file system, so that the file
+    /// operations of the navigator (trash, undo, git, ...) work with them.
+    fn is_local(&self) -> bool {
+        false
+    }
+
+    /// The home directory of the user, where `~` leads.
+    fn home(&self) -> io::Result<PathBuf> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    /// Why the file system can't be used anymore, e.g. what ssh said when
+    /// the connection ended. Given once.
+    fn take_error(&self) -> Option<String> {
+        None
+    }
+
+    fn rename(&self, _from: &Path, _to: &Path) -> io::Result<()> {
+        Err(read_only())
+    }
+
+    /// Removes a file, or a directory with everything inside.
+    fn remove(&self, _path: &Path) -> io::Result<()> {
+        Err(read_only())
+    }
+
+    /// Copies entries, with everything inside directories, to the local
+    /// paths paired with them. Nothing may be at those paths yet. Returns how
+    /// many entries were copied; special files are skipped.
+    fn extract(&self, targets: &[(PathBuf, PathBuf)]) -> io::Result<usize> {
+        if targets.iter().any(|(_, to)| exists(to)) {
+            return Err(io::ErrorKind::AlreadyExists.into());
+        }
+        let mut count = 0;
+        for (from, to) in targets {
+            count += copy_out(self, from, to)?;
+        }
+        Ok(count)
+    }
+}
+
+fn read_only() -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, "read-only file system")
+}
+
+// without `.` and `..`, which providers other than the local one don't know
+fn normalize(path: &Path) -> PathBuf {
+    let mut normal = PathBuf::from("/");
+    for c in path.components() {
+        match c {
+            Component::Normal(name) => normal.push(name),
+            Component::ParentDir => {
+                normal.pop();
+            }
+            Component::RootDir | Component::CurDir | Component::Prefix(_) => {}
+        }
+    }
+    normal
+}
+
+fn copy_out<P: FsProvider + ?Sized>(fs: &P, from: &Path, to: &Path) -> io::Result<usize> {
+    let node = fs.stat(from)?;
+    match node.typ {
+        NodeType::Dir => {
+            fs::create_dir(to)?;
+            let mut count = 1;
+            for entry in fs.list(from)? {
+                // a name from elsewhere could lead out of `to`
+                let mut parts = Path::new(&entry.name).components();
+                if !matches!(
+                    (parts.next(), parts.next()),
+                    (Some(Component::Normal(_)), None)
+                ) {
+                    continue;
+                }
+                count += copy_out(fs, &from.join(&entry.name), &to.join(&entry.name))?;
+            }
+            fs::set_permissions(to, Permissions::from_mode(node.mode & 0o7777))?;
+            Ok(count)
+        }
+        NodeType::SymLink => symlink(fs.read_link(from)?, to).map(|_| 1),
+        NodeType::File => {
+            io::copy(&mut fs.read(from)?, &mut File::create_new(to)?)?;
+            fs::set_permissions(to, Permissions::from_mode(node.mode & 0o7777))?;
+            Ok(1)
+        }
+        _ => Ok(0),
+    }
+}
+
+/// The local file system.
+pub struct LocalFs;
+
+impl FsProvider for LocalFs {
+    fn list(&self, dir: &Path) -> io::Result<Vec<SysNode>> {
+        Ok(fs::read_dir(dir)?
+            .filter_map(|res| res.ok().map(|e| SysNode::from(&e)))
+            .collect())
+    }
+
+    fn stat(&self, path: &Path) -> io::Result<SysNode> {
+        fs::symlink_metadata(path)?;
+        Ok(SysNode::from_path(path))
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<SysNode> {
+        let name = path.file_name().unwrap_or(path.as_os_str()).to_os_string();
+        Ok(SysNode::with_metadata(name, Ok(fs::metadata(path)?)))
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Box<dyn Read + '_>> {
+        Ok(Box::new(File::open(path)?))
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        fs::read_link(path)
+    }
+
+    fn is_local(&self) -> bool {
+        true
+    }
+
+    fn home(&self) -> io::Result<PathBuf> {
+        match env::var_os("HOME") {
+            Some(home) if !home.is_empty() => Ok(PathBuf::from(home)),
+            _ => Err(io::Error::new(io::ErrorKind::NotFound, "HOME is not set")),
+        }
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        fs::rename(from, to)
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        remove_all(path)
+    }
+}
+
+/// A file system kept in memory, to test the tree without touching the disk.
+#[cfg(test)]
+pub mod mem {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    pub struct MemFs {
+        entries: BTreeMap<PathBuf, (SysNode, Vec<u8>)>,
+        home: Option<PathBuf>,
+    }
+
+    impl MemFs {
+        pub fn new() -> MemFs {
+            let mut fs = MemFs {
+                entries: BTreeMap::new(),
+                home: None,
+            };
+            fs.add("/", NodeType::Dir, b"");
+            fs
+        }
+
+        /// Makes `path` the home directory, which must be added as well.
+        pub fn home(mut self, path: &str) -> MemFs {
+            self.home = Some(PathBuf::from(path));
+            self
+        }
+
+        /// Adds a directory, with the ones leading to it.
+        pub fn dir(mut self, path: &str) -> MemFs {
+            self.add(path, NodeType::Dir, b"");
+            self
+        }
+
+        /// Adds a file, with the directories leading to it.
+        pub fn file(mut self, path: &str, content: &[u8]) -> MemFs {
+            self.add(path, NodeType::File, content);
+            self
+        }
+
+        /// Adds a symlink to `target`, kept as its content.
+        pub fn symlink(mut self, path: &str, target: &str) -> MemFs {
+            self.add(path, NodeType::SymLink, target.as_bytes());
+            self
+        }
+
+        fn add(&mut self, path: &str, typ: NodeType, content: &[u8]) {
+            let path = Path::new(path);
+            for dir in path.ancestors().skip(1) {
+                if !self.entries.contains_key(dir) {
+                    self.add(&dir.to_string_lossy(), NodeType::Dir, b"");
+                }
+            }
+            let name = path.file_name().unwrap_or(path.as_os_str());
+            let mut node = SysNode::new(name, typ);
+            node.mode = if typ == NodeType::Dir { 0o755 } else { 0o644 };
+            node.size = content.len() as u64;
+            self.entries
+                .insert(path.to_path_buf(), (node, content.to_vec()));
+        }
+
+        fn get(&self, path: &Path) -> io::Result<&(SysNode, Vec<u8>)> {
+            self.entries
+                .get(path)
+                .ok_or_else(|| io::ErrorKind::NotFound.into())
+        }
+    }
+
+    impl FsProvider for MemFs {
+        fn list(&self, dir: &Path) -> io::Result<Vec<SysNode>> {
+            if self.get(dir)?.0.typ != NodeType::Dir {
+                return Err(io::Error::from_raw_os_error(libc::ENOTDIR));
+            }
+            Ok(self
+                .entries
+                .iter()
+                .filter(|(path, _)| path.parent() == Some(dir))
+                .map(|(_, (node, _))| node.clone())
+                .collect())
+        }
+
+        fn stat(&self, path: &Path) -> io::Result<SysNode> {
+            Ok(self.get(path)?.0.clone())
+        }
+
+        fn read(&self, path: &Path) -> io::Result<Box<dyn Read + '_>> {
+            Ok(Box::new(&self.get(path)?.1[..]))
+        }
+
+        fn home(&self) -> io::Result<PathBuf> {
+            self.home
+                .clone()
+                .ok_or_else(|| io::ErrorKind::NotFound.into())
+        }
+
+        fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+            match self.get(path)? {
+                (node, target) if node.typ == NodeType::SymLink => {
+                    Ok(PathBuf::from(OsStr::from_bytes(target)))
+                }
+                _ => Err(io::ErrorKind::InvalidInput.into()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::mem::MemFs;
+    use super::*;
+
+    #[test]
+    fn metadata_follows_symlinks() {
+        let fs = MemFs::new()
+            .dir("/data/sets")
+            .symlink("/data/current", "sets")
+            .symlink("/up", "data/./current/..")
+            .symlink("/loop", "loop")
+            .symlink("/dangling", "nothing");
+        let node = fs.metadata(Path::new("/data/current")).unwrap();
+        assert!(node.typ == NodeType::Dir);
+        assert_eq!(node.name, "current");
+        assert!(fs.stat(Path::new("/data/current")).unwrap().typ == NodeType::SymLink);
+        assert!(fs.metadata(Path::new("/up")).unwrap().typ == NodeType::Dir);
+        let err = fs.metadata(Path::new("/loop")).err().unwrap();
+        assert_eq!(err.raw_os_error(), Some(libc::ELOOP));
+        let err = fs.metadata(Path::new("/dangling")).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+}
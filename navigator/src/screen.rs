@@ -1,20 +1,47 @@
 use crate::common::*;
 use ncurses::*;
 
+#[derive(Copy, Clone, PartialEq)]
+pub enum Layout {
+    Both,
+    TreeOnly,
+    ListOnly,
+    /// The tree and two file lists side by side.
+    Dual,
+}
+
+impl Layout {
+    pub fn parse(s: &str) -> Option<Layout> {
+        match s {
+            "both" => Some(Layout::Both),
+            "tree" | "tree-only" => Some(Layout::TreeOnly),
+            "list" | "list-only" => Some(Layout::ListOnly),
+            "dual" => Some(Layout::Dual),
+            _ => None,
+        }
+    }
+}
+
 pub struct Screen {
-    pub left_pane: WINDOW,
-    pub right_pane: WINDOW,
-    pub tree_win: WINDOW,
-    pub list_win: WINDOW,
+    pub left_pane: Option<WINDOW>,
+    pub right_pane: Option<WINDOW>,
+    pub tree_win: Option<WINDOW>,
+    pub list_win: Option<WINDOW>,
+    /// The second file list of the dual layout, right of the first one.
+    pub second_pane: Option<WINDOW>,
+    pub second_list_win: Option<WINDOW>,
     pub status_win: WINDOW,
 
     pub tw_size: Size,
     pub lw_size: Size,
+    pub slw_size: Size,
     pub sw_size: Size,
 }
 
 impl Screen {
-    pub fn create() -> Screen {
+    pub fn create(layout: Layout) -> Screen {
+        // needed for ncursesw to print multibyte characters
+        let _ = setlocale(LcCategory::all, "");
         initscr();
         start_color();
         use_default_colors();
@@ -30,47 +57,160 @@ impl Screen {
 
         getmaxyx(ncurses::stdscr(), &mut scr_height, &mut scr_width);
 
-        let l_width = scr_width / 4;
-        let r_width = scr_width - l_width;
+        let l_width = match layout {
+            Layout::Both => scr_width / 4,
+            Layout::TreeOnly => scr_width,
+            Layout::ListOnly => 0,
+            Layout::Dual => scr_width / 5,
+        };
+        // the second list takes half of what's right of the tree
+        let s_width = match layout {
+            Layout::Dual => (scr_width - l_width) / 2,
+            _ => 0,
+        };
+        let r_width = scr_width - l_width - s_width;
 
         // Tree
-        let left_pane = newwin(scr_height - 1, l_width, 0, 0);
-        wborder(left_pane, 0, 0, 0, 0, 0, ACS_TTEE(), 0, ACS_BTEE());
-        wrefresh(left_pane);
+        let (left_pane, tree_win) = if l_width > 0 {
+            let left_pane = newwin(scr_height - 1, l_width, 0, 0);
+            if r_width > 0 {
+                wborder(left_pane, 0, 0, 0, 0, 0, ACS_TTEE(), 0, ACS_BTEE());
+            } else {
+                box_(left_pane, 0, 0);
+            }
+            wrefresh(left_pane);
 
-        let tree_win: WINDOW = newwin(scr_height - 3, l_width - 2, 1, 1);
-        wrefresh(tree_win);
+            let tree_win: WINDOW = newwin(scr_height - 3, l_width - 2, 1, 1);
+            wrefresh(tree_win);
+            (Some(left_pane), Some(tree_win))
+        } else {
+            (None, None)
+        };
 
         // List
-        let right_pane = newwin(scr_height - 1, r_width, 0, l_width);
-        wborder(right_pane, 0, 0, 0, 0, ACS_HLINE(), 0, ACS_HLINE(), 0);
-        wrefresh(right_pane);
+        let (right_pane, list_win) = if r_width > 0 {
+            let right_pane = newwin(scr_height - 1, r_width, 0, l_width);
+            if s_width > 0 {
+                wborder(
+                    right_pane,
+                    0,
+                    0,
+                    0,
+                    0,
+                    ACS_HLINE(),
+                    ACS_TTEE(),
+                    ACS_HLINE(),
+                    ACS_BTEE(),
+                );
+            } else if l_width > 0 {
+                wborder(right_pane, 0, 0, 0, 0, ACS_HLINE(), 0, ACS_HLINE(), 0);
+            } else {
+                box_(right_pane, 0, 0);
+            }
+            wrefresh(right_pane);
+
+            let (list_x, list_w) = if l_width > 0 {
+                (l_width, r_width - 1)
+            } else {
+                (1, r_width - 2)
+            };
+            let list_win: WINDOW = newwin(scr_height - 3, list_w, 1, list_x);
+            wrefresh(list_win);
+            (Some(right_pane), Some(list_win))
+        } else {
+            (None, None)
+        };
+
+        // Second list
+        let (second_pane, second_list_win) = if s_width > 0 {
+            let x = l_width + r_width;
+            let second_pane = newwin(scr_height - 1, s_width, 0, x);
+            wborder(second_pane, 0, 0, 0, 0, ACS_HLINE(), 0, ACS_HLINE(), 0);
+            wrefresh(second_pane);
 
-        let list_win: WINDOW = newwin(scr_height - 3, r_width - 1, 1, l_width);
-        wrefresh(list_win);
+            let second_list_win: WINDOW = newwin(scr_height - 3, s_width - 1, 1, x);
+            wrefresh(second_list_win);
+            (Some(second_pane), Some(second_list_win))
+        } else {
+            (None, None)
+        };
 
         // Status
         let status_win: WINDOW = newwin(1, scr_width, scr_height - 1, 0);
-        wrefresh(list_win);
+        wrefresh(status_win);
 
         Screen {
             left_pane,
             right_pane,
             tree_win,
             list_win,
+            second_pane,
+            second_list_win,
             status_win,
 
             tw_size: Size::new(l_width - 2, scr_height - 3),
             lw_size: Size::new(r_width - 2, scr_height - 3),
+            slw_size: Size::new(s_width - 2, scr_height - 3),
             sw_size: Size::new(scr_width, 1),
         }
     }
 
+    pub fn enable_mouse(&self) {
+        mousemask(
+            (BUTTON1_CLICKED | BUTTON1_DOUBLE_CLICKED | BUTTON4_PRESSED | BUTTON5_PRESSED)
+                as mmask_t,
+            None,
+        );
+    }
+
+    /// Gives the terminal back for running a program in it, e.g. an editor.
+    pub fn suspend(&self) {
+        def_prog_mode();
+        endwin();
+    }
+
+    /// Takes the terminal again after `suspend` and repaints everything.
+    pub fn resume(&self) {
+        reset_prog_mode();
+        refresh();
+        self.redraw();
+    }
+
+    /// Repaints everything, e.g. after a window was shown over the panes.
+    pub fn redraw(&self) {
+        for win in [
+            self.left_pane,
+            self.right_pane,
+            self.second_pane,
+            self.tree_win,
+            self.list_win,
+            self.second_list_win,
+            Some(self.status_win),
+        ]
+        .into_iter()
+        .flatten()
+        {
+            touchwin(win);
+            wnoutrefresh(win);
+        }
+        doupdate();
+    }
+
     pub fn close(&self) {
-        delwin(self.tree_win);
-        delwin(self.list_win);
-        delwin(self.left_pane);
-        delwin(self.right_pane);
+        for win in [
+            self.tree_win,
+            self.list_win,
+            self.second_list_win,
+            self.left_pane,
+            self.right_pane,
+            self.second_pane,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            delwin(win);
+        }
+        delwin(self.status_win);
         endwin();
     }
 }
@@ -0,0 +1,935 @@
+use std::cell::{Cell, RefCell};
+use std::ffi::{OsStr, OsString};
+use std::io::{self, BufReader, Read, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::thread::{self, JoinHandle};
+
+use crate::common::AppError;
+use crate::filesystem::{percent_decode, percent_encode, unix_time_to_local, NodeType, SysNode};
+use crate::provider::FsProvider;
+
+/// A remote directory given as `ssh://[USER@]HOST[:PORT][/PATH]` (or
+/// `sftp://...`), an IPv6 address in brackets. A path starting with `/~` is
+/// relative to the home directory, no path at all means the home directory.
+pub struct SshUrl {
+    pub user: Option<String>,
+    pub host: String,
+    pub port: Option<u16>,
+    pub path: PathBuf,
+}
+
+impl SshUrl {
+    /// `None` if `arg` is not an ssh URL, e.g. a local path.
+    pub fn parse(arg: &OsStr) -> Option<Result<SshUrl, AppError>> {
+        let arg = arg.to_str()?;
+        let rest = arg
+            .strip_prefix("ssh://")
+            .or_else(|| arg.strip_prefix("sftp://"))?;
+        let invalid = || AppError::UsageError(format!("invalid ssh URL '{}'", arg));
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, ""),
+        };
+        let (user, host_port) = match authority.rsplit_once('@') {
+            Some((user, host)) => (Some(user.to_owned()), host),
+            None => (None, authority),
+        };
+        let (host, port) = match host_port.strip_prefix('[').map(|h| h.split_once(']')) {
+            Some(Some((host, ""))) => (host, None),
+            Some(Some((host, port))) => match port.strip_prefix(':').map(str::parse) {
+                Some(Ok(port)) => (host, Some(port)),
+                _ => return Some(Err(invalid())),
+            },
+            Some(None) => return Some(Err(invalid())),
+            None => match host_port.rsplit_once(':') {
+                // an IPv6 address must be in brackets
+                Some((host, _)) if host.contains(':') => return Some(Err(invalid())),
+                Some((host, port)) => match port.parse() {
+                    Ok(port) => (host, Some(port)),
+                    Err(_) => return Some(Err(invalid())),
+                },
+                None => (host_port, None),
+            },
+        };
+        if host.is_empty() || user.as_deref() == Some("") {
+            return Some(Err(invalid()));
+        }
+        let path = match path.strip_prefix("/~") {
+            Some(rest) => rest.trim_start_matches('/'),
+            None => path,
+        };
+        Some(Ok(SshUrl {
+            user,
+            host: host.to_owned(),
+            port,
+            path: percent_decode(path),
+        }))
+    }
+
+    /// The URL of another path on the same host.
+    pub fn with_path(&self, path: &Path) -> OsString {
+        let user = self
+            .user
+            .as_ref()
+            .map_or(String::new(), |u| format!("{}@", u));
+        let host = match self.host.contains(':') {
+            true => format!("[{}]", self.host),
+            false => self.host.clone(),
+        };
+        let port = self.port.map_or(String::new(), |p| format!(":{}", p));
+        format!("ssh://{}{}{}{}", user, host, port, percent_encode(path)).into()
+    }
+}
+
+// packet types of SFTP version 3
+const SSH_FXP_INIT: u8 = 1;
+const SSH_FXP_VERSION: u8 = 2;
+const SSH_FXP_OPEN: u8 = 3;
+const SSH_FXP_CLOSE: u8 = 4;
+const SSH_FXP_READ: u8 = 5;
+const SSH_FXP_LSTAT: u8 = 7;
+const SSH_FXP_OPENDIR: u8 = 11;
+const SSH_FXP_READDIR: u8 = 12;
+const SSH_FXP_REALPATH: u8 = 16;
+const SSH_FXP_STAT: u8 = 17;
+const SSH_FXP_READLINK: u8 = 19;
+const SSH_FXP_STATUS: u8 = 101;
+const SSH_FXP_HANDLE: u8 = 102;
+const SSH_FXP_DATA: u8 = 103;
+const SSH_FXP_NAME: u8 = 104;
+const SSH_FXP_ATTRS: u8 = 105;
+
+const SSH_FX_EOF: u32 = 1;
+const SSH_FX_NO_SUCH_FILE: u32 = 2;
+const SSH_FX_PERMISSION_DENIED: u32 = 3;
+
+const SSH_FILEXFER_ATTR_SIZE: u32 = 0x1;
+const SSH_FILEXFER_ATTR_UIDGID: u32 = 0x2;
+const SSH_FILEXFER_ATTR_PERMISSIONS: u32 = 0x4;
+const SSH_FILEXFER_ATTR_ACMODTIME: u32 = 0x8;
+const SSH_FILEXFER_ATTR_EXTENDED: u32 = 0x8000_0000;
+
+const SSH_FXF_READ: u32 = 0x1;
+
+// bytes asked for by one read request
+const READ_CHUNK: u32 = 32 * 1024;
+
+// the longest packet accepted, the limit of OpenSSH with room for headers;
+// the length comes from the server and is not trusted further
+const MAX_PACKET: usize = 256 * 1024 + 1024;
+
+/// A packet being built, fields are appended in the order of the protocol.
+struct Packet {
+    data: Vec<u8>,
+}
+
+impl Packet {
+    fn new(typ: u8) -> Packet {
+        Packet { data: vec![typ] }
+    }
+
+    fn u32(mut self, v: u32) -> Packet {
+        self.data.extend_from_slice(&v.to_be_bytes());
+        self
+    }
+
+    fn u64(mut self, v: u64) -> Packet {
+        self.data.extend_from_slice(&v.to_be_bytes());
+        self
+    }
+
+    fn bytes(self, v: &[u8]) -> Packet {
+        let mut p = self.u32(v.len() as u32);
+        p.data.extend_from_slice(v);
+        p
+    }
+}
+
+/// Reads the fields of a received packet.
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        if self.data.len() < n {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "short SFTP packet",
+            ));
+        }
+        let (head, tail) = self.data.split_at(n);
+        self.data = tail;
+        Ok(head)
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_be_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn bytes(&mut self) -> io::Result<&'a [u8]> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+
+    fn attrs(&mut self, name: &OsStr) -> io::Result<SysNode> {
+        let flags = self.u32()?;
+        let mut node = SysNode::new(name, NodeType::File);
+        if flags & SSH_FILEXFER_ATTR_SIZE != 0 {
+            node.size = self.u64()?;
+        }
+        if flags & SSH_FILEXFER_ATTR_UIDGID != 0 {
+            node.uid = self.u32()?;
+            node.gid = self.u32()?;
+            node.user = node.uid.to_string().into();
+            node.group = node.gid.to_string().into();
+        }
+        if flags & SSH_FILEXFER_ATTR_PERMISSIONS != 0 {
+            let mode = self.u32()?;
+            node.typ = match mode & libc::S_IFMT {
+                libc::S_IFDIR => NodeType::Dir,
+                libc::S_IFLNK => NodeType::SymLink,
+                libc::S_IFBLK => NodeType::BlockDevice,
+                libc::S_IFCHR => NodeType::CharDevice,
+                libc::S_IFIFO => NodeType::Fifo,
+                libc::S_IFSOCK => NodeType::Socket,
+                _ => NodeType::File,
+            };
+            node.mode = mode;
+        }
+        if flags & SSH_FILEXFER_ATTR_ACMODTIME != 0 {
+            node.accessed = unix_time_to_local(self.u32()? as i64, 0);
+            node.modified = unix_time_to_local(self.u32()? as i64, 0);
+            node.changed = node.modified;
+        }
+        if flags & SSH_FILEXFER_ATTR_EXTENDED != 0 {
+            for _ in 0..self.u32()? {
+                self.bytes()?;
+                self.bytes()?;
+            }
+        }
+        node.nlink = 1;
+        Ok(node)
+    }
+}
+
+/// Takes the link count, owner and group from the `ls -l` like line servers
+/// send with directory entries, as names are not part of the attributes.
+fn apply_long_name(node: &mut SysNode, long_name: &[u8]) {
+    let mut fields = long_name
+        .split(|b| *b == b' ')
+        .filter(|f| !f.is_empty())
+        .skip(1);
+    if let (Some(nlink), Some(user), Some(group)) = (fields.next(), fields.next(), fields.next()) {
+        if let Some(nlink) = std::str::from_utf8(nlink).ok().and_then(|n| n.parse().ok()) {
+            node.nlink = nlink;
+        }
+        node.user = OsStr::from_bytes(user).to_os_string();
+        node.group = OsStr::from_bytes(group).to_os_string();
+    }
+}
+
+fn status_error(code: u32, message: &[u8]) -> io::Error {
+    let kind = match code {
+        SSH_FX_EOF => io::ErrorKind::UnexpectedEof,
+        SSH_FX_NO_SUCH_FILE => io::ErrorKind::NotFound,
+        SSH_FX_PERMISSION_DENIED => io::ErrorKind::PermissionDenied,
+        _ => io::ErrorKind::Other,
+    };
+    io::Error::new(kind, String::from_utf8_lossy(message).into_owned())
+}
+
+/// The `ssh` program running the SFTP subsystem. What it writes to stderr
+/// is collected, not shown over the screen.
+struct SshProcess {
+    child: Child,
+    stderr: Option<JoinHandle<Vec<u8>>>,
+}
+
+impl SshProcess {
+    /// What ssh said, once it has exited; it's killed if `kill`.
+    fn finish(&mut self, kill: bool) -> Option<String> {
+        if kill {
+            let _ = self.child.kill();
+        }
+        let status = match kill {
+            true => self.child.wait().ok()?,
+            false => self.child.try_wait().ok()??,
+        };
+        let text = self.stderr.take()?.join().unwrap_or_default();
+        let text = String::from_utf8_lossy(&text).trim().to_owned();
+        Some(match text.is_empty() {
+            true => format!("ssh exited: {}", status),
+            false => text,
+        })
+    }
+}
+
+/// A connection to an SFTP server, through the `ssh` program: its config,
+/// keys and agent are used, and it asks for passwords itself. Browsing only,
+/// nothing on the host is changed.
+pub struct Sftp {
+    ssh: Option<RefCell<SshProcess>>,
+    input: RefCell<Box<dyn Write>>,
+    output: RefCell<BufReader<Box<dyn Read>>>,
+    next_id: Cell<u32>,
+    /// Why the connection was given up, after which every request fails.
+    broken: RefCell<Option<String>>,
+    /// Whether `broken` was given out by `take_error` already.
+    reported: Cell<bool>,
+}
+
+impl Sftp {
+    /// Runs `ssh` (`ssh_command`, a shell command line) to start the
+    /// SFTP subsystem on the host. Must be done before the terminal UI is
+    /// started, ssh may ask for a password.
+    pub fn connect(url: &SshUrl, ssh_command: &str) -> Result<Sftp, AppError> {
+        Sftp::spawn(Sftp::command(url, ssh_command), &url.host)
+    }
+
+    // The command line is read by the shell, so that options may be quoted
+    // and `~` stands for the home directory, as in a shell or `.ssh/config`.
+    fn command(url: &SshUrl, ssh_command: &str) -> Command {
+        let mut command = Command::new("sh");
+        command
+            .arg("-c")
+            .arg(format!("{} \"$@\"", ssh_command))
+            .arg("sh");
+        command.args(["-o", "ForwardX11=no", "-o", "ClearAllForwardings=yes"]);
+        if let Some(port) = url.port {
+            command.arg("-p").arg(port.to_string());
+        }
+        if let Some(user) = &url.user {
+            command.arg("-l").arg(user);
+        }
+        command.args(["-s", "--", &url.host, "sftp"]);
+        command
+    }
+
+    fn spawn(mut command: Command, host: &str) -> Result<Sftp, AppError> {
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|err| AppError::StrError(format!("cannot run ssh: {}", err)))?;
+        let (Some(stdin), Some(stdout), Some(mut stderr)) =
+            (child.stdin.take(), child.stdout.take(), child.stderr.take())
+        else {
+            return Err(AppError::StrError("cannot talk to ssh".to_owned()));
+        };
+        let stderr = thread::spawn(move || {
+            let mut text = Vec::new();
+            let _ = stderr.read_to_end(&mut text);
+            text
+        });
+        let mut sftp = Sftp::new(Box::new(stdin), Box::new(stdout));
+        sftp.ssh = Some(RefCell::new(SshProcess {
+            child,
+            stderr: Some(stderr),
+        }));
+        if let Err(err) = sftp.init() {
+            let said = sftp
+                .ssh
+                .as_ref()
+                .and_then(|ssh| ssh.borrow_mut().finish(true));
+            let message = match said {
+                Some(said) => format!("cannot connect to {}: {}\n{}", host, err, said),
+                None => format!("cannot connect to {}: {}", host, err),
+            };
+            return Err(AppError::StrError(message));
+        }
+        Ok(sftp)
+    }
+
+    /// A connection over any pipes, the server must be at the other end.
+    fn new(input: Box<dyn Write>, output: Box<dyn Read>) -> Sftp {
+        Sftp {
+            ssh: None,
+            input: RefCell::new(input),
+            output: RefCell::new(BufReader::new(output)),
+            next_id: Cell::new(0),
+            broken: RefCell::new(None),
+            reported: Cell::new(false),
+        }
+    }
+
+    fn init(&self) -> io::Result<()> {
+        self.send(Packet::new(SSH_FXP_INIT).u32(3))?;
+        let reply = self.receive()?;
+        match reply.first() {
+            Some(&SSH_FXP_VERSION) => Ok(()),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not an SFTP server",
+            )),
+        }
+    }
+
+    fn send(&self, packet: Packet) -> io::Result<()> {
+        let mut input = self.input.borrow_mut();
+        input.write_all(&(packet.data.len() as u32).to_be_bytes())?;
+        input.write_all(&packet.data)?;
+        input.flush()
+    }
+
+    fn receive(&self) -> io::Result<Vec<u8>> {
+        let mut output = self.output.borrow_mut();
+        let mut len = [0; 4];
+        output.read_exact(&mut len)?;
+        let len = u32::from_be_bytes(len) as usize;
+        if len > MAX_PACKET {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "SFTP packet too long",
+            ));
+        }
+        let mut data = vec![0; len];
+        output.read_exact(&mut data)?;
+        Ok(data)
+    }
+
+    /// Sends a request and waits for its reply. Returns the type of the reply
+    /// and its fields after the request id; error statuses are turned into
+    /// errors.
+    fn request(&self, typ: u8, fields: impl FnOnce(Packet) -> Packet) -> io::Result<(u8, Vec<u8>)> {
+        if self.broken.borrow().is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotConnected,
+                "the SFTP connection is closed",
+            ));
+        }
+        let id = self.next_id.get();
+        self.next_id.set(id.wrapping_add(1));
+        let (typ, rest) = match self.exchange(id, fields(Packet::new(typ).u32(id))) {
+            Ok(reply) => reply,
+            Err(err) => {
+                self.break_off(&err);
+                return Err(err);
+            }
+        };
+        if typ == SSH_FXP_STATUS {
+            let mut reader = Reader { data: &rest };
+            let code = reader.u32()?;
+            if code != 0 {
+                let message = reader.bytes().unwrap_or_default();
+                return Err(status_error(code, message));
+            }
+        }
+        Ok((typ, rest))
+    }
+
+    // Sends a request and reads the reply, which must have the same id.
+    // Requests are sent one at a time, so the reply is the next packet.
+    fn exchange(&self, id: u32, packet: Packet) -> io::Result<(u8, Vec<u8>)> {
+        self.send(packet)?;
+        let reply = self.receive()?;
+        let mut reader = Reader { data: &reply };
+        let typ = reader.take(1)?[0];
+        if reader.u32()? != id {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unexpected SFTP reply",
+            ));
+        }
+        Ok((typ, reader.data.to_vec()))
+    }
+
+    // Gives up the connection after a reply that couldn't be read or doesn't
+    // fit the request: later replies could no longer be matched to requests.
+    fn break_off(&self, err: &io::Error) {
+        let said = self
+            .ssh
+            .as_ref()
+            .and_then(|ssh| ssh.borrow_mut().finish(true));
+        let message = match said {
+            Some(said) => format!("{}\n{}", err, said),
+            None => err.to_string(),
+        };
+        *self.broken.borrow_mut() = Some(message);
+    }
+
+    fn expect(reply: (u8, Vec<u8>), typ: u8) -> io::Result<Vec<u8>> {
+        match reply.0 == typ {
+            true => Ok(reply.1),
+            false => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unexpected SFTP reply",
+            )),
+        }
+    }
+
+    fn open_handle(
+        &self,
+        typ: u8,
+        path: &Path,
+        fields: impl FnOnce(Packet) -> Packet,
+    ) -> io::Result<Vec<u8>> {
+        let path = path.as_os_str().as_bytes();
+        let reply = self.request(typ, |p| fields(p.bytes(path)))?;
+        let data = Sftp::expect(reply, SSH_FXP_HANDLE)?;
+        Ok(Reader { data: &data }.bytes()?.to_vec())
+    }
+
+    fn close(&self, handle: &[u8]) {
+        let _ = self.request(SSH_FXP_CLOSE, |p| p.bytes(handle));
+    }
+
+    /// The absolute path of `path` on the host, without `..` and symlinks.
+    /// An empty or relative path is taken from the home directory.
+    pub fn realpath(&self, path: &Path) -> io::Result<PathBuf> {
+        let path = match path.as_os_str().is_empty() {
+            true => Path::new("."),
+            false => path,
+        };
+        let reply = self.request(SSH_FXP_REALPATH, |p| p.bytes(path.as_os_str().as_bytes()))?;
+        let data = Sftp::expect(reply, SSH_FXP_NAME)?;
+        let mut reader = Reader { data: &data };
+        reader.u32()?;
+        Ok(PathBuf::from(OsStr::from_bytes(reader.bytes()?)))
+    }
+
+    // reads the entries of an opened directory into `nodes`, to the end
+    fn read_dir(&self, handle: &[u8], nodes: &mut Vec<SysNode>) -> io::Result<()> {
+        loop {
+            let data = match self.request(SSH_FXP_READDIR, |p| p.bytes(handle)) {
+                Ok(reply) => Sftp::expect(reply, SSH_FXP_NAME)?,
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+                Err(err) => return Err(err),
+            };
+            let mut reader = Reader { data: &data };
+            for _ in 0..reader.u32()? {
+                let name = OsStr::from_bytes(reader.bytes()?).to_os_string();
+                let long_name = reader.bytes()?;
+                let mut node = reader.attrs(&name)?;
+                if name != "." && name != ".." {
+                    apply_long_name(&mut node, long_name);
+                    nodes.push(node);
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Sftp {
+    fn drop(&mut self) {
+        if let Some(ssh) = &self.ssh {
+            ssh.borrow_mut().finish(true);
+        }
+    }
+}
+
+impl FsProvider for Sftp {
+    fn list(&self, dir: &Path) -> io::Result<Vec<SysNode>> {
+        let handle = self.open_handle(SSH_FXP_OPENDIR, dir, |p| p)?;
+        let mut nodes = Vec::new();
+        let result = self.read_dir(&handle, &mut nodes);
+        self.close(&handle);
+        result.map(|_| nodes)
+    }
+
+    fn stat(&self, path: &Path) -> io::Result<SysNode> {
+        let reply = self.request(SSH_FXP_LSTAT, |p| p.bytes(path.as_os_str().as_bytes()))?;
+        let data = Sftp::expect(reply, SSH_FXP_ATTRS)?;
+        let name = path.file_name().unwrap_or(path.as_os_str());
+        Reader { data: &data }.attrs(name)
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<SysNode> {
+        let reply = self.request(SSH_FXP_STAT, |p| p.bytes(path.as_os_str().as_bytes()))?;
+        let data = Sftp::expect(reply, SSH_FXP_ATTRS)?;
+        let name = path.file_name().unwrap_or(path.as_os_str());
+        Reader { data: &data }.attrs(name)
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Box<dyn Read + '_>> {
+        let handle = self.open_handle(SSH_FXP_OPEN, path, |p| p.u32(SSH_FXF_READ).u32(0))?;
+        Ok(Box::new(SftpFile {
+            sftp: self,
+            handle,
+            offset: 0,
+            buffer: Vec::new(),
+            pos: 0,
+            eof: false,
+        }))
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        let reply = self.request(SSH_FXP_READLINK, |p| p.bytes(path.as_os_str().as_bytes()))?;
+        let data = Sftp::expect(reply, SSH_FXP_NAME)?;
+        let mut reader = Reader { data: &data };
+        reader.u32()?;
+        Ok(PathBuf::from(OsStr::from_bytes(reader.bytes()?)))
+    }
+
+    fn home(&self) -> io::Result<PathBuf> {
+        self.realpath(Path::new(""))
+    }
+
+    /// Why the connection was given up, or what ssh said if it ended.
+    fn take_error(&self) -> Option<String> {
+        if self.broken.borrow().is_none() {
+            let said = self
+                .ssh
+                .as_ref()
+                .and_then(|ssh| ssh.borrow_mut().finish(false));
+            *self.broken.borrow_mut() = said;
+        }
+        if self.broken.borrow().is_none() || self.reported.replace(true) {
+            return None;
+        }
+        self.broken.borrow().clone()
+    }
+}
+
+/// A remote file opened for reading, closed when dropped.
+struct SftpFile<'a> {
+    sftp: &'a Sftp,
+    handle: Vec<u8>,
+    offset: u64,
+    buffer: Vec<u8>,
+    pos: usize,
+    eof: bool,
+}
+
+impl Read for SftpFile<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.buffer.len() && !self.eof {
+            let (handle, offset) = (&self.handle, self.offset);
+            let reply = self.sftp.request(SSH_FXP_READ, |p| {
+                p.bytes(handle).u64(offset).u32(READ_CHUNK)
+            });
+            match reply {
+                Ok(reply) => {
+                    let data = Sftp::expect(reply, SSH_FXP_DATA)?;
+                    self.buffer = Reader { data: &data }.bytes()?.to_vec();
+                    self.offset += self.buffer.len() as u64;
+                    self.pos = 0;
+                }
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => self.eof = true,
+                Err(err) => return Err(err),
+            }
+        }
+        let n = buf.len().min(self.buffer.len() - self.pos);
+        buf[..n].copy_from_slice(&self.buffer[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+impl Drop for SftpFile<'_> {
+    fn drop(&mut self) {
+        self.sftp.close(&self.handle);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixStream;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    fn url(arg: &str) -> Result<SshUrl, AppError> {
+        SshUrl::parse(OsStr::new(arg)).unwrap()
+    }
+
+    #[test]
+    fn parses_urls() {
+        let u = url("ssh://me@host:2222/srv/a%20b").ok().unwrap();
+        assert_eq!(
+            (u.user.as_deref(), u.host.as_str(), u.port),
+            (Some("me"), "host", Some(2222))
+        );
+        assert_eq!(u.path, Path::new("/srv/a b"));
+        let u = url("sftp://host/~/notes").ok().unwrap();
+        assert_eq!(
+            (u.host.as_str(), u.port, u.path.as_path()),
+            ("host", None, Path::new("notes"))
+        );
+        assert!(SshUrl::parse(OsStr::new("/local/path")).is_none());
+
+        let u = url("ssh://[::1]:22/").ok().unwrap();
+        assert_eq!(
+            (u.host.as_str(), u.port, u.path.as_path()),
+            ("::1", Some(22), Path::new("/"))
+        );
+        let u = url("ssh://me@[fe80::1%eth0]").ok().unwrap();
+        assert_eq!((u.host.as_str(), u.port), ("fe80::1%eth0", None));
+        assert_eq!(
+            u.with_path(Path::new("/tmp")),
+            "ssh://me@[fe80::1%eth0]/tmp"
+        );
+
+        let bad = [
+            "ssh://::1/",
+            "ssh://[::1/",
+            "ssh://[::1]x/",
+            "ssh://[::1]:/",
+            "ssh://@h",
+            "ssh://h:x",
+        ];
+        for bad in bad {
+            assert!(url(bad).is_err(), "{}", bad);
+        }
+    }
+
+    const CONTENT_LEN: usize = 70_000;
+
+    // Answers like an SFTP server with a home directory of `a.txt` and
+    // `sub`, read in two parts. `/bad` lists garbage, `/huge` answers with
+    // a packet too long. Open handles are counted.
+    fn serve(mut stream: UnixStream, open: Arc<AtomicUsize>) {
+        let content: Vec<u8> = (0..CONTENT_LEN).map(|i| i as u8).collect();
+        let mut dir_reads = 0;
+        let file_attrs = |p: Packet, mode: u32, size: u64| {
+            p.u32(SSH_FILEXFER_ATTR_SIZE | SSH_FILEXFER_ATTR_PERMISSIONS)
+                .u64(size)
+                .u32(mode)
+        };
+        loop {
+            let mut len = [0; 4];
+            if stream.read_exact(&mut len).is_err() {
+                return;
+            }
+            let mut data = vec![0; u32::from_be_bytes(len) as usize];
+            stream.read_exact(&mut data).unwrap();
+            let mut req = Reader { data: &data };
+            let typ = req.take(1).unwrap()[0];
+            if typ == SSH_FXP_INIT {
+                let reply = Packet::new(SSH_FXP_VERSION).u32(3).data;
+                stream
+                    .write_all(&(reply.len() as u32).to_be_bytes())
+                    .unwrap();
+                stream.write_all(&reply).unwrap();
+                continue;
+            }
+            let id = req.u32().unwrap();
+            let status = |code: u32| {
+                Packet::new(SSH_FXP_STATUS)
+                    .u32(id)
+                    .u32(code)
+                    .bytes(b"")
+                    .bytes(b"")
+            };
+            let handle = |h: &[u8]| {
+                open.fetch_add(1, Ordering::SeqCst);
+                Packet::new(SSH_FXP_HANDLE).u32(id).bytes(h)
+            };
+            let arg = req.bytes().unwrap().to_vec();
+            let reply = match (typ, &arg[..]) {
+                (SSH_FXP_REALPATH, b".") => Packet::new(SSH_FXP_NAME)
+                    .u32(id)
+                    .u32(1)
+                    .bytes(b"/home/me")
+                    .bytes(b"")
+                    .u32(0),
+                (SSH_FXP_OPENDIR, b"/home/me") => handle(b"dir"),
+                (SSH_FXP_OPENDIR, b"/bad") => handle(b"bad"),
+                (SSH_FXP_READDIR, b"dir") => {
+                    dir_reads += 1;
+                    match dir_reads {
+                        1 => {
+                            let p = Packet::new(SSH_FXP_NAME).u32(id).u32(2);
+                            let p = file_attrs(p.bytes(b".").bytes(b""), 0o40755, 0);
+                            let p = p.bytes(b"a.txt").bytes(b"-rw-r--r-- 2 me staff 70000 x");
+                            file_attrs(p, 0o100644, CONTENT_LEN as u64)
+                        }
+                        2 => {
+                            let p = Packet::new(SSH_FXP_NAME).u32(id).u32(1);
+                            file_attrs(p.bytes(b"sub").bytes(b""), 0o40700, 0)
+                        }
+                        _ => {
+                            dir_reads = 0;
+                            status(SSH_FX_EOF)
+                        }
+                    }
+                }
+                // two entries announced, one sent
+                (SSH_FXP_READDIR, b"bad") => {
+                    let p = Packet::new(SSH_FXP_NAME).u32(id).u32(2);
+                    file_attrs(p.bytes(b"x").bytes(b""), 0o100644, 0)
+                }
+                (SSH_FXP_CLOSE, _) => {
+                    open.fetch_sub(1, Ordering::SeqCst);
+                    status(0)
+                }
+                (SSH_FXP_OPEN, b"/home/me/a.txt") => handle(b"file"),
+                (SSH_FXP_READ, b"file") => {
+                    let offset = req.u64().unwrap() as usize;
+                    let len = req.u32().unwrap() as usize;
+                    match offset < content.len() {
+                        true => {
+                            let end = content.len().min(offset + len);
+                            Packet::new(SSH_FXP_DATA)
+                                .u32(id)
+                                .bytes(&content[offset..end])
+                        }
+                        false => status(SSH_FX_EOF),
+                    }
+                }
+                // a body follows which would pass for the next reply
+                (SSH_FXP_LSTAT, b"/huge") => {
+                    stream.write_all(&u32::MAX.to_be_bytes()).unwrap();
+                    file_attrs(Packet::new(SSH_FXP_ATTRS).u32(id + 1), 0o40755, 0)
+                }
+                // the reply to the request after it
+                (SSH_FXP_LSTAT, b"/ahead") => {
+                    file_attrs(Packet::new(SSH_FXP_ATTRS).u32(id + 1), 0o40755, 0)
+                }
+                _ => status(SSH_FX_NO_SUCH_FILE),
+            };
+            stream
+                .write_all(&(reply.data.len() as u32).to_be_bytes())
+                .unwrap();
+            stream.write_all(&reply.data).unwrap();
+        }
+    }
+
+    fn start() -> (Sftp, Arc<AtomicUsize>) {
+        let (client, server) = UnixStream::pair().unwrap();
+        let open = Arc::new(AtomicUsize::new(0));
+        let counter = open.clone();
+        thread::spawn(move || serve(server, counter));
+        let sftp = Sftp::new(Box::new(client.try_clone().unwrap()), Box::new(client));
+        sftp.init().unwrap();
+        (sftp, open)
+    }
+
+    #[test]
+    fn lists_and_reads() {
+        let (sftp, open) = start();
+        let home = sftp.realpath(Path::new("")).unwrap();
+        assert_eq!(home, Path::new("/home/me"));
+
+        let nodes = sftp.list(&home).unwrap();
+        let names: Vec<&OsStr> = nodes.iter().map(|n| n.name.as_os_str()).collect();
+        assert_eq!(names, ["a.txt", "sub"]);
+        assert!(nodes[0].typ == NodeType::File);
+        assert_eq!((nodes[0].size, nodes[0].nlink), (70_000, 2));
+        assert_eq!(nodes[0].user, "me");
+        assert!(nodes[1].typ == NodeType::Dir);
+        assert_eq!(nodes[1].mode & 0o777, 0o700);
+
+        let mut content = Vec::new();
+        sftp.read(&home.join("a.txt"))
+            .unwrap()
+            .read_to_end(&mut content)
+            .unwrap();
+        assert_eq!(content.len(), CONTENT_LEN);
+        assert!(content.iter().enumerate().all(|(i, b)| *b == i as u8));
+        assert_eq!(open.load(Ordering::SeqCst), 0);
+
+        let err = sftp.list(Path::new("/missing")).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn closes_handles_of_bad_listings() {
+        let (sftp, open) = start();
+        let err = sftp.list(Path::new("/bad")).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(open.load(Ordering::SeqCst), 0);
+        // still in step with the server
+        assert_eq!(sftp.list(Path::new("/home/me")).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn refuses_huge_packets() {
+        let (sftp, _) = start();
+        let err = sftp.stat(Path::new("/huge")).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        // out of step with the server, nothing more is asked
+        let err = sftp.stat(Path::new("/home/me")).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::NotConnected);
+        assert!(sftp.list(Path::new("/home/me")).is_err());
+        assert_eq!(sftp.take_error().as_deref(), Some("SFTP packet too long"));
+        assert_eq!(sftp.take_error(), None);
+    }
+
+    #[test]
+    fn gives_up_on_replies_out_of_order() {
+        let (sftp, _) = start();
+        let err = sftp.stat(Path::new("/ahead")).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let err = sftp.stat(Path::new("/home/me")).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::NotConnected);
+        assert_eq!(sftp.take_error().as_deref(), Some("unexpected SFTP reply"));
+    }
+
+    #[test]
+    fn reports_what_ssh_said() {
+        let script = std::env::temp_dir().join(format!("navigator-ssh-{}", std::process::id()));
+        std::fs::write(
+            &script,
+            "#!/bin/sh\necho 'ssh: Could not resolve hostname' >&2\nexit 255\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&script, std::os::unix::fs::PermissionsExt::from_mode(0o755))
+            .unwrap();
+        let err = Sftp::connect(
+            &url("ssh://nowhere").ok().unwrap(),
+            script.to_str().unwrap(),
+        )
+        .err()
+        .unwrap();
+        std::fs::remove_file(&script).unwrap();
+        let message = err.to_string();
+        assert!(message.contains("cannot connect to nowhere"), "{}", message);
+        assert!(
+            message.contains("Could not resolve hostname"),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn ssh_command_is_read_by_the_shell() {
+        let home = std::env::temp_dir().join(format!("navigator-home-{}", std::process::id()));
+        std::fs::create_dir_all(&home).unwrap();
+        let script = home.join("fake-ssh");
+        std::fs::write(
+            &script,
+            "#!/bin/sh\nfor arg; do echo \"[$arg]\" >&2; done\nexit 255\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&script, std::os::unix::fs::PermissionsExt::from_mode(0o755))
+            .unwrap();
+        let mut command = Sftp::command(
+            &url("ssh://alice@nowhere:2222").ok().unwrap(),
+            "~/fake-ssh -o 'Foo #1' -F \"a b\"",
+        );
+        command.env("HOME", &home);
+        let err = Sftp::spawn(command, "nowhere").err().unwrap();
+        std::fs::remove_dir_all(&home).unwrap();
+        let message = err.to_string();
+        let said: Vec<&str> = message.lines().skip(1).collect();
+        assert_eq!(
+            said,
+            [
+                "[-o]",
+                "[Foo #1]",
+                "[-F]",
+                "[a b]",
+                "[-o]",
+                "[ForwardX11=no]",
+                "[-o]",
+                "[ClearAllForwardings=yes]",
+                "[-p]",
+                "[2222]",
+                "[-l]",
+                "[alice]",
+                "[-s]",
+                "[--]",
+                "[nowhere]",
+                "[sftp]",
+            ]
+        );
+    }
+}
@@ -0,0 +1,415 @@
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, DirBuilder, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::path::{Path, PathBuf};
+
+use crate::common::AppError;
+use crate::filesystem::{escape_name, percent_decode, percent_encode};
+
+/// An entry in one of the trash directories, following the freedesktop.org
+/// trash specification: the file is in `files/`, where it came from and
+/// when in `info/NAME.trashinfo`.
+pub struct TrashEntry {
+    pub trash_dir: PathBuf,
+    /// Name in `files/`, the original name made unique.
+    pub name: OsString,
+    pub original: PathBuf,
+    /// As written in the info file, e.g. `2024-05-01T12:30:00`.
+    pub deleted: String,
+}
+
+impl TrashEntry {
+    pub fn file_path(&self) -> PathBuf {
+        self.trash_dir.join("files").join(&self.name)
+    }
+
+    fn info_path(&self) -> PathBuf {
+        info_path(&self.trash_dir, &self.name)
+    }
+}
+
+fn info_path(trash_dir: &Path, name: &OsStr) -> PathBuf {
+    let mut file = name.to_os_string();
+    file.push(".trashinfo");
+    trash_dir.join("info").join(file)
+}
+
+fn path_error(path: &Path) -> impl FnOnce(io::Error) -> AppError + '_ {
+    move |err| AppError::PathError(err.to_string(), escape_name(path.as_os_str()))
+}
+
+/* #region Trash directories */
+
+/// `$XDG_DATA_HOME/Trash`, by default `~/.local/share/Trash`.
+fn home_trash() -> Option<PathBuf> {
+    let data = match env::var_os("XDG_DATA_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".local/share"),
+    };
+    Some(data.join("Trash"))
+}
+
+// device of the path, or of its nearest existing ancestor
+fn device_of(path: &Path) -> Option<u64> {
+    path.ancestors()
+        .find_map(|p| fs::metadata(p).ok())
+        .map(|md| md.dev())
+}
+
+// the top directory of the file system `path` is on
+fn mount_top(path: &Path, dev: u64) -> PathBuf {
+    let mut top = path.parent().unwrap_or(path).to_path_buf();
+    while let Some(parent) = top.parent() {
+        if fs::metadata(parent).map(|md| md.dev()).ok() != Some(dev) {
+            break;
+        }
+        top = parent.to_path_buf();
+    }
+    top
+}
+
+/// The trash directory for a file: the home trash if the file is on the
+/// same file system, otherwise `$topdir/.Trash/$uid` if the administrator
+/// set up `.Trash` (a sticky directory), else `$topdir/.Trash-$uid`. Files
+/// are moved, never copied.
+fn trash_dir_for(path: &Path) -> Result<PathBuf, AppError> {
+    let dev = fs::symlink_metadata(path).map_err(path_error(path))?.dev();
+    if let Some(home) = home_trash() {
+        if device_of(&home) == Some(dev) {
+            return Ok(home);
+        }
+    }
+    let top = mount_top(path, dev);
+    let uid = users::get_current_uid();
+    let shared = top.join(".Trash");
+    if let Ok(md) = fs::symlink_metadata(&shared) {
+        if md.is_dir() && md.mode() & 0o1000 != 0 {
+            return Ok(shared.join(uid.to_string()));
+        }
+    }
+    Ok(top.join(format!(".Trash-{}", uid)))
+}
+
+/// What relative paths in the info files of a trash start from: `$topdir`
+/// for `$topdir/.Trash/$uid` and `$topdir/.Trash-$uid`.
+fn top_dir(trash_dir: &Path) -> Option<&Path> {
+    let parent = trash_dir.parent()?;
+    match parent.ends_with(".Trash") {
+        true => parent.parent(),
+        false => Some(parent),
+    }
+}
+
+/// All trash directories which exist: the home trash and those at the top
+/// of mounted file systems.
+fn trash_dirs() -> Vec<PathBuf> {
+    let uid = users::get_current_uid();
+    let mut dirs: Vec<PathBuf> = home_trash().into_iter().collect();
+    for top in mount_points() {
+        dirs.push(top.join(".Trash").join(uid.to_string()));
+        dirs.push(top.join(format!(".Trash-{}", uid)));
+    }
+    let mut found: Vec<PathBuf> = Vec::new();
+    for dir in dirs {
+        if dir.join("info").is_dir() && !found.contains(&dir) {
+            found.push(dir);
+        }
+    }
+    found
+}
+
+// from /proc/self/mounts, where spaces and such are octal escapes
+fn mount_points() -> Vec<PathBuf> {
+    match fs::read("/proc/self/mounts") {
+        Ok(mounts) => parse_mounts(&mounts),
+        Err(_) => Vec::new(),
+    }
+}
+
+fn parse_mounts(mounts: &[u8]) -> Vec<PathBuf> {
+    mounts
+        .split(|b| *b == b'\n')
+        .filter_map(|line| line.split(|b| *b == b' ').nth(1))
+        .map(|field| {
+            let mut bytes = Vec::new();
+            let mut i = 0;
+            while i < field.len() {
+                let octal = field
+                    .get(i + 1..i + 4)
+                    .and_then(|d| u8::from_str_radix(std::str::from_utf8(d).ok()?, 8).ok());
+                match (field[i], octal) {
+                    (b'\\', Some(b)) => {
+                        bytes.push(b);
+                        i += 4;
+                    }
+                    (b, _) => {
+                        bytes.push(b);
+                        i += 1;
+                    }
+                }
+            }
+            PathBuf::from(OsString::from_vec(bytes))
+        })
+        .collect()
+}
+
+/* #endregion */
+
+/* #region Operations */
+
+/// Moves the file or directory at `path` (absolute) to the trash.
+pub fn trash(path: &Path) -> Result<TrashEntry, AppError> {
+    let trash_dir = trash_dir_for(path)?;
+    let files = trash_dir.join("files");
+    let info = trash_dir.join("info");
+    for dir in [&files, &info] {
+        DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)
+            .map_err(path_error(dir))?;
+    }
+
+    let base = path.file_name().unwrap_or(path.as_os_str());
+    // the info file is created first, exclusively, and so reserves the name
+    let mut n = 1;
+    let (name, mut info_file) = loop {
+        let mut name = base.to_os_string();
+        if n > 1 {
+            name.push(format!(".{}", n));
+        }
+        n += 1;
+        if fs::symlink_metadata(files.join(&name)).is_ok() {
+            continue;
+        }
+        let info_path = info_path(&trash_dir, &name);
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&info_path)
+        {
+            Ok(file) => break (name, file),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(path_error(&info_path)(err)),
+        }
+    };
+
+    let entry = TrashEntry {
+        original: path.to_path_buf(),
+        deleted: chrono::Local::now().format("%Y-%m-%dT%H:%M:%S").to_string(),
+        name,
+        trash_dir,
+    };
+    // paths in a trash at the top of a file system are relative to the top
+    let written = match Some(entry.trash_dir.as_path()) == home_trash().as_deref() {
+        true => path,
+        false => top_dir(&entry.trash_dir)
+            .and_then(|top| path.strip_prefix(top).ok())
+            .unwrap_or(path),
+    };
+    let text = format!(
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        percent_encode(written),
+        entry.deleted
+    );
+    let result = info_file
+        .write_all(text.as_bytes())
+        .and_then(|_| fs::rename(path, entry.file_path()));
+    if let Err(err) = result {
+        let _ = fs::remove_file(entry.info_path());
+        return Err(path_error(path)(err));
+    }
+    Ok(entry)
+}
+
+/// Lists the entries of all trash directories, the latest deleted first.
+pub fn list() -> Vec<TrashEntry> {
+    let mut entries = Vec::new();
+    for trash_dir in trash_dirs() {
+        let Ok(infos) = fs::read_dir(trash_dir.join("info")) else {
+            continue;
+        };
+        for info in infos.flatten() {
+            let file_name = info.file_name();
+            let Some(name) = file_name.as_bytes().strip_suffix(b".trashinfo") else {
+                continue;
+            };
+            let Ok(text) = fs::read_to_string(info.path()) else {
+                continue;
+            };
+            let name = OsStr::from_bytes(name).to_os_string();
+            if let Some(entry) = parse_info(&trash_dir, name, &text) {
+                entries.push(entry);
+            }
+        }
+    }
+    entries.sort_by(|a, b| b.deleted.cmp(&a.deleted));
+    entries
+}
+
+fn parse_info(trash_dir: &Path, name: OsString, text: &str) -> Option<TrashEntry> {
+    let mut lines = text.lines().map(str::trim);
+    if lines.next()? != "[Trash Info]" {
+        return None;
+    }
+    let (mut original, mut deleted) = (None, String::new());
+    for line in lines {
+        if let Some(path) = line.strip_prefix("Path=") {
+            original = Some(percent_decode(path));
+        } else if let Some(date) = line.strip_prefix("DeletionDate=") {
+            deleted = date.to_owned();
+        }
+    }
+    let mut original = original?;
+    if original.is_relative() {
+        original = top_dir(trash_dir)?.join(original);
+    }
+    Some(TrashEntry {
+        trash_dir: trash_dir.to_path_buf(),
+        name,
+        original,
+        deleted,
+    })
+}
+
+/// Moves the entry back where it was deleted from. Fails if something is
+/// there now.
+pub fn restore(entry: &TrashEntry) -> Result<(), AppError> {
+    let original = &entry.original;
+    if fs::symlink_metadata(original).is_ok() {
+        return Err(AppError::PathError(
+            "already exists".to_owned(),
+            escape_name(original.as_os_str()),
+        ));
+    }
+    if let Some(parent) = original.parent() {
+        fs::create_dir_all(parent).map_err(path_error(parent))?;
+    }
+    fs::rename(entry.file_path(), original).map_err(path_error(original))?;
+    let _ = fs::remove_file(entry.info_path());
+    Ok(())
+}
+
+/// Deletes the entry for good.
+pub fn purge(entry: &TrashEntry) -> Result<(), AppError> {
+    let path = entry.file_path();
+    let result = match fs::symlink_metadata(&path) {
+        Ok(md) if md.is_dir() => fs::remove_dir_all(&path),
+        Ok(_) => fs::remove_file(&path),
+        // only the info file was left
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err),
+    };
+    result.map_err(path_error(&path))?;
+    fs::remove_file(entry.info_path()).map_err(path_error(&entry.info_path()))
+}
+
+/* #endregion */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process;
+
+    #[test]
+    fn parses_info_files() {
+        let trash_dir = Path::new("/mnt/disk/.Trash-1000");
+        let entry = parse_info(
+            trash_dir,
+            OsString::from("a b.2"),
+            "[Trash Info]\nPath=dir/a%20b\nDeletionDate=2024-05-01T12:30:00\n",
+        )
+        .unwrap();
+        assert_eq!(entry.original, Path::new("/mnt/disk/dir/a b"));
+        assert_eq!(entry.deleted, "2024-05-01T12:30:00");
+        assert_eq!(
+            entry.file_path(),
+            Path::new("/mnt/disk/.Trash-1000/files/a b.2")
+        );
+
+        let shared = Path::new("/mnt/disk/.Trash/1000");
+        let entry = parse_info(shared, OsString::from("x"), "[Trash Info]\nPath=x\n").unwrap();
+        assert_eq!(entry.original, Path::new("/mnt/disk/x"));
+        assert_eq!(entry.deleted, "");
+
+        let entry = parse_info(shared, OsString::from("x"), "[Trash Info]\nPath=/x%FF\n");
+        assert_eq!(entry.unwrap().original.as_os_str().as_bytes(), b"/x\xff");
+
+        for text in [
+            "Path=/x\n",
+            "",
+            "[Trash Info]\nDeletionDate=2024-05-01T12:30:00\n",
+        ] {
+            assert!(parse_info(shared, OsString::from("x"), text).is_none());
+        }
+    }
+
+    #[test]
+    fn unescapes_mount_points() {
+        let mounts = b"proc /proc proc rw 0 0\n\
+            /dev/sdb1 /media/My\\040Disk vfat rw 0 0\n\
+            /dev/sdc1 /mnt/tab\\011back\\134slash ext4 rw 0 0\n\
+            /dev/sdd1 /mnt/not\\08 ext4 rw 0 0\n";
+        assert_eq!(
+            parse_mounts(mounts),
+            [
+                Path::new("/proc"),
+                Path::new("/media/My Disk"),
+                Path::new("/mnt/tab\tback\\slash"),
+                Path::new("/mnt/not\\08"),
+            ]
+        );
+    }
+
+    #[test]
+    fn trashes_lists_restores_and_purges() {
+        let top = env::temp_dir().join(format!("navigator-trash-{}", process::id()));
+        let _ = fs::remove_dir_all(&top);
+        let data = top.join("data");
+        let work = top.join("work");
+        fs::create_dir_all(&work).unwrap();
+        env::set_var("XDG_DATA_HOME", &data);
+        let trash_dir = data.join("Trash");
+        let ours = || {
+            let mut entries = list();
+            entries.retain(|e| e.trash_dir == trash_dir);
+            entries
+        };
+
+        let file = work.join("a file");
+        fs::write(&file, "one").unwrap();
+        let first = trash(&file).unwrap();
+        assert_eq!(first.trash_dir, trash_dir);
+        assert!(!file.exists());
+        fs::write(&file, "two").unwrap();
+        let second = trash(&file).unwrap();
+        assert_eq!(second.name, "a file.2");
+        let dir = work.join("dir");
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        trash(&dir).unwrap();
+
+        let info = fs::read_to_string(second.info_path()).unwrap();
+        assert!(info.starts_with("[Trash Info]\nPath=/"), "{}", info);
+        assert!(info.contains("/work/a%20file\n"), "{}", info);
+        let mut names: Vec<OsString> = ours().into_iter().map(|e| e.name).collect();
+        names.sort();
+        assert_eq!(names, ["a file", "a file.2", "dir"]);
+
+        let entries = ours();
+        let entry = |name: &str| entries.iter().find(|e| e.name == name).unwrap();
+        restore(entry("a file.2")).unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "two");
+        // the other one can't go back there now
+        assert!(restore(entry("a file")).is_err());
+        purge(entry("a file")).unwrap();
+        purge(entry("dir")).unwrap();
+        assert!(ours().is_empty());
+        assert_eq!(fs::read_dir(trash_dir.join("files")).unwrap().count(), 0);
+
+        env::remove_var("XDG_DATA_HOME");
+        fs::remove_dir_all(&top).unwrap();
+    }
+}
@@ -1,12 +1,21 @@
 use std::{
     cell::RefCell,
-    ffi::OsString,
+    ffi::{OsStr, OsString},
+    io,
     path::{Component, Components, Path, PathBuf},
     rc::{Rc, Weak},
 };
 
+use crate::archive::{Archive, ArchiveKind};
+use crate::bulk_rename::{self, NamePair};
+use crate::file_ops::{copy_all, exists, move_entry};
+use crate::git::GitState;
 use crate::graph::{list_view::ListView, tree_view::TreeView};
-use crate::{common::*, filesystem::*, tree_node::*};
+use crate::journal::{Journal, Operation};
+use crate::permissions::{AttrChange, ChangeReport};
+use crate::provider::FsProvider;
+use crate::trash;
+use crate::{common::*, dir_size::*, filesystem::*, tree_node::*};
 
 struct Cursor {
     node: Option<TreeNodeRef>,
@@ -32,18 +41,37 @@ pub struct Tree {
     pub tree_view: Weak<RefCell<TreeView>>,
     pub list_view: Weak<RefCell<ListView>>,
     pub root: TreeNodeRef,
+    pub load_opts: LoadOptions,
+    pub size_jobs: SizeJobs,
+    /// Shown in the status line instead of the path until the next key.
+    pub status_msg: Option<String>,
+    /// The last search pattern, for searching again.
+    pub search: Option<String>,
+    /// Status of the git repository around the current directory.
+    pub git: GitState,
+    /// File operations done, for undo and redo. Shared by the trees of both
+    /// panes in the dual layout.
+    pub journal: Rc<RefCell<Journal>>,
+    sort_when_sized: Option<TreeNodeWeak>,
     cursor: Cursor,
 }
 
 impl Tree {
-    pub fn new() -> Tree {
+    pub fn new(load_opts: LoadOptions, one_file_system: bool, git: bool) -> Tree {
         let root = TreeNode::from(SysNode::new(&OsString::from("/"), NodeType::Dir));
         root.borrow_mut().expanded = true;
-        let _ = TreeNode::load(&root); // Error ignored
+        let _ = TreeNode::load(&root, &load_opts); // Error ignored
         Tree {
             tree_view: Weak::new(),
             list_view: Weak::new(),
             root: root.clone(),
+            load_opts,
+            size_jobs: SizeJobs::new(one_file_system),
+            status_msg: None,
+            search: None,
+            git: GitState::new(git),
+            journal: Rc::new(RefCell::new(Journal::new())),
+            sort_when_sized: None,
             cursor: Cursor {
                 node: None,
                 tpos: 0,
@@ -91,6 +119,21 @@ impl Tree {
         self.goto(&node)
     }
 
+    /// Moves to any directory, e.g. one typed by the user, and redraws both
+    /// views.
+    pub fn jump_to_path(&mut self, path: &Path) -> Result<(), AppError> {
+        self.go_to_path(path)?;
+        if let Some(tv) = self.tree_view.upgrade() {
+            tv.borrow_mut().modif_flags.render = true;
+            tv.borrow_mut().modif_flags.print = true;
+        }
+        if let Some(lv) = self.list_view.upgrade() {
+            lv.borrow_mut().modif_flags.render = true;
+            lv.borrow_mut().modif_flags.print = true;
+        }
+        Ok(())
+    }
+
     fn move_to_list_node(&mut self, node: &TreeNodeRef) -> Result<(), AppError> {
         let cd = self.curr_dir();
         if let Some(idx) = cd
@@ -140,7 +183,7 @@ impl Tree {
         let old_cd = self.curr_dir();
         let ul = self.move_from_to(&old_cd, node)?;
 
-        let _ = TreeNode::load(node); // Error ignored
+        let _ = TreeNode::load(node, &self.load_opts); // Error ignored
 
         if let Some(lv) = self.list_view.upgrade() {
             lv.borrow_mut().modif_flags.render = true;
@@ -200,6 +243,51 @@ impl Tree {
         }
         Ok(())
     }
+
+    /// Deletes the current directory from disk and moves to its parent.
+    pub fn tv_delete(&mut self, tv: &mut TreeView) -> Result<(), AppError> {
+        let cd = self.curr_dir();
+        let Some(parent) = cd.borrow().parent.upgrade() else {
+            return Ok(());
+        };
+        let (fs, path) = cd.borrow().location();
+        if let Err(err) = fs.remove(&path) {
+            self.status_msg = Some(format!(
+                "Cannot delete {}: {}",
+                escape_name(path.as_os_str()),
+                err
+            ));
+            // partially deleted, sizes must be computed again
+            cd.borrow_mut().unload();
+            TreeNode::reset_sizes(&cd);
+            tv.modif_flags.render = true;
+            tv.modif_flags.print = true;
+            return Ok(());
+        }
+
+        let freed = match cd.borrow().dir_size {
+            DirSize::Done(size) => Some(size + cd.borrow().sys_node.size),
+            _ => None,
+        };
+        self.move_from_to(&cd, &parent)?;
+        parent
+            .borrow_mut()
+            .subnodes
+            .retain(|sn| !Rc::ptr_eq(sn, &cd));
+        match freed {
+            Some(freed) => TreeNode::subtract_size(&parent, freed),
+            None => TreeNode::reset_sizes(&parent),
+        }
+        self.status_msg = Some(format!("Deleted {}", escape_name(path.as_os_str())));
+
+        if let Some(lv) = self.list_view.upgrade() {
+            lv.borrow_mut().modif_flags.render = true;
+            lv.borrow_mut().modif_flags.print = true;
+        }
+        tv.modif_flags.render = true;
+        tv.modif_flags.print = true;
+        Ok(())
+    }
     /* #endregion */
 
     /* #region ListView Operations */
@@ -212,11 +300,12 @@ impl Tree {
 
     pub fn lv_enter(&mut self, lv: &mut ListView) -> Result<(), AppError> {
         if let Some(file) = self.curr_file() {
+            self.open_archive(&file);
             if file.borrow().sys_node.typ == NodeType::Dir {
                 let cd = self.curr_dir();
                 cd.borrow_mut().expanded = true;
                 let _ul = self.move_from_to(&cd, &file)?;
-                let _ = TreeNode::load(&file); // Error ignored
+                let _ = TreeNode::load(&file, &self.load_opts); // Error ignored
                 if let Some(tv) = self.tree_view.upgrade() {
                     tv.borrow_mut().modif_flags.render = true;
                     tv.borrow_mut().modif_flags.print = true;
@@ -228,6 +317,295 @@ impl Tree {
         Ok(())
     }
 
+    // archives on the local file system are entered like directories
+    fn open_archive(&mut self, file: &TreeNodeRef) {
+        let kind = {
+            let file = file.borrow();
+            if file.sys_node.typ != NodeType::File || !file.location().0.is_local() {
+                return;
+            }
+            match ArchiveKind::detect(&file.sys_node.name) {
+                Some(kind) => kind,
+                None => return,
+            }
+        };
+        let path = file.borrow().get_path();
+        match Archive::open(&path, kind) {
+            Ok(archive) => TreeNode::mount(file, Rc::new(archive)),
+            Err(err) => self.status_msg = Some(err.to_string()),
+        }
+    }
+
+    pub fn lv_compute_size(&mut self, lv: &mut ListView) -> Result<(), AppError> {
+        if let Some(file) = self.curr_file() {
+            self.size_jobs.retry(&file);
+            lv.modif_flags.render = true;
+            lv.modif_flags.print = true;
+        }
+        Ok(())
+    }
+
+    pub fn lv_compute_all_sizes(&mut self, lv: &mut ListView) -> Result<(), AppError> {
+        let cd = self.curr_dir();
+        for node in &cd.borrow().subnodes {
+            self.size_jobs.retry(node);
+        }
+        self.sort_when_sized = Some(Rc::downgrade(&cd));
+        self.sort_sized_dir();
+        lv.modif_flags.render = true;
+        lv.modif_flags.print = true;
+        Ok(())
+    }
+
+    pub fn lv_toggle_mark(&mut self, lv: &mut ListView) -> Result<(), AppError> {
+        if let Some(file) = self.curr_file() {
+            let marked = file.borrow().marked;
+            file.borrow_mut().marked = !marked;
+            lv.modif_flags.render = true;
+            lv.modif_flags.print = true;
+        }
+        Ok(())
+    }
+
+    /// The marked entries of the current directory, or the one under the
+    /// cursor if none are marked.
+    pub fn marked_files(&self) -> Vec<TreeNodeRef> {
+        let cd = self.curr_dir();
+        let marked: Vec<TreeNodeRef> = cd
+            .borrow()
+            .subnodes
+            .iter()
+            .filter(|n| n.borrow().marked)
+            .cloned()
+            .collect();
+        match marked.is_empty() {
+            true => self.curr_file().into_iter().collect(),
+            false => marked,
+        }
+    }
+
+    /// The marked entries of the current directory, or all of them if none
+    /// are marked.
+    pub fn marked_or_all(&self) -> Vec<TreeNodeRef> {
+        let cd = self.curr_dir();
+        let subnodes = &cd.borrow().subnodes;
+        let marked: Vec<TreeNodeRef> = subnodes
+            .iter()
+            .filter(|n| n.borrow().marked)
+            .cloned()
+            .collect();
+        match marked.is_empty() {
+            true => subnodes.clone(),
+            false => marked,
+        }
+    }
+
+    /// Changes the permissions and owner of `nodes`, then reads their
+    /// attributes again and unmarks them.
+    pub fn change_attrs(&mut self, nodes: &[TreeNodeRef], change: &AttrChange) -> ChangeReport {
+        let mut report = ChangeReport {
+            changed: 0,
+            failures: Vec::new(),
+        };
+        for node in nodes {
+            change.apply(&node.borrow().get_path(), &mut report);
+            TreeNode::refresh(node, change.recursive);
+            node.borrow_mut().marked = false;
+        }
+        if let Some(tv) = self.tree_view.upgrade() {
+            tv.borrow_mut().modif_flags.render = true;
+            tv.borrow_mut().modif_flags.print = true;
+        }
+        if let Some(lv) = self.list_view.upgrade() {
+            lv.borrow_mut().modif_flags.render = true;
+            lv.borrow_mut().modif_flags.print = true;
+        }
+        report
+    }
+
+    /// Moves the marked entries, or the one under the cursor, to the trash.
+    pub fn lv_trash(&mut self, lv: &mut ListView) -> Result<(), AppError> {
+        let nodes = self.marked_files();
+        let mut ops = Vec::new();
+        let mut error = None;
+        for node in &nodes {
+            match trash::trash(&node.borrow().get_path()) {
+                Ok(entry) => ops.push(Operation::trashed(entry)),
+                Err(err) => error = Some(err),
+            }
+        }
+        let moved = ops.len();
+        self.journal.borrow_mut().record(ops);
+        self.status_msg = match (error, moved) {
+            (Some(err), 0) => Some(format!("Cannot move to the trash: {}", err)),
+            (Some(err), moved) => Some(format!(
+                "Moved {} entries to the trash, then: {}",
+                moved, err
+            )),
+            (None, 1) => Some(format!(
+                "Moved {} to the trash",
+                escape_name(&nodes[0].borrow().sys_node.name)
+            )),
+            (None, moved) => Some(format!("Moved {} entries to the trash", moved)),
+        };
+        self.warn_unsaved();
+        if moved > 0 {
+            self.reload(&self.curr_path());
+            if let Some(tv) = self.tree_view.upgrade() {
+                tv.borrow_mut().modif_flags.render = true;
+                tv.borrow_mut().modif_flags.print = true;
+            }
+        }
+        lv.modif_flags.render = true;
+        lv.modif_flags.print = true;
+        Ok(())
+    }
+
+    /// Reads a directory again if it's loaded, after entries were moved in or
+    /// out of it. The cursor stays on the same entries, or goes up if its
+    /// directory is gone.
+    pub fn reload_dir(&mut self, path: &Path) {
+        if !self.reload(path) {
+            return;
+        }
+        if let Some(tv) = self.tree_view.upgrade() {
+            tv.borrow_mut().modif_flags.render = true;
+            tv.borrow_mut().modif_flags.print = true;
+        }
+        if let Some(lv) = self.list_view.upgrade() {
+            lv.borrow_mut().modif_flags.render = true;
+            lv.borrow_mut().modif_flags.print = true;
+        }
+    }
+
+    fn reload(&mut self, path: &Path) -> bool {
+        let Some(dir) = self.find_loaded(path) else {
+            return false;
+        };
+        let cd = self.curr_dir();
+        let cf = self.curr_file();
+        let _ = TreeNode::reload(&dir, &self.load_opts); // Error ignored
+        TreeNode::reset_sizes(&dir);
+
+        // the cursor is kept as indexes, which entries before it shifted
+        let mut target = cd.clone();
+        let mut node = cd.clone();
+        loop {
+            let Some(parent) = node.borrow().parent.upgrade() else {
+                break;
+            };
+            if !parent
+                .borrow()
+                .subnodes
+                .iter()
+                .any(|n| Rc::ptr_eq(n, &node))
+            {
+                target = parent.clone();
+            }
+            node = parent;
+        }
+        let lpos = self.cursor.lpos;
+        let _ = self.goto(&target);
+        if Rc::ptr_eq(&target, &cd) {
+            // where the entry was if it's gone
+            let count = cd.borrow().subnodes.len();
+            self.cursor.lpos = lpos.min(count.saturating_sub(1));
+            if let Some(cf) = cf {
+                let _ = self.move_to_list_node(&cf);
+            }
+        }
+        true
+    }
+
+    /// Browses another file system than the local one from its top, e.g. a
+    /// remote host.
+    pub fn mount_root(&mut self, fs: Rc<dyn FsProvider>) {
+        TreeNode::mount(&self.root, fs);
+        let _ = TreeNode::load(&self.root, &self.load_opts); // Error ignored
+        self.cursor = Cursor {
+            node: None,
+            tpos: 0,
+            lpos: 0,
+        };
+    }
+
+    /// Reads the current directory and the git status again, for changes
+    /// made outside the navigator.
+    pub fn refresh(&mut self) {
+        let path = self.curr_path();
+        self.reload_dir(&path);
+        // failed sizes are computed again when shown
+        for node in &self.curr_dir().borrow().subnodes {
+            let mut node = node.borrow_mut();
+            if node.dir_size == DirSize::Failed {
+                node.dir_size = DirSize::Unknown;
+            }
+        }
+        self.git.invalidate();
+    }
+
+    /// Picks up the git status read in the background, or asks for it if the
+    /// current directory moved to another repository or it's stale. Redraws
+    /// the views when it changed.
+    pub fn update_git(&mut self) {
+        let path = self.curr_path();
+        if !self.git.update(&path) {
+            return;
+        }
+        if let Some(tv) = self.tree_view.upgrade() {
+            tv.borrow_mut().modif_flags.render = true;
+            tv.borrow_mut().modif_flags.print = true;
+        }
+        if let Some(lv) = self.list_view.upgrade() {
+            lv.borrow_mut().modif_flags.render = true;
+            lv.borrow_mut().modif_flags.print = true;
+        }
+    }
+
+    /// Picks up finished size computations. The list is redrawn while
+    /// computing, to animate the spinner.
+    pub fn poll_sizes(&mut self) {
+        let busy = self.size_jobs.busy();
+        let changed = self.size_jobs.poll();
+        if changed {
+            self.sort_sized_dir();
+        }
+        if busy || changed {
+            if let Some(lv) = self.list_view.upgrade() {
+                lv.borrow_mut().modif_flags.render = true;
+                lv.borrow_mut().modif_flags.print = true;
+            }
+            if let Some(tv) = self.tree_view.upgrade() {
+                if tv.borrow().du_mode {
+                    tv.borrow_mut().modif_flags.render = true;
+                    tv.borrow_mut().modif_flags.print = true;
+                }
+            }
+        }
+    }
+
+    fn sort_sized_dir(&mut self) {
+        let Some(dir) = self.sort_when_sized.as_ref().and_then(|w| w.upgrade()) else {
+            return;
+        };
+        let computing = dir
+            .borrow()
+            .subnodes
+            .iter()
+            .any(|sn| sn.borrow().dir_size == DirSize::Computing);
+        if computing {
+            return;
+        }
+        self.sort_when_sized = None;
+        let curr_file = self.curr_file();
+        dir.borrow_mut().sort_by_size();
+        if Rc::ptr_eq(&dir, &self.curr_dir()) {
+            if let Some(file) = curr_file {
+                let _ = self.move_to_list_node(&file);
+            }
+        }
+    }
+
     pub fn lv_move_up(&mut self, lv: &mut ListView) -> Result<(), AppError> {
         let cd = self.curr_dir();
         let parent = cd.borrow().parent.upgrade();
@@ -244,6 +622,217 @@ impl Tree {
     }
     /* #endregion */
 
+    /* #region File Operations */
+
+    /// Copies or moves `nodes` into `dest` if it's a directory, else to that
+    /// path, which takes a single entry. Nothing is overwritten.
+    pub fn transfer(&mut self, nodes: &[TreeNodeRef], dest: &Path, copy: bool) {
+        let (verb, done) = if copy {
+            ("copy", "Copied")
+        } else {
+            ("move", "Moved")
+        };
+        // without `..` and symlinks, to compare with the sources
+        let dest = dest
+            .canonicalize()
+            .unwrap_or_else(|_| canonical_parent(dest));
+        let dest = dest.as_path();
+        let into = dest.is_dir();
+        if copy && nodes.iter().any(|n| !n.borrow().location().0.is_local()) {
+            self.extract(nodes, dest, into);
+            return;
+        }
+        if !into && nodes.len() > 1 {
+            self.status_msg = Some(format!(
+                "Cannot {} {} entries to {}: not a directory",
+                verb,
+                nodes.len(),
+                escape_name(dest.as_os_str())
+            ));
+            return;
+        }
+
+        let mut ops = Vec::new();
+        let mut error = None;
+        for node in nodes {
+            let from = node.borrow().get_path();
+            let to = match into {
+                true => dest.join(&node.borrow().sys_node.name),
+                false => dest.to_path_buf(),
+            };
+            // the entry itself may be a symlink, which is moved as it is
+            let result = if to.starts_with(canonical_parent(&from)) {
+                Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "destination is inside it",
+                ))
+            } else if copy {
+                copy_all(&from, &to)
+            } else {
+                move_entry(&from, &to)
+            };
+            match result {
+                Ok(()) if copy => ops.push(Operation::Copy { from, to }),
+                Ok(()) => ops.push(Operation::Move { from, to }),
+                Err(err) => error = Some(format!("{}: {}", escape_name(from.as_os_str()), err)),
+            }
+            node.borrow_mut().marked = false;
+        }
+
+        self.status_msg = match (error, ops.len()) {
+            (Some(err), 0) => Some(format!("Cannot {}: {}", verb, err)),
+            (Some(err), n) => Some(format!("{} {} entries, then: {}", done, n, err)),
+            (None, 1) => Some(format!("{} to {}", done, escape_name(dest.as_os_str()))),
+            (None, n) => Some(format!(
+                "{} {} entries to {}",
+                done,
+                n,
+                escape_name(dest.as_os_str())
+            )),
+        };
+        self.record(ops);
+    }
+
+    // copies from another file system, e.g. out of an archive, see `transfer`
+    fn extract(&mut self, nodes: &[TreeNodeRef], dest: &Path, into: bool) {
+        if !into && nodes.len() > 1 {
+            self.status_msg = Some(format!(
+                "Cannot extract {} entries to {}: not a directory",
+                nodes.len(),
+                escape_name(dest.as_os_str())
+            ));
+            return;
+        }
+        // all from the same directory, and so file system
+        let (fs, _) = nodes[0].borrow().location();
+        let mut targets = Vec::new();
+        for node in nodes {
+            let (_, from) = node.borrow().location();
+            let to = match into {
+                true => dest.join(&node.borrow().sys_node.name),
+                false => dest.to_path_buf(),
+            };
+            targets.push((from, to));
+            node.borrow_mut().marked = false;
+        }
+        self.status_msg = Some(match fs.extract(&targets) {
+            Ok(count) => format!(
+                "Extracted {} entries to {}",
+                count,
+                escape_name(dest.as_os_str())
+            ),
+            Err(err) => format!("Cannot extract: {}", err),
+        });
+        for (_, to) in &targets {
+            if let Some(dir) = to.parent() {
+                self.reload_dir(dir);
+            }
+        }
+    }
+
+    /// Renames an entry of the current directory. Nothing is overwritten.
+    pub fn rename(&mut self, node: &TreeNodeRef, name: &OsStr) {
+        let (fs, from) = node.borrow().location();
+        let to = from.with_file_name(name);
+        let bytes = name.as_encoded_bytes();
+        if bytes.is_empty() || bytes.contains(&b'/') || bytes == b"." || bytes == b".." {
+            self.status_msg = Some(format!("Not a valid name: {}", escape_name(name)));
+            return;
+        }
+        if to == from {
+            return;
+        }
+        let result = match exists(&to) {
+            true => Err(io::Error::from(io::ErrorKind::AlreadyExists)),
+            false => fs.rename(&from, &to),
+        };
+        match result {
+            Ok(()) => {
+                self.status_msg = Some(format!("Renamed to {}", escape_name(name)));
+                self.record(vec![Operation::Move { from, to }]);
+                let renamed = self
+                    .curr_dir()
+                    .borrow()
+                    .subnodes
+                    .iter()
+                    .find(|n| n.borrow().sys_node.name == name)
+                    .cloned();
+                if let Some(renamed) = renamed {
+                    let _ = self.move_to_list_node(&renamed);
+                }
+            }
+            Err(err) => self.status_msg = Some(format!("Cannot rename: {}", err)),
+        }
+    }
+
+    /// Renames entries of the directory `dir` at once, as planned by
+    /// `bulk_rename::plan`. Names may be swapped or passed on in a chain.
+    pub fn bulk_rename(&mut self, dir: &Path, names: Vec<NamePair>) {
+        for node in &self.curr_dir().borrow().subnodes {
+            node.borrow_mut().marked = false;
+        }
+        match bulk_rename::apply(dir, &names) {
+            Ok(()) => {
+                self.status_msg = Some(format!("Renamed {} entries", names.len()));
+                self.record(vec![Operation::Renames {
+                    dir: dir.to_path_buf(),
+                    names,
+                }]);
+            }
+            Err(err) => {
+                self.status_msg = Some(format!("Cannot rename, nothing changed: {}", err));
+                self.reload_dir(dir);
+            }
+        }
+    }
+
+    // adds the operations to the journal and shows their result
+    fn record(&mut self, ops: Vec<Operation>) {
+        let dirs: Vec<PathBuf> = ops.iter().flat_map(|op| op.dirs()).collect();
+        self.journal.borrow_mut().record(ops);
+        self.warn_unsaved();
+        for dir in dirs {
+            self.reload_dir(&dir);
+        }
+    }
+
+    /// Reverses the last file operation, if nothing changed since that would
+    /// get in the way.
+    pub fn undo(&mut self) {
+        let (message, dirs) = match self.journal.borrow_mut().undo() {
+            Some(Ok(change)) => (format!("Undone: {}", change.describe()), change.dirs()),
+            Some(Err(err)) => (format!("Cannot undo: {}", err), Vec::new()),
+            None => ("Nothing to undo".to_owned(), Vec::new()),
+        };
+        self.status_msg = Some(message);
+        self.warn_unsaved();
+        for dir in dirs {
+            self.reload_dir(&dir);
+        }
+    }
+
+    pub fn redo(&mut self) {
+        let (message, dirs) = match self.journal.borrow_mut().redo() {
+            Some(Ok(change)) => (format!("Redone: {}", change.describe()), change.dirs()),
+            Some(Err(err)) => (format!("Cannot redo: {}", err), Vec::new()),
+            None => ("Nothing to redo".to_owned(), Vec::new()),
+        };
+        self.status_msg = Some(message);
+        self.warn_unsaved();
+        for dir in dirs {
+            self.reload_dir(&dir);
+        }
+    }
+
+    fn warn_unsaved(&mut self) {
+        let error = self.journal.borrow_mut().take_save_error();
+        if let Some(err) = error {
+            self.status_msg = Some(err.to_string());
+        }
+    }
+
+    /* #endregion */
+
     /* #region Searching */
 
     fn find(&self, path: &Path) -> Result<TreeNodeRef, AppError> {
@@ -252,11 +841,13 @@ impl Tree {
         match oc {
             // some component exist
             Some(c) => match c {
-                std::path::Component::RootDir => return Tree::inner_find(&self.root, &mut it),
+                std::path::Component::RootDir => {
+                    return Tree::inner_find(&self.root, &mut it, &self.load_opts)
+                }
                 _ => {
                     return Err(AppError::PathError(
                         "absolute path expected".to_owned(),
-                        path.to_string_lossy().to_string(),
+                        escape_name(path.as_os_str()),
                     ));
                 }
             },
@@ -266,10 +857,30 @@ impl Tree {
         }
     }
 
-    fn inner_find(this_node: &TreeNodeRef, it: &mut Components) -> Result<TreeNodeRef, AppError> {
-        let _ = TreeNode::load(this_node); // Error ignored
+    /// The node of `path` if it and the directories leading to it are loaded.
+    fn find_loaded(&self, path: &Path) -> Option<TreeNodeRef> {
+        let mut node = self.root.clone();
+        for c in path.components().skip(1) {
+            let next = node
+                .borrow()
+                .subnodes
+                .iter()
+                .find(|sn| sn.borrow().sys_node.name == c.as_os_str())
+                .cloned()?;
+            node = next;
+        }
+        Some(node).filter(|n| n.borrow().loaded)
+    }
+
+    fn inner_find(
+        this_node: &TreeNodeRef,
+        it: &mut Components,
+        opts: &LoadOptions,
+    ) -> Result<TreeNodeRef, AppError> {
+        let _ = TreeNode::load(this_node, opts); // Error ignored
         let oc = it.next();
         if let Some(c) = oc {
+            Tree::add_hidden_dir(this_node, c.as_os_str(), opts);
             match this_node
                 .borrow()
                 .subnodes
@@ -279,22 +890,179 @@ impl Tree {
                 None => {
                     return Err(AppError::PathError(
                         "path not found".to_owned(),
-                        c.as_os_str().to_string_lossy().to_string(),
+                        escape_name(c.as_os_str()),
                     ));
                 }
                 Some(subnode) => {
                     if subnode.borrow().sys_node.typ != NodeType::Dir {
                         return Err(AppError::PathError(
                             "not a directory".to_owned(),
-                            c.as_os_str().to_string_lossy().to_string(),
+                            escape_name(c.as_os_str()),
                         ));
                     }
-                    return Tree::inner_find(subnode, it);
+                    return Tree::inner_find(subnode, it, opts);
                 }
             }
         }
 
         Ok(this_node.clone())
     }
+
+    // Hidden entries are skipped when loading, but an explicitly given path
+    // may still lead through them.
+    fn add_hidden_dir(this_node: &TreeNodeRef, name: &OsStr, opts: &LoadOptions) {
+        if opts.show_hidden || !name.as_encoded_bytes().starts_with(b".") {
+            return;
+        }
+        if this_node
+            .borrow()
+            .subnodes
+            .iter()
+            .any(|sn| sn.borrow().sys_node.name == name)
+        {
+            return;
+        }
+        let (fs, path) = this_node.borrow().dir_location();
+        if fs
+            .stat(&path.join(name))
+            .is_ok_and(|n| n.typ == NodeType::Dir)
+        {
+            TreeNode::append(this_node, TreeNode::from(SysNode::new(name, NodeType::Dir)));
+        }
+    }
     /* #endregion */
 }
+
+/// The path with its parent directory resolved, without `..` and symlinks,
+/// but the last component as it is. Unchanged if the parent does not exist.
+fn canonical_parent(path: &Path) -> PathBuf {
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => match parent.canonicalize() {
+            Ok(parent) => parent.join(name),
+            Err(_) => path.to_path_buf(),
+        },
+        _ => path.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colors::{ColorScheme, Palette};
+    use crate::graph::columns::{Column, ColumnLayout};
+    use crate::provider::mem::MemFs;
+    use std::io::Read;
+
+    fn mem_tree(fs: MemFs) -> Rc<RefCell<Tree>> {
+        let opts = LoadOptions {
+            show_hidden: false,
+            sort: SortOrder::Name,
+            reverse: false,
+        };
+        let mut tree = Tree::new(opts, false, false);
+        tree.mount_root(Rc::new(fs));
+        Rc::new(RefCell::new(tree))
+    }
+
+    fn views(tree: &Rc<RefCell<Tree>>) -> (Rc<RefCell<TreeView>>, Rc<RefCell<ListView>>) {
+        // no colors without a screen, so no ncurses calls
+        let colors = Rc::new(ColorScheme::init(Palette::from_ls_colors("")));
+        let layout = ColumnLayout::new(&Column::defaults(), false);
+        let tv = Rc::new(RefCell::new(TreeView::new(tree.clone(), colors.clone())));
+        let lv = Rc::new(RefCell::new(ListView::new(tree.clone(), layout, colors)));
+        tree.borrow_mut().tree_view = Rc::downgrade(&tv);
+        tree.borrow_mut().list_view = Rc::downgrade(&lv);
+        (tv, lv)
+    }
+
+    fn names(node: &TreeNodeRef) -> Vec<String> {
+        let node = node.borrow();
+        node.subnodes
+            .iter()
+            .map(|n| n.borrow().sys_node.name.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    fn sample() -> MemFs {
+        MemFs::new()
+            .file("/b.txt", b"bee")
+            .file("/a.txt", b"a")
+            .dir("/src/sub")
+            .file("/src/main.rs", b"fn main() {}")
+            .dir("/docs")
+            .dir("/.cache/old")
+            .symlink("/link", "src")
+    }
+
+    #[test]
+    fn loads_sorted_without_hidden() {
+        let tree = mem_tree(sample());
+        let root = tree.borrow().root.clone();
+        assert!(root.borrow().loaded);
+        assert!(!root.borrow().is_local());
+        assert_eq!(names(&root), ["docs", "src", "a.txt", "b.txt", "link"]);
+        assert_eq!(tree.borrow().curr_path(), Path::new("/"));
+        let file = tree.borrow().curr_file().unwrap();
+        assert_eq!(file.borrow().sys_node.name, "docs");
+        // not loaded until entered
+        assert!(!root.borrow().subnodes[1].borrow().loaded);
+    }
+
+    #[test]
+    fn expands_and_navigates() {
+        let tree = mem_tree(sample());
+        let (tv, lv) = views(&tree);
+
+        // into `docs` from the list, then on to `src` in the tree
+        tree.borrow_mut().lv_enter(&mut lv.borrow_mut()).unwrap();
+        assert_eq!(tree.borrow().curr_path(), Path::new("/docs"));
+        assert!(tree.borrow().curr_file().is_none());
+        let src = tree.borrow().root.borrow().subnodes[1].clone();
+        tree.borrow_mut()
+            .tv_goto(&src, &mut tv.borrow_mut())
+            .unwrap();
+        assert_eq!(tree.borrow().curr_path(), Path::new("/src"));
+        assert_eq!(names(&src), ["sub", "main.rs"]);
+        let (fs, path) = src.borrow().subnodes[1].borrow().location();
+        let mut content = String::new();
+        fs.read(&path)
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "fn main() {}");
+
+        // expanded and collapsed again, which forgets what's below
+        tree.borrow_mut().go_to_path(Path::new("/src/sub")).unwrap();
+        assert!(src.borrow().expanded);
+        tree.borrow_mut().tv_move_up(&mut tv.borrow_mut()).unwrap();
+        assert_eq!(tree.borrow().curr_path(), Path::new("/src"));
+        tree.borrow_mut()
+            .tv_expand(false, &mut tv.borrow_mut())
+            .unwrap();
+        assert!(!src.borrow().expanded);
+        assert!(src.borrow().subnodes[0].borrow().subnodes.is_empty());
+        tree.borrow_mut().lv_move_up(&mut lv.borrow_mut()).unwrap();
+        assert_eq!(tree.borrow().curr_path(), Path::new("/"));
+    }
+
+    #[test]
+    fn finds_hidden_and_missing_paths() {
+        let tree = mem_tree(sample());
+        tree.borrow_mut()
+            .jump_to_path(Path::new("/.cache/old"))
+            .unwrap();
+        assert_eq!(tree.borrow().curr_path(), Path::new("/.cache/old"));
+        let root = tree.borrow().root.clone();
+        assert_eq!(names(&root).last().map(String::as_str), Some(".cache"));
+
+        // still there when read again, though not listed
+        tree.borrow_mut().reload_dir(Path::new("/"));
+        assert_eq!(names(&root).last().map(String::as_str), Some(".cache"));
+        assert_eq!(tree.borrow().curr_path(), Path::new("/.cache/old"));
+
+        assert!(tree.borrow_mut().go_to_path(Path::new("/missing")).is_err());
+        assert!(tree.borrow_mut().go_to_path(Path::new("/a.txt")).is_err());
+        assert!(tree.borrow_mut().go_to_path(Path::new("src")).is_err());
+        assert_eq!(tree.borrow().curr_path(), Path::new("/.cache/old"));
+    }
+}
@@ -1,32 +1,90 @@
 use std::{
     cell::RefCell,
     cmp::Ordering,
-    fs,
-    path::PathBuf,
+    path::{Path, PathBuf},
     rc::{Rc, Weak},
 };
 
-use crate::{common::*, filesystem::*};
+use crate::provider::{FsProvider, LocalFs};
+use crate::{common::*, dir_size::DirSize, filesystem::*};
 
 pub type TreeNodeRef = Rc<RefCell<TreeNode>>;
 pub type TreeNodeWeak = Weak<RefCell<TreeNode>>;
 
+#[derive(Copy, Clone, PartialEq)]
+pub enum SortOrder {
+    Name,
+    Extension,
+    Size,
+    Modified,
+}
+
+impl SortOrder {
+    pub fn parse(s: &str) -> Option<SortOrder> {
+        match s {
+            "name" => Some(SortOrder::Name),
+            "ext" | "extension" => Some(SortOrder::Extension),
+            "size" => Some(SortOrder::Size),
+            "time" | "mtime" => Some(SortOrder::Modified),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct LoadOptions {
+    pub show_hidden: bool,
+    pub sort: SortOrder,
+    pub reverse: bool,
+}
+
+impl LoadOptions {
+    fn compare(&self, a: &SysNode, b: &SysNode) -> Ordering {
+        // byte order, which for UTF-8 names is also code point order
+        let name_ord = || a.name.cmp(&b.name);
+        let ord = match self.sort {
+            SortOrder::Name => name_ord(),
+            SortOrder::Extension => Path::new(&a.name)
+                .extension()
+                .cmp(&Path::new(&b.name).extension())
+                .then_with(name_ord),
+            // largest and newest first
+            SortOrder::Size => b.size.cmp(&a.size).then_with(name_ord),
+            SortOrder::Modified => b.modified.cmp(&a.modified).then_with(name_ord),
+        };
+        if self.reverse {
+            ord.reverse()
+        } else {
+            ord
+        }
+    }
+}
+
 pub struct TreeNode {
     pub sys_node: SysNode,
+    /// The file system the entries are read from, if it's another than the
+    /// one of the node itself, e.g. for an opened archive.
+    pub mount: Option<Rc<dyn FsProvider>>,
     pub subnodes: Vec<TreeNodeRef>,
     pub parent: TreeNodeWeak,
     pub loaded: bool,
     pub expanded: bool,
+    pub dir_size: DirSize,
+    /// Selected in the list, for operations on several entries.
+    pub marked: bool,
 }
 
 impl TreeNode {
     pub fn from(sys_node: SysNode) -> TreeNodeRef {
         Rc::new(RefCell::new(Self {
             sys_node,
+            mount: None,
             subnodes: Vec::new(),
             parent: Weak::new(),
             loaded: false,
             expanded: false,
+            dir_size: DirSize::Unknown,
+            marked: false,
         }))
     }
 
@@ -48,6 +106,26 @@ impl TreeNode {
         path
     }
 
+    /// The file system the node is on and its path there.
+    pub fn location(&self) -> (Rc<dyn FsProvider>, PathBuf) {
+        match self.parent.upgrade() {
+            Some(parent) => {
+                let (fs, path) = parent.borrow().dir_location();
+                (fs, path.join(&self.sys_node.name))
+            }
+            None => (Rc::new(LocalFs), self.get_path()),
+        }
+    }
+
+    /// The file system the entries of the node are on and the node's path
+    /// there.
+    pub fn dir_location(&self) -> (Rc<dyn FsProvider>, PathBuf) {
+        match &self.mount {
+            Some(fs) => (fs.clone(), PathBuf::from("/")),
+            None => self.location(),
+        }
+    }
+
     pub fn try_unload(this_node: &TreeNodeRef, next_node: &TreeNodeRef) -> bool {
         let mut unloaded = false;
         let mut dest_branch: Vec<TreeNodeRef> = Vec::new();
@@ -82,18 +160,10 @@ impl TreeNode {
         }
     }
 
-    pub fn load(this: &TreeNodeRef) -> Result<(), AppError> {
+    pub fn load(this: &TreeNodeRef, opts: &LoadOptions) -> Result<(), AppError> {
         if !this.borrow().loaded {
             this.borrow_mut().subnodes.clear();
-            let nodes = fs::read_dir(this.borrow().get_path())?;
-            let nodes = nodes.map(|res| res.map(|e| SysNode::from(&e)));
-            let mut nodes: Vec<SysNode> = nodes.filter_map(Result::ok).collect();
-
-            nodes.sort_by(|a, b| match a.typ.priority().cmp(&b.typ.priority()) {
-                Ordering::Equal => a.name.to_string_lossy().cmp(&b.name.to_string_lossy()),
-                other => other,
-            });
-
+            let nodes = TreeNode::read_entries(&this.borrow(), opts)?;
             for node in nodes {
                 TreeNode::append(this, TreeNode::from(node));
             }
@@ -103,6 +173,137 @@ impl TreeNode {
         Ok(())
     }
 
+    /// Reads the entries of a loaded directory again. Entries which are still
+    /// there keep their nodes, and so what was loaded, expanded or computed
+    /// below them.
+    pub fn reload(this: &TreeNodeRef, opts: &LoadOptions) -> Result<(), AppError> {
+        if !this.borrow().loaded {
+            return Ok(());
+        }
+        let (fs, path) = this.borrow().dir_location();
+        let nodes = TreeNode::read_entries(&this.borrow(), opts)?;
+        let mut old = std::mem::take(&mut this.borrow_mut().subnodes);
+        for mut sys_node in nodes {
+            // opened archives stay open
+            let idx = old.iter().position(|n| {
+                let n = n.borrow();
+                n.sys_node.name == sys_node.name
+                    && (n.sys_node.typ == sys_node.typ || n.mount.is_some())
+            });
+            match idx {
+                Some(idx) => {
+                    let node = old.remove(idx);
+                    if node.borrow().mount.is_some() {
+                        sys_node.typ = NodeType::Dir;
+                    }
+                    node.borrow_mut().sys_node = sys_node;
+                    this.borrow_mut().subnodes.push(node);
+                }
+                None => TreeNode::append(this, TreeNode::from(sys_node)),
+            }
+        }
+        // hidden directories entered by path aren't listed, but stay
+        for node in old {
+            let name = node.borrow().sys_node.name.clone();
+            let is_dir = || {
+                fs.metadata(&path.join(&name))
+                    .is_ok_and(|n| n.typ == NodeType::Dir)
+            };
+            if name.as_encoded_bytes().starts_with(b".") && is_dir() {
+                this.borrow_mut().subnodes.push(node);
+            }
+        }
+        Ok(())
+    }
+
+    fn read_entries(node: &TreeNode, opts: &LoadOptions) -> Result<Vec<SysNode>, AppError> {
+        let (fs, path) = node.dir_location();
+        let nodes = fs.list(&path)?;
+        let mut nodes: Vec<SysNode> = nodes
+            .into_iter()
+            .filter(|n| opts.show_hidden || !n.name.as_encoded_bytes().starts_with(b"."))
+            .collect();
+
+        nodes.sort_by(|a, b| match a.typ.priority().cmp(&b.typ.priority()) {
+            Ordering::Equal => opts.compare(a, b),
+            other => other,
+        });
+        Ok(nodes)
+    }
+
+    /// Shows the entries of another file system below a file, e.g. of an
+    /// archive, which is then browsed like a directory.
+    pub fn mount(this: &TreeNodeRef, fs: Rc<dyn FsProvider>) {
+        let mut node = this.borrow_mut();
+        node.mount = Some(fs);
+        node.sys_node.typ = NodeType::Dir;
+        node.loaded = false;
+    }
+
+    /// Whether the entries of the node are on the local file system, so the
+    /// file operations can change them.
+    pub fn is_local(&self) -> bool {
+        self.dir_location().0.is_local()
+    }
+
+    /// Reads the attributes of `this` again, and with `recursive` of all
+    /// loaded nodes below it.
+    pub fn refresh(this: &TreeNodeRef, recursive: bool) {
+        let (fs, path) = this.borrow().location();
+        if let Ok(sys_node) = fs.stat(&path) {
+            this.borrow_mut().sys_node = sys_node;
+        }
+        if recursive {
+            let subnodes = this.borrow().subnodes.clone();
+            for sn in &subnodes {
+                TreeNode::refresh(sn, true);
+            }
+        }
+    }
+
+    /// Size of a file, or the computed size of a directory if known.
+    pub fn effective_size(&self) -> u64 {
+        match self.dir_size {
+            DirSize::Done(size) => size,
+            _ => self.sys_node.size,
+        }
+    }
+
+    /// Reorders the subnodes by size, largest first, keeping directories
+    /// before files.
+    pub fn sort_by_size(&mut self) {
+        self.subnodes.sort_by(|a, b| {
+            let (a, b) = (a.borrow(), b.borrow());
+            a.sys_node
+                .typ
+                .priority()
+                .cmp(&b.sys_node.typ.priority())
+                .then_with(|| b.effective_size().cmp(&a.effective_size()))
+        });
+    }
+
+    /// Subtracts removed content from the computed sizes of `this` and its
+    /// ancestors.
+    pub fn subtract_size(this: &TreeNodeRef, removed: u64) {
+        let dir_size = this.borrow().dir_size;
+        if let DirSize::Done(size) = dir_size {
+            this.borrow_mut().dir_size = DirSize::Done(size.saturating_sub(removed));
+        }
+        if let Some(parent) = this.borrow().parent.upgrade() {
+            TreeNode::subtract_size(&parent, removed);
+        }
+    }
+
+    /// Forgets the computed sizes of `this` and its ancestors.
+    pub fn reset_sizes(this: &TreeNodeRef) {
+        if this.borrow().dir_size != DirSize::Computing {
+            this.borrow_mut().dir_size = DirSize::Unknown;
+        }
+        if let Some(parent) = this.borrow().parent.upgrade() {
+            TreeNode::reset_sizes(&parent);
+        }
+    }
+
     pub fn unload(&mut self) {
         if self.loaded {
             self.subnodes.clear();